@@ -0,0 +1,40 @@
+/*
+    Runs arbitrary bytes as 6502 code. The input becomes the start of an NROM
+    cartridge's PRG ROM with the reset vector pointing at it, then the cpu
+    runs until a KIL opcode halts it or the instruction budget runs out.
+    KIL halting is the only documented way for the cpu to stop, any panic
+    (including overflow in debug builds) is a bug.
+
+    cargo +nightly fuzz run fuzz_cpu
+*/
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rnes::CPU;
+use rnes::cartridge::{Cartridge, Mapper};
+
+const PRG_SIZE: usize = 0x8000;
+//enough to get through most loops, short enough to keep the fuzzer fast
+const MAX_INSTRUCTIONS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    //NROM with two PRG banks and CHR RAM
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 0, 0, 0];
+    rom.resize(16, 0);
+    let mut prg = vec![0; PRG_SIZE];
+    let len = data.len().min(PRG_SIZE - 6);
+    prg[..len].copy_from_slice(&data[..len]);
+    //nmi, reset and irq all start at $8000
+    prg[PRG_SIZE - 6..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+    rom.extend(prg);
+
+    let mut cpu = CPU::init();
+    cpu.bus.load_cartridge(Mapper::with_cart(Cartridge::from_bytes(rom)));
+    cpu.reset();
+    for _ in 0..MAX_INSTRUCTIONS {
+        if cpu.halted {
+            break;
+        }
+        cpu.execute_instruction();
+    }
+});
@@ -0,0 +1,61 @@
+use std::f32::consts::PI;
+
+enum FilterKind {
+    HighPass,
+    LowPass,
+}
+
+/*
+    Single pole IIR filter, the digital version of the RC filters on the
+    console's audio output. alpha comes from the usual RC = 1 / (2 pi fc)
+    with dt being one sample.
+*/
+pub struct Filter {
+    kind: FilterKind,
+    alpha: f32,
+    prev_input: f32,
+    prev_output: f32,
+}
+
+impl Filter {
+    pub fn high_pass(cutoff: f32, sample_rate: f32) -> Self {
+        let (rc, dt) = (1.0 / (2.0 * PI * cutoff), 1.0 / sample_rate);
+        Filter {
+            kind: FilterKind::HighPass,
+            alpha: rc / (rc + dt),
+            prev_input: 0.0,
+            prev_output: 0.0,
+        }
+    }
+    pub fn low_pass(cutoff: f32, sample_rate: f32) -> Self {
+        let (rc, dt) = (1.0 / (2.0 * PI * cutoff), 1.0 / sample_rate);
+        Filter {
+            kind: FilterKind::LowPass,
+            alpha: dt / (rc + dt),
+            prev_input: 0.0,
+            prev_output: 0.0,
+        }
+    }
+    pub fn clear(&mut self) {
+        self.prev_input = 0.0;
+        self.prev_output = 0.0;
+    }
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = match self.kind {
+            FilterKind::HighPass => self.alpha * (self.prev_output + input - self.prev_input),
+            FilterKind::LowPass => self.prev_output + self.alpha * (input - self.prev_output),
+        };
+        self.prev_input = input;
+        self.prev_output = output;
+        output
+    }
+}
+
+//the NES's output stage, two high-pass filters at 90Hz and 440Hz, then a low-pass at 14kHz
+pub fn output_filters(sample_rate: f32) -> [Filter; 3] {
+    [
+        Filter::high_pass(90.0, sample_rate),
+        Filter::high_pass(440.0, sample_rate),
+        Filter::low_pass(14000.0, sample_rate),
+    ]
+}
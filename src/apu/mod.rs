@@ -0,0 +1,892 @@
+pub mod filter;
+pub mod resample;
+
+use std::cell::Cell;
+
+use filter::{Filter, output_filters};
+use resample::Resampler;
+use serde::{Deserialize, Serialize};
+
+use crate::config::AudioConfig;
+use crate::savestate::ApuState;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+/*
+    The emulator runs 29781 CPU cycles per frame at a fixed 60 frames per second,
+    slightly slower than the real 1.789773MHz. Resampling from the rate the
+    frame limiter actually produces keeps audio locked to video, instead of the
+    audio buffer slowly running dry.
+*/
+pub const CLOCK_RATE: f64 = 29781.0 * 60.0;
+
+//length counter loads, indexed by bits 7-3 of $4003, $4007, $400B and $400F
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+//NTSC noise timer periods, in CPU cycles
+const NOISE_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+//NTSC DMC timer periods, in CPU cycles
+const DMC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+//frame sequencer steps, in CPU cycles
+const FRAME_STEP_1: u32 = 7457;
+const FRAME_STEP_2: u32 = 14913;
+const FRAME_STEP_3: u32 = 22371;
+const FRAME_STEP_4: u32 = 29829;
+const FRAME_STEP_5: u32 = 37281;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Envelope {
+    start: bool,
+    looping: bool,
+    constant_volume: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Envelope {
+            start: false,
+            looping: false,
+            constant_volume: false,
+            volume: 0,
+            divider: 0,
+            decay: 0,
+        }
+    }
+    pub fn write(&mut self, val: u8) {
+        self.looping = (val & 0x20) != 0;
+        self.constant_volume = (val & 0x10) != 0;
+        self.volume = val & 0x0F;
+    }
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+            return;
+        }
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.volume;
+        if self.decay > 0 {
+            self.decay -= 1;
+        } else if self.looping {
+            self.decay = 15;
+        }
+    }
+    pub fn output(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    pub(crate) counter: u8,
+}
+
+impl LengthCounter {
+    pub fn new() -> Self {
+        LengthCounter {
+            enabled: false,
+            halt: false,
+            counter: 0,
+        }
+    }
+    pub fn load(&mut self, idx: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(idx & 0x1F) as usize];
+        }
+    }
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+    pub fn set_halt(&mut self, halt: bool) {
+        self.halt = halt;
+    }
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+    //pulse 1 negates with one's complement (adds -c - 1), pulse 2 with two's complement (adds -c)
+    ones_complement: bool,
+}
+
+impl Sweep {
+    pub fn new(ones_complement: bool) -> Self {
+        Sweep {
+            enabled: false,
+            period: 0,
+            negate: false,
+            shift: 0,
+            divider: 0,
+            reload: false,
+            ones_complement,
+        }
+    }
+    pub fn write(&mut self, val: u8) {
+        self.enabled = (val & 0x80) != 0;
+        self.period = (val >> 4) & 0x07;
+        self.negate = (val & 0x08) != 0;
+        self.shift = val & 0x07;
+        self.reload = true;
+    }
+    pub fn target_period(&self, timer_period: u16) -> u16 {
+        let change = timer_period >> self.shift;
+        if !self.negate {
+            return timer_period + change;
+        }
+        if self.ones_complement {
+            timer_period.saturating_sub(change + 1)
+        } else {
+            timer_period.saturating_sub(change)
+        }
+    }
+    pub fn mutes(&self, timer_period: u16) -> bool {
+        timer_period < 8 || self.target_period(timer_period) > 0x7FF
+    }
+    pub fn clock(&mut self, timer_period: &mut u16) {
+        if self.divider == 0 && self.enabled && self.shift > 0 && !self.mutes(*timer_period) {
+            *timer_period = self.target_period(*timer_period);
+        }
+        if self.divider == 0 || self.reload {
+            self.divider = self.period;
+            self.reload = false;
+        } else {
+            self.divider -= 1;
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PulseChannel {
+    duty: u8,
+    sequence_step: u8,
+    pub(crate) timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    pub(crate) sweep: Sweep,
+    pub(crate) length_counter: LengthCounter,
+}
+
+impl PulseChannel {
+    pub fn new(ones_complement: bool) -> Self {
+        PulseChannel {
+            duty: 0,
+            sequence_step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::new(),
+            sweep: Sweep::new(ones_complement),
+            length_counter: LengthCounter::new(),
+        }
+    }
+    //reg is the offset from the channel's base register (0..=3)
+    pub fn write_register(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.duty = val >> 6;
+                self.length_counter.set_halt((val & 0x20) != 0);
+                self.envelope.write(val);
+            }
+            1 => self.sweep.write(val),
+            2 => self.timer_period = (self.timer_period & 0x0700) | val as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((val as u16 & 0x07) << 8);
+                self.length_counter.load(val >> 3);
+                self.sequence_step = 0;
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_step = (self.sequence_step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+    pub fn clock_length_counter(&mut self) {
+        self.length_counter.clock();
+    }
+    pub fn clock_sweep(&mut self) {
+        self.sweep.clock(&mut self.timer_period);
+    }
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.active()
+            || self.sweep.mutes(self.timer_period)
+            || DUTY_TABLE[self.duty as usize][self.sequence_step as usize] == 0
+        {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TriangleChannel {
+    sequence_step: u8,
+    timer_period: u16,
+    timer: u16,
+    //doubles as the length counter halt flag
+    control: bool,
+    linear_counter: u8,
+    linear_reload_value: u8,
+    linear_reload: bool,
+    pub(crate) length_counter: LengthCounter,
+}
+
+impl TriangleChannel {
+    pub fn new() -> Self {
+        TriangleChannel {
+            sequence_step: 0,
+            timer_period: 0,
+            timer: 0,
+            control: false,
+            linear_counter: 0,
+            linear_reload_value: 0,
+            linear_reload: false,
+            length_counter: LengthCounter::new(),
+        }
+    }
+    //reg is the offset from $4008 (0..=3)
+    pub fn write_register(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.control = (val & 0x80) != 0;
+                self.linear_reload_value = val & 0x7F;
+                self.length_counter.set_halt(self.control);
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | val as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((val as u16 & 0x07) << 8);
+                self.length_counter.load(val >> 3);
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+    //the triangle timer runs at the CPU clock rate, not the APU rate
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+        /*
+            Periods below 2 produce ultrasonic frequencies that real hardware
+            still outputs, but on speakers it is just a loud pop. Freezing the
+            sequencer silences the channel instead.
+        */
+        if self.timer_period < 2 {
+            return;
+        }
+        if self.linear_counter > 0 && self.length_counter.active() {
+            self.sequence_step = (self.sequence_step + 1) & 0x1F;
+        }
+    }
+    pub fn clock_linear_counter(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+    pub fn clock_length_counter(&mut self) {
+        self.length_counter.clock();
+    }
+    pub fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.sequence_step as usize]
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NoiseChannel {
+    //15-bit linear feedback shift register
+    pub(crate) shift_register: u16,
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    pub(crate) length_counter: LengthCounter,
+}
+
+impl NoiseChannel {
+    pub fn new() -> Self {
+        NoiseChannel {
+            shift_register: 1,
+            short_mode: false,
+            timer_period: NOISE_PERIOD_TABLE[0],
+            timer: 0,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+        }
+    }
+    //reg is the offset from $400C (0..=3)
+    pub fn write_register(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.length_counter.set_halt((val & 0x20) != 0);
+                self.envelope.write(val);
+            }
+            2 => {
+                self.short_mode = (val & 0x80) != 0;
+                self.timer_period = NOISE_PERIOD_TABLE[(val & 0x0F) as usize];
+            }
+            3 => {
+                self.length_counter.load(val >> 3);
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+    //the period table is in CPU cycles, so this is clocked once per CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+        self.clock_shift_register();
+    }
+    fn clock_shift_register(&mut self) {
+        //short mode taps bit 6 instead of bit 1, giving a 93 (or 31) step metallic loop
+        let tap = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+        self.shift_register >>= 1;
+        self.shift_register |= feedback << 14;
+    }
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+    pub fn clock_length_counter(&mut self) {
+        self.length_counter.clock();
+    }
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.active() || (self.shift_register & 1) != 0 {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DmcChannel {
+    irq_enabled: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    pub(crate) irq_flag: bool,
+}
+
+impl DmcChannel {
+    pub fn new() -> Self {
+        DmcChannel {
+            irq_enabled: false,
+            looping: false,
+            timer_period: DMC_RATE_TABLE[0],
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            irq_flag: false,
+        }
+    }
+    //reg is the offset from $4010 (0..=3)
+    pub fn write_register(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = (val & 0x80) != 0;
+                self.looping = (val & 0x40) != 0;
+                self.timer_period = DMC_RATE_TABLE[(val & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.irq_flag = false;
+                }
+            }
+            1 => self.output_level = val & 0x7F,
+            2 => self.sample_address = 0xC000 | ((val as u16) << 6),
+            3 => self.sample_length = ((val as u16) << 4) | 1,
+            _ => {}
+        }
+    }
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+    //address the memory reader wants to fetch, if the sample buffer needs refilling
+    pub fn pending_read(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+    pub fn load_sample_byte(&mut self, val: u8) {
+        self.sample_buffer = Some(val);
+        //the address wraps around to $8000 rather than $0000
+        self.current_address = if self.current_address == 0xFFFF {
+            0x8000
+        } else {
+            self.current_address + 1
+        };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+    //the rate table is in CPU cycles, so this is clocked once per CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if (self.shift_register & 1) != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+pub enum FrameEvent {
+    None,
+    QuarterFrame,
+    //half frames also clock everything a quarter frame does
+    HalfFrame,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FrameCounter {
+    cycle: u32,
+    five_step: bool,
+    irq_inhibit: bool,
+    //Cell because reading $4015 clears the flag, and Bus::read only has &self
+    irq_flag: Cell<bool>,
+}
+
+impl FrameCounter {
+    pub fn new() -> Self {
+        FrameCounter {
+            cycle: 0,
+            five_step: false,
+            irq_inhibit: false,
+            irq_flag: Cell::new(false),
+        }
+    }
+    //returns true if the write should immediately clock all units
+    pub fn write(&mut self, val: u8) -> bool {
+        self.five_step = (val & 0x80) != 0;
+        self.irq_inhibit = (val & 0x40) != 0;
+        if self.irq_inhibit {
+            self.irq_flag.set(false);
+        }
+        self.cycle = 0;
+        self.five_step
+    }
+    pub fn tick(&mut self) -> FrameEvent {
+        self.cycle += 1;
+        match (self.cycle, self.five_step) {
+            (FRAME_STEP_1, _) | (FRAME_STEP_3, _) => FrameEvent::QuarterFrame,
+            (FRAME_STEP_2, _) => FrameEvent::HalfFrame,
+            (FRAME_STEP_4, false) => {
+                self.set_irq();
+                self.cycle = 0;
+                FrameEvent::HalfFrame
+            }
+            (FRAME_STEP_5, true) => {
+                self.cycle = 0;
+                FrameEvent::HalfFrame
+            }
+            _ => FrameEvent::None,
+        }
+    }
+    fn set_irq(&self) {
+        if !self.irq_inhibit {
+            self.irq_flag.set(true);
+        }
+    }
+    pub fn irq_flag(&self) -> bool {
+        self.irq_flag.get()
+    }
+    pub fn clear_irq(&self) {
+        self.irq_flag.set(false);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuChannel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl AuChannel {
+    pub const ALL: [AuChannel; 5] = [
+        AuChannel::Pulse1,
+        AuChannel::Pulse2,
+        AuChannel::Triangle,
+        AuChannel::Noise,
+        AuChannel::Dmc,
+    ];
+}
+
+pub struct APU {
+    pub(crate) pulse1: PulseChannel,
+    pub(crate) pulse2: PulseChannel,
+    pub(crate) triangle: TriangleChannel,
+    pub(crate) noise: NoiseChannel,
+    pub(crate) dmc: DmcChannel,
+    pub(crate) frame_counter: FrameCounter,
+    resampler: Resampler,
+    //run on the resampled output, the coefficients depend on the device's sample rate
+    filters: [Filter; 3],
+    cycle: u64,
+    //listener settings rather than console state, so reset leaves them alone
+    channel_volumes: [f32; 5],
+    channel_muted: [bool; 5],
+    master_volume: f32,
+}
+
+impl APU {
+    pub fn new(config: AudioConfig) -> Self {
+        APU {
+            pulse1: PulseChannel::new(true),
+            pulse2: PulseChannel::new(false),
+            triangle: TriangleChannel::new(),
+            noise: NoiseChannel::new(),
+            dmc: DmcChannel::new(),
+            frame_counter: FrameCounter::new(),
+            resampler: Resampler::new(CLOCK_RATE, config.sample_rate as f64),
+            filters: output_filters(config.sample_rate as f32),
+            cycle: 0,
+            channel_volumes: [1.0; 5],
+            channel_muted: [false; 5],
+            master_volume: 1.0,
+        }
+    }
+    pub fn reset(&mut self) {
+        self.pulse1 = PulseChannel::new(true);
+        self.pulse2 = PulseChannel::new(false);
+        self.triangle = TriangleChannel::new();
+        self.noise = NoiseChannel::new();
+        self.dmc = DmcChannel::new();
+        self.frame_counter = FrameCounter::new();
+        self.resampler.clear();
+        self.filters.iter_mut().for_each(Filter::clear);
+        self.cycle = 0;
+    }
+    pub fn save_state(&self) -> ApuState {
+        ApuState {
+            pulse1: self.pulse1.clone(),
+            pulse2: self.pulse2.clone(),
+            triangle: self.triangle.clone(),
+            noise: self.noise.clone(),
+            dmc: self.dmc.clone(),
+            frame_counter: self.frame_counter.clone(),
+            cycle: self.cycle,
+        }
+    }
+    //the resampler and filters hold audio on its way to the device, it's dropped like on reset
+    pub fn load_state(&mut self, state: ApuState) {
+        self.pulse1 = state.pulse1;
+        self.pulse2 = state.pulse2;
+        self.triangle = state.triangle;
+        self.noise = state.noise;
+        self.dmc = state.dmc;
+        self.frame_counter = state.frame_counter;
+        self.cycle = state.cycle;
+        self.resampler.clear();
+        self.filters.iter_mut().for_each(Filter::clear);
+    }
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler = Resampler::new(CLOCK_RATE, sample_rate as f64);
+        self.filters = output_filters(sample_rate as f32);
+    }
+    pub fn set_channel_volume(&mut self, channel: AuChannel, vol: f32) {
+        self.channel_volumes[channel as usize] = vol.max(0.0);
+    }
+    pub fn set_channel_muted(&mut self, channel: AuChannel, muted: bool) {
+        self.channel_muted[channel as usize] = muted;
+    }
+    pub fn set_master_volume(&mut self, vol: f32) {
+        self.master_volume = vol.max(0.0);
+    }
+    fn channel_gain(&self, channel: AuChannel) -> f32 {
+        if self.channel_muted[channel as usize] {
+            0.0
+        } else {
+            self.channel_volumes[channel as usize]
+        }
+    }
+    //audio samples at the output sample rate, produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        let mut samples = self.resampler.take_samples();
+        for sample in samples.iter_mut() {
+            let filtered = self.filters.iter_mut().fold(*sample, |sample, filter| filter.process(sample));
+            //channel volumes above 1.0 can push past full scale
+            *sample = filtered.clamp(-1.0, 1.0);
+        }
+        samples
+    }
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write_register(addr - 0x4000, val),
+            0x4004..=0x4007 => self.pulse2.write_register(addr - 0x4004, val),
+            0x4008..=0x400B => self.triangle.write_register(addr - 0x4008, val),
+            0x400C..=0x400F => self.noise.write_register(addr - 0x400C, val),
+            0x4010..=0x4013 => self.dmc.write_register(addr - 0x4010, val),
+            0x4015 => self.set_channel_enable(val),
+            0x4017 => {
+                let clock_now = self.frame_counter.write(val);
+                if clock_now {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+    //$4015 writes, a cleared bit silences the channel by zeroing its length counter
+    pub fn set_channel_enable(&mut self, val: u8) {
+        self.pulse1.set_enabled((val & 0x01) != 0);
+        self.pulse2.set_enabled((val & 0x02) != 0);
+        self.triangle.set_enabled((val & 0x04) != 0);
+        self.noise.set_enabled((val & 0x08) != 0);
+        self.dmc.set_enabled((val & 0x10) != 0);
+    }
+    pub fn read_status(&self) -> u8 {
+        let mut status = 0;
+        if self.pulse1.length_counter.active() {
+            status |= 0x01;
+        }
+        if self.pulse2.length_counter.active() {
+            status |= 0x02;
+        }
+        if self.triangle.length_counter.active() {
+            status |= 0x04;
+        }
+        if self.noise.length_counter.active() {
+            status |= 0x08;
+        }
+        if self.dmc.active() {
+            status |= 0x10;
+        }
+        if self.frame_counter.irq_flag() {
+            status |= 0x40;
+        }
+        if self.dmc.irq_flag {
+            status |= 0x80;
+        }
+        //reading the status acknowledges the frame interrupt
+        self.frame_counter.clear_irq();
+        status
+    }
+    //called once per CPU cycle
+    pub fn tick(&mut self) {
+        self.cycle += 1;
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        //pulse timers are clocked every other CPU cycle
+        if (self.cycle & 1) == 0 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        match self.frame_counter.tick() {
+            FrameEvent::QuarterFrame => self.clock_quarter_frame(),
+            FrameEvent::HalfFrame => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            FrameEvent::None => {}
+        }
+        self.resampler.push(self.mix_output());
+    }
+    fn clock_quarter_frame(&mut self) {
+        self.clock_envelopes();
+        self.clock_linear_counter();
+    }
+    fn clock_half_frame(&mut self) {
+        self.clock_length_counter();
+        self.clock_sweeps();
+    }
+    pub fn clock_envelopes(&mut self) {
+        self.pulse1.clock_envelope();
+        self.pulse2.clock_envelope();
+        self.noise.clock_envelope();
+    }
+    pub fn clock_linear_counter(&mut self) {
+        self.triangle.clock_linear_counter();
+    }
+    pub fn clock_length_counter(&mut self) {
+        self.pulse1.clock_length_counter();
+        self.pulse2.clock_length_counter();
+        self.triangle.clock_length_counter();
+        self.noise.clock_length_counter();
+    }
+    pub fn clock_sweeps(&mut self) {
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+    pub fn irq_pending(&self) -> bool {
+        self.dmc.irq_flag || self.frame_counter.irq_flag()
+    }
+    //the current channel outputs through the DAC, with the volume settings applied
+    pub fn mix_output(&self) -> f32 {
+        //channels are scaled before the DAC, like turning a channel down at the source
+        let level = |output: u8, channel| output as f32 * self.channel_gain(channel);
+        let mixed = Self::dac(
+            level(self.pulse1.output(), AuChannel::Pulse1),
+            level(self.pulse2.output(), AuChannel::Pulse2),
+            level(self.triangle.output(), AuChannel::Triangle),
+            level(self.noise.output(), AuChannel::Noise),
+            level(self.dmc.output(), AuChannel::Dmc),
+        );
+        mixed * self.master_volume
+    }
+    /*
+        The hardware's non-linear DAC. The pulse channels share one resistor
+        network and triangle, noise and DMC another, so neither group adds up
+        linearly. Output is in 0.0..=1.0.
+    */
+    pub fn mix(pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+        Self::dac(pulse1 as f32, pulse2 as f32, triangle as f32, noise as f32, dmc as f32)
+    }
+    fn dac(pulse1: f32, pulse2: f32, triangle: f32, noise: f32, dmc: f32) -> f32 {
+        let pulse = pulse1 + pulse2;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        (pulse_out + tnd_out).clamp(-1.0, 1.0)
+    }
+}
@@ -0,0 +1,110 @@
+use std::f64::consts::PI;
+
+//half width of the sinc kernel, in decimated samples
+const KERNEL_HALF_WIDTH: i64 = 8;
+const HISTORY_LEN: usize = 32;
+//rate the box filter decimates down to before the sinc stage
+const INTERMEDIATE_RATE: f64 = 96_000.0;
+
+/*
+    Two stage downsampler from the APU clock to the audio device rate.
+    The APU produces one sample per CPU cycle, so running a sinc filter
+    at that rate would be far too expensive. Instead samples are first
+    averaged down to roughly 96kHz, then a Blackman windowed sinc is
+    evaluated at each output sample's fractional position.
+*/
+pub struct Resampler {
+    input_rate: f64,
+    output_rate: f64,
+    decimation: u32,
+    accumulator: f32,
+    accumulated: u32,
+    history: [f32; HISTORY_LEN],
+    //number of decimated samples received so far
+    decimated_count: i64,
+    //position of the next output sample, in decimated samples
+    next_output_time: f64,
+    step: f64,
+    cutoff: f64,
+    output: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: f64) -> Self {
+        let decimation = ((input_rate / INTERMEDIATE_RATE).floor() as u32).max(1);
+        let decimated_rate = input_rate / decimation as f64;
+        Resampler {
+            input_rate,
+            output_rate,
+            decimation,
+            accumulator: 0.0,
+            accumulated: 0,
+            history: [0.0; HISTORY_LEN],
+            decimated_count: 0,
+            next_output_time: KERNEL_HALF_WIDTH as f64,
+            step: decimated_rate / output_rate,
+            //leave a little room below the output nyquist frequency for the window's rolloff
+            cutoff: (output_rate / decimated_rate).min(1.0) * 0.9,
+            output: Vec::new(),
+        }
+    }
+    pub fn input_rate(&self) -> f64 {
+        self.input_rate
+    }
+    pub fn output_rate(&self) -> f64 {
+        self.output_rate
+    }
+    pub fn clear(&mut self) {
+        *self = Resampler::new(self.input_rate, self.output_rate);
+    }
+    pub fn push(&mut self, sample: f32) {
+        self.accumulator += sample;
+        self.accumulated += 1;
+        if self.accumulated < self.decimation {
+            return;
+        }
+        let decimated = self.accumulator / self.accumulated as f32;
+        self.accumulator = 0.0;
+        self.accumulated = 0;
+
+        self.history[self.decimated_count as usize % HISTORY_LEN] = decimated;
+        let newest = self.decimated_count;
+        self.decimated_count += 1;
+
+        while self.next_output_time.floor() as i64 + KERNEL_HALF_WIDTH <= newest {
+            let sample = self.interpolate(self.next_output_time);
+            self.output.push(sample);
+            self.next_output_time += self.step;
+        }
+    }
+    //hands over every sample produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.output)
+    }
+    fn interpolate(&self, time: f64) -> f32 {
+        let center = time.floor() as i64;
+        let mut sum = 0.0;
+        for k in (center - KERNEL_HALF_WIDTH + 1)..=(center + KERNEL_HALF_WIDTH) {
+            if k < 0 {
+                continue;
+            }
+            let sample = self.history[k as usize % HISTORY_LEN] as f64;
+            sum += sample * self.kernel(time - k as f64);
+        }
+        sum as f32
+    }
+    fn kernel(&self, distance: f64) -> f64 {
+        let u = distance / KERNEL_HALF_WIDTH as f64;
+        if u.abs() >= 1.0 {
+            return 0.0;
+        }
+        let x = self.cutoff * distance;
+        let sinc = if x == 0.0 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        };
+        let window = 0.42 + 0.5 * (PI * u).cos() + 0.08 * (2.0 * PI * u).cos();
+        self.cutoff * sinc * window
+    }
+}
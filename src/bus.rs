@@ -1,6 +1,20 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, ops::RangeInclusive, rc::Rc};
 
-use crate::{cartridge::Mapper, input::Input, ppu::PPU};
+use crate::{apu::APU, cartridge::{Mapper, MapperState}, config::AudioConfig, input::Input, ppu::PPU, savestate::BusState};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr_range: RangeInclusive<u16>,
+    pub on_read: bool,
+    pub on_write: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchpointHit {
+    pub watchpoint: Watchpoint,
+    pub addr: u16,
+    pub val: u8,
+}
 
 pub struct Bus {
     cartridge: Mapper,
@@ -9,39 +23,96 @@ pub struct Bus {
     pub input: Rc<RefCell<Input>>,
     ram: Vec<u8>,
     pub ppu: PPU,
+    pub apu: APU,
     pub irq: bool,
     pub nmi_request: bool,
     pub extra_cycles: i32,
+    //page written to $4014, the DMA runs once the cpu finishes the write
+    pub pending_oam_dma: Option<u8>,
+    //set while the cpu is stalled by an OAM DMA, a DMC fetch in the middle of one costs less
+    pub dma_active: bool,
+    pub watchpoints: Vec<Watchpoint>,
+    //RefCell for the same reason as input, reads can trigger a watchpoint
+    pub watchpoint_hit: RefCell<Option<WatchpointHit>>,
 }
 
 impl Bus {
     pub fn init() -> Self {
         Bus {
-            cartridge: Mapper::None,
+            cartridge: Mapper::none(),
             input: Rc::new(RefCell::new(Input::new())),
             ram: vec![0; 2048],
             irq: false,
             nmi_request: false,
             ppu: PPU::new(),
+            //the emulator sets the real sample rate once it knows the audio device's
+            apu: APU::new(AudioConfig::default()),
             extra_cycles: 0,
+            pending_oam_dma: None,
+            dma_active: false,
+            watchpoints: Vec::new(),
+            watchpoint_hit: RefCell::new(None),
         }
     }
     pub fn load_cartridge(&mut self, cartridge: Mapper) {
         self.reset();
         self.cartridge = cartridge;
     }
+    pub fn mapper(&self) -> &Mapper {
+        &self.cartridge
+    }
+    pub fn save_cartridge(&self) -> std::io::Result<()> {
+        self.cartridge.save()
+    }
+    pub fn save_state(&self) -> BusState {
+        BusState {
+            ram: self.ram.clone(),
+            extra_cycles: self.extra_cycles,
+            dma_active: self.dma_active,
+            irq: self.irq,
+            nmi_request: self.nmi_request,
+        }
+    }
+    pub fn load_state(&mut self, state: BusState) {
+        self.ram = state.ram;
+        self.extra_cycles = state.extra_cycles;
+        self.dma_active = state.dma_active;
+        self.irq = state.irq;
+        self.nmi_request = state.nmi_request;
+    }
+    pub fn save_mapper_state(&self) -> bincode::Result<MapperState> {
+        self.cartridge.save_state()
+    }
+    pub fn load_mapper_state(&mut self, mapper: &MapperState) -> bool {
+        self.cartridge.load_state(mapper)
+    }
     pub fn reset(&mut self) {
         self.input.borrow_mut().controller_state = 0;
         self.input.borrow_mut().controller_shift = 0;
+        self.input.borrow_mut().controller2_state = 0;
+        self.input.borrow_mut().controller2_shift = 0;
         self.ram = vec![0; 2048];
         self.irq = false;
         self.nmi_request = false;
         self.ppu.reset();
+        self.apu.reset();
         self.extra_cycles = 0;
+        self.pending_oam_dma = None;
+        self.dma_active = false;
+        *self.watchpoint_hit.get_mut() = None;
     }
     pub fn read(&self, addr: u16) -> u8 {
-        match addr {
+        let val = match addr {
             0x4016 => self.input.borrow_mut().read(),
+            0x4017 => {
+                let zapper = self.input.borrow().zapper;
+                match zapper {
+                    Some(zapper) => zapper.read(&self.ppu),
+                    None => self.input.borrow_mut().read2(),
+                }
+            }
+            //
+            0x4015 => self.apu.read_status(),
             //
             0x0000..=0x1FFF => self.ram[addr as usize & 0x07FF],
             //
@@ -50,8 +121,42 @@ impl Bus {
 
                 self.ppu.read_register(&self.cartridge, reg)
             }
-            //
-            0x6000..=0xFFFF => self.cartridge.cpu_read(addr),
+            //expansion area, only some mappers put registers below $6000
+            0x4020..=0xFFFF => self.cartridge.cpu_read(addr),
+            _ => 0,
+        };
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, val, false);
+        }
+        val
+    }
+    //None for the apu and controller registers and open bus, where there's nothing to show
+    pub fn peek_readable(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x0000..=0x3FFF | 0x6000..=0xFFFF => Some(self.read_no_side_effects(addr)),
+            _ => None,
+        }
+    }
+    /*
+        What a read would return, without clearing vblank, advancing the
+        $2007 address or anything else a real read does. The apu and
+        controller registers read as 0.
+    */
+    pub fn read_no_side_effects(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.ram[addr as usize & 0x07FF],
+            0x2000..=0x3FFF => {
+                let registers = self.ppu.registers.borrow();
+                match 0x2000 + (addr & 0x07) {
+                    0x2002 => (registers.status & 0xE0) | (registers.ppu_bus_latch & 0x1F),
+                    0x2004 => self.ppu.oam_ram[registers.oam_addr as usize],
+                    //palette reads skip the buffer
+                    0x2007 if registers.ppu_addr >= 0x3F00 => self.ppu.read(&self.cartridge, registers.ppu_addr),
+                    0x2007 => registers.data_buffer,
+                    _ => registers.ppu_bus_latch,
+                }
+            }
+            0x4020..=0xFFFF => self.cartridge.cpu_read(addr),
             _ => 0,
         }
     }
@@ -65,7 +170,11 @@ impl Bus {
         match addr {
             0x4016 => self.input.borrow_mut().write(val),
             //
-            0x4014 => self.write_oam_dma(val),
+            0x4014 => self.pending_oam_dma = Some(val),
+            //
+            0x4015 => self.apu.set_channel_enable(val),
+            //
+            0x4000..=0x4013 | 0x4017 => self.apu.write_register(addr, val),
             //
             0x0000..=0x1FFF => self.ram[addr as usize & 0x7FF] = val,
             //
@@ -74,11 +183,33 @@ impl Bus {
                 self.ppu.write_register(mapper, addr, val)
             }
             //
-            0x6000..=0xFFFF => self.cartridge.cpu_write(addr, val),
+            //mappers like MMC5 and nsf bank switching have registers below $6000
+            0x4020..=0xFFFF => self.cartridge.cpu_write(addr, val),
             _ => {}
         }
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, val, true);
+        }
     }
-    fn write_oam_dma(&mut self, page: u8) {
+    //the first hit is kept until someone takes it
+    fn check_watchpoints(&self, addr: u16, val: u8, is_write: bool) {
+        let mut hit = self.watchpoint_hit.borrow_mut();
+        if hit.is_some() {
+            return;
+        }
+        let triggered = self.watchpoints.iter().find(|watchpoint| {
+            watchpoint.addr_range.contains(&addr) && if is_write { watchpoint.on_write } else { watchpoint.on_read }
+        });
+        if let Some(watchpoint) = triggered {
+            *hit = Some(WatchpointHit {
+                watchpoint: watchpoint.clone(),
+                addr,
+                val,
+            });
+        }
+    }
+    //the DMA has to start on an even cycle, so it takes an extra alignment cycle when started on an odd one
+    pub fn write_oam_dma(&mut self, page: u8, cpu_cycle: u64) {
         let base_addr = (page as u16) << 8;
         for i in 0..256 {
             let val = self.read(base_addr + i);
@@ -86,7 +217,8 @@ impl Bus {
             self.ppu.oam_ram[oam_addr as usize] = val;
             self.ppu.registers.borrow_mut().oam_addr = oam_addr.wrapping_add(1);
         }
-        self.extra_cycles = 513;
+        self.extra_cycles = if cpu_cycle % 2 == 1 { 514 } else { 513 };
+        self.dma_active = true;
     }
     pub fn tick_ppu(&mut self, elapsed_cycles: i32) {
         let (ppu, mapper, irq, nmi) = (
@@ -99,4 +231,36 @@ impl Bus {
             ppu.step(mapper, nmi, irq);
         }
     }
+    //cpu_cycle is the cpu's count once the elapsed cycles are done, DMC fetches go by its parity
+    pub fn tick_apu(&mut self, elapsed_cycles: i32, cpu_cycle: u64) {
+        for remaining in (0..elapsed_cycles as u64).rev() {
+            self.apu.tick();
+            self.cartridge.clock_cpu_cycle();
+
+            if let Some(addr) = self.apu.dmc.pending_read() {
+                let val = self.read(addr);
+                self.apu.dmc.load_sample_byte(val);
+                self.extra_cycles += self.dmc_stall_cycles(cpu_cycle.wrapping_sub(remaining));
+            }
+            if self.apu.irq_pending() {
+                self.irq = true;
+            }
+        }
+    }
+    /*
+        The CPU is halted while the DMC fetches its sample byte. On its own
+        that's a halt cycle, a dummy cycle, an alignment cycle and the read,
+        one less if the CPU was already halted by an earlier fetch. During an
+        OAM DMA the CPU is halted anyway, so the fetch only steals the read
+        on an even cycle, and the read plus realigning the OAM DMA on an odd one.
+    */
+    fn dmc_stall_cycles(&self, cpu_cycle: u64) -> i32 {
+        let even_cycle = cpu_cycle.is_multiple_of(2);
+        match (self.dma_active, even_cycle) {
+            (true, true) => 1,
+            (true, false) => 2,
+            (false, _) if self.extra_cycles > 0 => 3,
+            (false, _) => 4,
+        }
+    }
 }
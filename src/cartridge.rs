@@ -1,66 +1,200 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum MirrorMode {
     Vertical,
     Horizontal,
     SingleScreenA,
     SingleScreenB,
+    //extra vram on the cartridge gives each nametable its own memory
+    FourScreen,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
 }
-#[derive(Clone, Debug)]
 
+impl Region {
+    //cpu cycles per frame
+    pub fn frame_cycles(self) -> i32 {
+        match self {
+            Region::Ntsc => 29781,
+            Region::Pal => 33247,
+        }
+    }
+    pub fn frame_rate(self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0,
+            Region::Pal => 50.0,
+        }
+    }
+    //ppu dots run after this many cpu cycles since power on, 3 per cycle on NTSC and 3.2 on PAL
+    pub fn ppu_dots(self, cpu_cycles: u64) -> u64 {
+        match self {
+            Region::Ntsc => cpu_cycles * 3,
+            Region::Pal => cpu_cycles * 16 / 5,
+        }
+    }
+    //last scanline of the frame, PAL has 50 more lines of vblank before it
+    pub fn pre_render_scanline(self) -> u32 {
+        match self {
+            Region::Ntsc => 261,
+            Region::Pal => 311,
+        }
+    }
+}
+//ROM contents are left out of save states, see Mapper::load_state
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Cartridge {
+    #[serde(skip)]
     rom_data: Vec<u8>,
+    #[serde(skip)]
     pub prg_rom: Vec<u8>,
+    #[serde(skip)]
     chr_rom: Vec<u8>,
+    //prg_chr_sha1, worked out once when the rom is loaded
+    #[serde(skip)]
+    pub sha1: [u8; 20],
     prg_banks: i32,
     chr_banks: i32,
     pub mapper_id: u8,
+    //NES 2.0 board variant, 0 when the header doesn't say
+    pub submapper: u8,
+    //discrete logic boards where the ROM keeps driving the bus while a register is written
+    pub has_bus_conflicts: bool,
     mirror_horz: bool,
     mirror_vert: bool,
     mirror_mode: MirrorMode,
+    pub region: Region,
     has_battery: bool,
     prg_ram: Vec<u8>,
     chr_ram: Vec<u8>,
+    //where battery backed prg ram is persisted, next to the rom
+    #[serde(skip)]
+    save_path: Option<PathBuf>,
+    //set when the cartridge was built from an NSF music rip rather than a game
+    pub nsf: Option<NsfHeader>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NsfHeader {
+    pub song_count: u8,
+    //songs are numbered from 1
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    //4KB banks mapped to $8000-$FFFF on init, all zero means no bank switching
+    pub bank_init: [u8; 8],
+}
+
+impl NsfHeader {
+    pub fn uses_bank_switching(&self) -> bool {
+        self.bank_init.iter().any(|&bank| bank != 0)
+    }
 }
 
 use std::error::Error;
 
-#[derive(Copy, Clone, Debug)]
-struct CartridgeLoadError {
-    pub reason: &'static str,
+#[derive(Debug)]
+pub enum CartridgeError {
+    IoError(std::io::Error),
+    //missing magic bytes, or header fields that make no sense
+    InvalidHeader,
+    UnsupportedMapper(u8),
+    //the header says there's a trainer but the file ends inside it
+    TrainerParseError,
+    //the file is shorter than the header (or an ips record) says it should be
+    InsufficientData { expected: usize, got: usize },
 }
 
-impl std::fmt::Display for CartridgeLoadError {
+impl std::fmt::Display for CartridgeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.reason)
+        match self {
+            CartridgeError::IoError(err) => write!(f, "{err}"),
+            CartridgeError::InvalidHeader => write!(f, "Not a valid rom, the header is broken"),
+            CartridgeError::UnsupportedMapper(mapper_id) => write!(f, "Mapper {mapper_id} isn't supported"),
+            CartridgeError::TrainerParseError => write!(f, "Rom ends in the middle of its trainer"),
+            CartridgeError::InsufficientData { expected, got } => {
+                write!(f, "Expected {expected} bytes of data, got {got}")
+            }
+        }
     }
 }
 
-impl Error for CartridgeLoadError {}
+impl Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CartridgeError::IoError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CartridgeError {
+    fn from(err: std::io::Error) -> Self {
+        CartridgeError::IoError(err)
+    }
+}
 
 unsafe impl Send for Cartridge {}
 
 impl Cartridge {
-    pub fn from_file<PathLike: AsRef<Path>>(file_path: PathLike) -> Result<Self, Box<dyn Error>> {
+    pub fn from_file<PathLike: AsRef<Path>>(file_path: PathLike) -> Result<Self, CartridgeError> {
         use std::fs::File;
         use std::io::Read;
 
-        let mut file = File::open(file_path)?;
+        let mut file = File::open(&file_path)?;
 
         let mut rom_data = Vec::new();
 
         let _ = file.read_to_end(&mut rom_data)?;
-        if rom_data[0..4] != [b'N', b'E', b'S', b'\x1A'] {
-            return Err(Box::new(CartridgeLoadError {
-                reason: "Not a valid nes rom",
-            }));
+
+        //translations and hacks are distributed as patches that sit next to the rom
+        let patch_path = file_path.as_ref().with_extension("ips");
+        if patch_path.exists() {
+            apply_ips_patch(&mut rom_data, &std::fs::read(patch_path)?)?;
+        }
+        Self::check_size(&rom_data)?;
+        let mut cart = Cartridge::from_bytes(rom_data);
+        if !Mapper::is_supported(cart.mapper_id) {
+            return Err(CartridgeError::UnsupportedMapper(cart.mapper_id));
+        }
+
+        if cart.has_battery {
+            let save_path = file_path.as_ref().with_extension("sav");
+            cart.load(&save_path)?;
+            cart.save_path = Some(save_path);
         }
-        let cart = Cartridge::from_bytes(rom_data);
 
         return Ok(cart);
     }
 
+    //from_bytes trusts the header, this makes sure the file is as long as it says
+    fn check_size(rom_data: &[u8]) -> Result<(), CartridgeError> {
+        //every mapper needs at least one PRG bank
+        if rom_data.len() < 16 || rom_data[0..4] != [b'N', b'E', b'S', b'\x1A'] || rom_data[4] == 0 {
+            return Err(CartridgeError::InvalidHeader);
+        }
+        let has_trainer = (rom_data[6] & 0x04) != 0;
+        let data_start = if has_trainer { 16 + 512 } else { 16 };
+        if rom_data.len() < data_start {
+            return Err(CartridgeError::TrainerParseError);
+        }
+        let expected = data_start + rom_data[4] as usize * 16 * 1024 + rom_data[5] as usize * 8 * 1024;
+        if rom_data.len() < expected {
+            return Err(CartridgeError::InsufficientData {
+                expected,
+                got: rom_data.len(),
+            });
+        }
+        Ok(())
+    }
     pub fn from_bytes(rom_data: Vec<u8>) -> Self {
         let prg_banks = rom_data[4] as i32;
         let chr_banks = rom_data[5] as i32;
@@ -76,63 +210,266 @@ impl Cartridge {
         if (flag6 & 0x08) == 0 && (flag6 & 1) != 0 {
             mirror_mode = MirrorMode::Vertical;
         }
+        if (flag6 & 0x08) != 0 {
+            mirror_mode = MirrorMode::FourScreen;
+        }
 
         let mapper_id = (flag6 >> 4) | ((flag7 >> 4) << 4);
 
+        //only NES 2.0 headers reliably say which tv system the game is for, Dendy is closest to PAL
+        let is_nes2 = (flag7 & 0x0C) == 0x08;
+        let region = match rom_data[12] & 0x03 {
+            1 | 3 if is_nes2 => Region::Pal,
+            _ => Region::Ntsc,
+        };
+        let submapper = if is_nes2 { rom_data[8] >> 4 } else { 0 };
+        //submapper 1 marks UxROM and CNROM boards without conflicts, 2 marks AxROM boards with them
+        let has_bus_conflicts = match mapper_id {
+            2 | 3 => submapper != 1,
+            7 => submapper == 2,
+            66 => true,
+            _ => false,
+        };
+
         let prg_size = prg_banks * 16 * 1024;
         let chr_size = chr_banks * 8 * 1024;
         
-        //let has_trainer = (flag6 &
+        //a 512 byte trainer sits between the header and PRG, it belongs at $7000 in PRG RAM
         let mut offset = 16;
         let has_trainer = (flag6 & 0x04) != 0;
-if has_trainer {
-    offset += 512; // Skip the trainer data if present
-}
+        let mut prg_ram = vec![0u8; 8 * 1024];
+        if has_trainer {
+            prg_ram[0x1000..0x1200].copy_from_slice(&rom_data[offset..offset + 512]);
+            offset += 512;
+        }
         let prg_rom = rom_data[offset..offset + prg_size as usize].to_vec();
 
         offset += prg_size as usize;
 
         let chr_rom = rom_data[offset..offset + chr_size as usize].to_vec();
 
-        let prg_ram = vec![0u8; 8 * 1024];
         let chr_ram = vec![0u8; 8 * 1024];
 
-        Self {
+        let mut cart = Self {
             rom_data,
             prg_rom,
             chr_rom,
+            sha1: [0; 20],
             prg_banks,
             chr_banks,
             mapper_id,
+            submapper,
+            has_bus_conflicts,
             mirror_horz,
             mirror_vert,
             mirror_mode,
+            region,
             has_battery,
             prg_ram,
             chr_ram,
+            save_path: None,
+            nsf: None,
+        };
+        cart.sha1 = cart.prg_chr_sha1();
+        cart
+    }
+    /*
+        NSF files are a 128 byte header followed by the music code and data, which
+        is placed at load_addr. Without bank switching the data is laid out as a
+        flat 32KB image of $8000-$FFFF. With bank switching the data is split into
+        4KB banks, with the first one padded by the low 12 bits of load_addr.
+    */
+    pub fn from_nsf(data: Vec<u8>) -> Result<Self, CartridgeError> {
+        if data.len() < 0x80 || data[0..5] != [b'N', b'E', b'S', b'M', 0x1A] {
+            return Err(CartridgeError::InvalidHeader);
+        }
+        let read_word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let mut bank_init = [0u8; 8];
+        bank_init.copy_from_slice(&data[0x70..0x78]);
+        let header = NsfHeader {
+            song_count: data[0x06],
+            starting_song: data[0x07].max(1),
+            load_addr: read_word(0x08),
+            init_addr: read_word(0x0A),
+            play_addr: read_word(0x0C),
+            bank_init,
+        };
+        //the load address has to be in cartridge space
+        if header.load_addr < 0x8000 {
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        let padding = if header.uses_bank_switching() {
+            header.load_addr as usize & 0x0FFF
+        } else {
+            header.load_addr as usize - 0x8000
+        };
+        let mut prg_rom = vec![0u8; padding];
+        prg_rom.extend_from_slice(&data[0x80..]);
+        //whole banks, and at least the full 32KB window
+        let prg_size = prg_rom.len().next_multiple_of(0x1000).max(0x8000);
+        prg_rom.resize(prg_size, 0);
+
+        let mut cart = Cartridge::empty();
+        cart.prg_banks = (prg_size / 0x4000) as i32;
+        cart.prg_rom = prg_rom;
+        cart.rom_data = data;
+        cart.prg_ram = vec![0u8; 8 * 1024];
+        cart.chr_ram = vec![0u8; 8 * 1024];
+        cart.nsf = Some(header);
+        cart.sha1 = cart.prg_chr_sha1();
+        Ok(cart)
+    }
+    //sha1 of the prg and chr rom, the header isn't included since dumps disagree on it
+    pub fn prg_chr_sha1(&self) -> [u8; 20] {
+        let mut hasher = sha1_smol::Sha1::new();
+        hasher.update(&self.prg_rom);
+        hasher.update(&self.chr_rom);
+        hasher.digest().bytes()
+    }
+    //sha1 of the whole file as loaded, header included
+    pub fn rom_sha1(&self) -> [u8; 20] {
+        sha1_smol::Sha1::from(&self.rom_data).digest().bytes()
+    }
+    //lowercase hex of sha1, the form movie files store it in
+    pub fn sha1_hex(&self) -> String {
+        self.sha1.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+    //the value a register write actually latches, ANDed with the ROM byte at the address on boards with bus conflicts
+    pub fn bus_conflict(&self, val: u8, rom_val: u8) -> u8 {
+        if self.has_bus_conflicts {
+            val & rom_val
+        } else {
+            val
         }
     }
     pub fn set_mirroring(&mut self, mode: MirrorMode) {
+        //four screen carts are wired that way, mirroring writes to the mapper do nothing
+        if self.mirror_mode == MirrorMode::FourScreen {
+            return;
+        }
         self.mirror_mode = mode;
         self.mirror_vert = mode == MirrorMode::Vertical;
         self.mirror_horz = mode == MirrorMode::Horizontal;
     }
-    pub fn save(&self){
-        if self.has_battery {
-            use std::io::Write;
-            let mut file = std::fs::File::create("SAVETEST.sav").unwrap();
-            file.write(&self.prg_ram[..]).unwrap();
+    pub fn save(&self) -> std::io::Result<()> {
+        if let Some(save_path) = &self.save_path {
+            std::fs::write(save_path, &self.prg_ram)?;
         }
+        Ok(())
     }
-    pub fn load(&mut self){
-        if !self.has_battery || !std::fs::exists("SAVETEST.sav").unwrap() {return;}
-        use std::io::Read;
-        let mut file = std::fs::File::open("SAVETEST.sav").unwrap();
-        file.read(&mut self.prg_ram[..]).unwrap();
+    fn empty() -> Self {
+        Cartridge {
+            rom_data: Vec::new(),
+            prg_rom: Vec::new(),
+            chr_rom: Vec::new(),
+            sha1: [0; 20],
+            prg_banks: 0,
+            chr_banks: 0,
+            mapper_id: 0,
+            submapper: 0,
+            has_bus_conflicts: false,
+            mirror_horz: false,
+            mirror_vert: false,
+            mirror_mode: MirrorMode::Horizontal,
+            region: Region::Ntsc,
+            has_battery: false,
+            prg_ram: Vec::new(),
+            chr_ram: Vec::new(),
+            save_path: None,
+            nsf: None,
+        }
+    }
+    //moves the rom and save file location over from another copy of the same cartridge
+    fn take_rom(&mut self, other: &mut Cartridge) {
+        self.rom_data = std::mem::take(&mut other.rom_data);
+        self.prg_rom = std::mem::take(&mut other.prg_rom);
+        self.chr_rom = std::mem::take(&mut other.chr_rom);
+        self.sha1 = other.sha1;
+        self.save_path = other.save_path.take();
+    }
+    fn load(&mut self, save_path: &Path) -> std::io::Result<()> {
+        if !save_path.exists() {
+            return Ok(());
+        }
+        let save_data = std::fs::read(save_path)?;
+        //a short or oversized file only fills what fits
+        let len = save_data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&save_data[..len]);
+        Ok(())
+    }
+}
+
+/*
+    IPS patches are "PATCH" followed by records of a 3 byte offset, 2 byte size
+    and that many bytes of data. A size of 0 marks an RLE record, a 2 byte run
+    length and the byte to repeat. The records end with "EOF", which may be
+    followed by a 3 byte length to truncate the file to.
+*/
+pub fn apply_ips_patch(rom: &mut Vec<u8>, patch: &[u8]) -> Result<(), CartridgeError> {
+    if !patch.starts_with(b"PATCH") {
+        return Err(CartridgeError::InvalidHeader);
+    }
+    let mut pos = 5;
+    let mut take = |len: usize| -> Result<&[u8], CartridgeError> {
+        let bytes = patch.get(pos..pos + len).ok_or(CartridgeError::InsufficientData {
+            expected: pos + len,
+            got: patch.len(),
+        })?;
+        pos += len;
+        Ok(bytes)
+    };
+    loop {
+        let offset = take(3)?;
+        if offset == b"EOF" {
+            break;
+        }
+        let offset = (offset[0] as usize) << 16 | (offset[1] as usize) << 8 | offset[2] as usize;
+        let size = take(2)?;
+        let size = (size[0] as usize) << 8 | size[1] as usize;
+
+        //records may write past the end of the rom, which grows it
+        let grow = |rom: &mut Vec<u8>, len: usize| {
+            if rom.len() < offset + len {
+                rom.resize(offset + len, 0);
+            }
+        };
+        if size == 0 {
+            let rle = take(3)?;
+            let len = (rle[0] as usize) << 8 | rle[1] as usize;
+            grow(rom, len);
+            rom[offset..offset + len].fill(rle[2]);
+        } else {
+            let data = take(size)?;
+            grow(rom, size);
+            rom[offset..offset + size].copy_from_slice(data);
+        }
+    }
+    if let Ok(length) = take(3) {
+        rom.truncate((length[0] as usize) << 16 | (length[1] as usize) << 8 | length[2] as usize);
+    }
+    Ok(())
+}
+
+//no bank switching at all, 16 or 32KB of prg rom and 8KB of chr
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NRomCartridge {
+    cart: Cartridge,
+}
+
+impl NRomCartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        NRomCartridge { cart }
     }
 }
 
-#[derive(Clone, Debug)]
+//stands in for the cartridge slot before a rom is loaded
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NoCartridge {
+    cart: Cartridge,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MMC1Cartridge {
     cart: Cartridge,
     shift_reg: u8,
@@ -142,6 +479,10 @@ pub struct MMC1Cartridge {
     shift_count: u8,
     prg_bank_offsets: (i32, i32),
     chr_bank_offsets: (i32, i32),
+    //cpu cycles seen through clock_cpu_cycle
+    cpu_cycle: u64,
+    //cpu_cycle of the last serial write, u64::MAX until the first one
+    last_write_cycle: u64,
 }
 unsafe impl Send for MMC1Cartridge {}
 
@@ -156,9 +497,10 @@ impl MMC1Cartridge {
             shift_count: 0,
             prg_bank_offsets: (0, 0),
             chr_bank_offsets: (0, 0),
+            cpu_cycle: 0,
+            last_write_cycle: u64::MAX,
         };
         cartridge.reset();
-        cartridge.cart.load();
         cartridge
     }
     fn reset(&mut self) {
@@ -208,8 +550,8 @@ impl MMC1Cartridge {
                 self.prg_bank_offsets = (bank * 0x4000, (bank + 1) * 0x4000);
             }
             2 => {
-                // First bank fixed to last bank, second bank switchable
-                self.prg_bank_offsets = ((prg_bank_count - 1) * 0x4000, (self.prg_bank as i32 % prg_bank_count) * 0x4000);
+                // First bank fixed to bank 0, second bank switchable
+                self.prg_bank_offsets = (0, (self.prg_bank as i32 % prg_bank_count) * 0x4000);
             }
             3 => {
                 // First bank switchable, second bank fixed to last bank
@@ -222,228 +564,1503 @@ impl MMC1Cartridge {
     
 }
 
-#[derive(Clone, Debug)]
-pub enum Mapper {
-    None,
-    Mapper0(Cartridge),
-    Mapper1(MMC1Cartridge),
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UxRomCartridge {
+    cart: Cartridge,
+    prg_bank: u8,
 }
-unsafe impl Send for Mapper {}
-impl Mapper {
-    pub fn with_cart(cart: Cartridge) -> Self {
-        
-        match cart.mapper_id {
-            0 => Self::Mapper0(cart),
-            1 => Self::Mapper1(MMC1Cartridge::with_cartridge(cart)),
-            2 => todo!("Mapper2"),
-            4 => todo!("Mapper4"),
-            _ => unreachable!(),
+
+impl UxRomCartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        UxRomCartridge { cart, prg_bank: 0 }
+    }
+    fn prg_bank_count(&self) -> usize {
+        self.cart.prg_rom.len() / 0x4000
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CnRomCartridge {
+    cart: Cartridge,
+    chr_bank: u8,
+}
+
+impl CnRomCartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        CnRomCartridge { cart, chr_bank: 0 }
+    }
+    fn read_prg(&self, addr: u16) -> u8 {
+        if self.cart.prg_banks == 1 {
+            self.cart.prg_rom[addr as usize & 0x3FFF]
+        } else {
+            self.cart.prg_rom[addr as usize - 0x8000]
         }
     }
+}
 
-    pub fn cpu_read(&self, addr: u16) -> u8 {
-        use Mapper::*;
+//dots A12 has to stay low for before a rise clocks the MMC3's scanline counter
+const MMC3_A12_FILTER_DOTS: u32 = 10;
 
-        match self {
-            None => 0,
-            //
-            Mapper0(cart) => match addr {
-                0x6000..=0x7FFF => cart.prg_ram[addr as usize - 0x6000],
-                0x8000..=0xFFFF => {
-                    if cart.prg_banks == 1 {
-                        cart.prg_rom[addr as usize & 0x3FFF]
-                    } else {
-                        cart.prg_rom[addr as usize - 0x8000]
-                    }
-                }
-                _ => 0,
-            },
-            Mapper::Mapper1(mmc1) => match addr {
-                0x6000..=0x7FFF => mmc1.cart.prg_ram[(addr as usize) - 0x6000],
-                0x8000..=0xBFFF => {
-                    let idx = mmc1.prg_bank_offsets.0.wrapping_add(addr as i32 - 0x8000) as usize;
-                    mmc1.cart.prg_rom[idx] // Read from PRG ROM, adjusted for bank offset
-                }
-                0xC000..=0xFFFF => {
-                    let idx = mmc1.prg_bank_offsets.1.wrapping_add(addr as i32 - 0xC000) as usize;
-                    mmc1.cart.prg_rom[idx] // Read from second PRG bank, adjusted for offset
-                }
-                _ => 0,
-            },
-            
-        
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MMC3Cartridge {
+    cart: Cartridge,
+    bank_select: u8,
+    bank_registers: [u8; 8],
+    prg_bank_offsets: [usize; 4],
+    chr_bank_offsets: [usize; 8],
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl MMC3Cartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        let mut cartridge = MMC3Cartridge {
+            cart,
+            bank_select: 0,
+            bank_registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            prg_bank_offsets: [0; 4],
+            chr_bank_offsets: [0; 8],
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        };
+        cartridge.apply_banks();
+        cartridge
+    }
+    fn apply_banks(&mut self) {
+        // PRG is mapped in 8KB banks, the last bank is always fixed at $E000
+        let prg_bank_count = self.cart.prg_rom.len() / 0x2000;
+        let second_last = prg_bank_count - 2;
+        let r6 = self.bank_registers[6] as usize % prg_bank_count;
+        let r7 = self.bank_registers[7] as usize % prg_bank_count;
+
+        //bit 6 swaps which of $8000 and $C000 is switched by R6, the other is fixed to the second last bank
+        let prg_mode = (self.bank_select & 0x40) != 0;
+        let prg_banks = if !prg_mode {
+            [r6, r7, second_last, prg_bank_count - 1]
+        } else {
+            [second_last, r7, r6, prg_bank_count - 1]
+        };
+        for (offset, bank) in self.prg_bank_offsets.iter_mut().zip(prg_banks) {
+            *offset = bank * 0x2000;
+        }
+
+        // CHR is mapped in 1KB units, R0 and R1 select 2KB banks
+        let r = &self.bank_registers;
+        let two_kb = [r[0] & 0xFE, r[0] | 1, r[1] & 0xFE, r[1] | 1];
+        let one_kb = [r[2], r[3], r[4], r[5]];
+        let chr_banks: Vec<u8> = if (self.bank_select & 0x80) == 0 {
+            two_kb.into_iter().chain(one_kb).collect()
+        } else {
+            one_kb.into_iter().chain(two_kb).collect()
+        };
+        let chr_size = if self.cart.chr_banks == 0 {
+            self.cart.chr_ram.len()
+        } else {
+            self.cart.chr_rom.len()
+        };
+        for (offset, bank) in self.chr_bank_offsets.iter_mut().zip(chr_banks) {
+            *offset = (bank as usize * 0x400) % chr_size;
+        }
+    }
+    fn clock_scanline_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
         }
     }
+}
 
-    pub fn cpu_write(&mut self, addr: u16, val: u8) {
-        use Mapper::*;
-        match self {
-            None => {
-                return;
-            }
-            //
-            Mapper0(cart) => {
-                if (0x6000..=0x7FFF).contains(&addr) {
-                    cart.prg_ram[addr as usize - 0x6000] = val;
-                    
-                }
-            } //
-            Mapper1(mmc1) => {
-                if addr < 0x6000 {
-                    return;
-                }
-                if addr >= 0x6000 && addr < 0x8000 {
-                    mmc1.cart.prg_ram[addr as usize - 0x6000] = val;
-                    mmc1.cart.save();
-                    return;
-                }
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AxRomCartridge {
+    cart: Cartridge,
+    prg_bank: u8,
+}
 
-                // Only $8000-$FFFF writes reach here
-                if (val & 0x80) != 0 {
-                    
-                    mmc1.shift_reg = 0x10;
-                    mmc1.control |= 0x0C;
-                    mmc1.shift_count = 0;
-                    mmc1.apply_banks();
-                    return;
-                }
+impl AxRomCartridge {
+    pub fn with_cartridge(mut cart: Cartridge) -> Self {
+        cart.set_mirroring(MirrorMode::SingleScreenA);
+        AxRomCartridge { cart, prg_bank: 0 }
+    }
+}
 
-                mmc1.shift_reg = (mmc1.shift_reg >> 1) | ((val & 0x01) << 4);
-                mmc1.shift_count += 1;
-
-                if mmc1.shift_count == 5 {
-                    let reg = (addr >> 13) & 0x03;
-
-                    match reg {
-                        0 => {
-                            mmc1.control = mmc1.shift_reg & 0x1F;
-                            
-                            mmc1.apply_mirroring();
-                        }
-                        1 => {
-                            mmc1.chr_banks.0 = mmc1.shift_reg & 0x1F;
-                            
-                        }
-                        2 => {
-                            mmc1.chr_banks.1 = mmc1.shift_reg & 0x1F;
-                            
-                        }
-                        3 => {
-                            mmc1.prg_bank = mmc1.shift_reg & 0x0F;
-                            
-                        }
-                        _ => {}
-                    }
-                    mmc1.shift_reg = 0x10;
-                    mmc1.shift_count = 0;
-                    mmc1.apply_banks();
-                }
-            }
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MMC2Cartridge {
+    cart: Cartridge,
+    prg_bank: u8,
+    // 4KB CHR banks, indexed by [pattern table][latch is $FE]
+    chr_banks: [[u8; 2]; 2],
+    // true when the latch holds $FE, false for $FD
+    latches: [bool; 2],
+}
+
+impl MMC2Cartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        MMC2Cartridge {
+            cart,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [true; 2],
         }
     }
+    fn chr_offset(&self, addr: u16) -> usize {
+        let table = (addr as usize >> 12) & 1;
+        let bank = self.chr_banks[table][self.latches[table] as usize] as usize;
+        (bank * 0x1000 + (addr as usize & 0x0FFF)) % self.cart.chr_rom.len()
+    }
+}
 
-    pub fn ppu_read(&self, addr: u16) -> u8 {
-        use Mapper::*;
-        match self {
-            None => 0,
-            Mapper0(cart) => {
-                if addr < 0x2000 {
-                    if cart.chr_banks != 0 {
-                        return cart.chr_rom[addr as usize];
-                    } else {
-                        return cart.chr_ram[addr as usize];
-                    }
-                }
-                0
-            }
-            Mapper1(mmc1) => {
-                if addr < 0x2000 {
-                    if mmc1.cart.chr_banks == 0 {
-                        // CHR RAM mode
-                        let chr_mode = (mmc1.control >> 4) & 1;
-                        if chr_mode == 0 {
-                            // 8KB mode
-                            let val = mmc1.cart.chr_ram[addr as usize];
-                            
-                            return val;
-                        } else {
-                            // 4KB mode
-                            let bank = if addr < 0x1000 { mmc1.chr_banks.0 } else { mmc1.chr_banks.1 };
-                            let offset = (bank as usize * 0x1000) + (addr as usize & 0x0FFF);
-                            let val = mmc1.cart.chr_ram[offset];
-                            
-                            return val;
-                        }
-                    } else {
-                        // CHR ROM mode
-                        let chr_mode = (mmc1.control >> 4) & 1;
-                        if chr_mode == 0 {
-                            // 8KB mode
-                            let bank = mmc1.chr_banks.0 & 0x1E;
-                            let offset = (bank as usize * 0x1000) + (addr as usize & 0x1FFF);
-                            return mmc1.cart.chr_rom[offset];
-                        } else {
-                            // 4KB mode
-                            let bank = if addr < 0x1000 { mmc1.chr_banks.0 } else { mmc1.chr_banks.1 };
-                            let offset = (bank as usize * 0x1000) + (addr as usize & 0x0FFF);
-                            return mmc1.cart.chr_rom[offset];
-                        }
-                    }
-                }
-                0
-            }
+/*
+    Konami VRC2 and VRC4 on mapper 23. The boards differ in which cpu
+    address lines pick the register within each $1000 range, the submapper
+    tells them apart and without one both wirings are accepted. VRC4 adds a
+    PRG swap mode, single screen mirroring and a cycle counting IRQ.
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VrcCartridge {
+    cart: Cartridge,
+    is_vrc2: bool,
+    prg_banks: [u8; 2],
+    prg_swap: bool,
+    chr_banks: [u16; 8],
+    irq_latch: u8,
+    irq_counter: u8,
+    //counts down 3 per cpu cycle from 341, so the counter ticks once per scanline
+    irq_prescaler: i16,
+    irq_enabled: bool,
+    irq_enable_after_ack: bool,
+    irq_cycle_mode: bool,
+    irq_pending: bool,
+}
+
+impl VrcCartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        VrcCartridge {
+            is_vrc2: cart.submapper == 3,
+            cart,
+            prg_banks: [0, 0],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_prescaler: 341,
+            irq_enabled: false,
+            irq_enable_after_ack: false,
+            irq_cycle_mode: false,
+            irq_pending: false,
+        }
+    }
+    //register 0-3 within a $1000 range, from A0/A1 or A2/A3 depending on the board
+    fn register(&self, addr: u16) -> u16 {
+        match self.cart.submapper {
+            1 | 3 => addr & 0x03,
+            2 => (addr >> 2) & 0x03,
+            _ => (addr | (addr >> 2)) & 0x03,
+        }
+    }
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0xFF {
+            self.irq_counter = self.irq_latch;
+            self.irq_pending = true;
+        } else {
+            self.irq_counter += 1;
         }
     }
+}
 
-    pub fn ppu_write(&mut self, addr: u16, val: u8) {
-        use Mapper::*;
-        match self {
-            None => {
-                return;
-            }
-            Mapper0(cart) => {
-                if addr < 0x2000 && cart.chr_banks == 0 {
-                    cart.chr_ram[addr as usize] = val;
-                }
+/*
+    Sunsoft FME-7, a command register at $8000-$9FFF picks which of 16
+    registers a write to $A000-$BFFF goes to. 0-7 are 1KB CHR banks, 8 maps
+    ROM or RAM at $6000, 9-B are 8KB PRG banks, C is mirroring and D-F
+    control an IRQ counter that counts down once per cpu cycle.
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Fme7Cartridge {
+    cart: Cartridge,
+    command: u8,
+    chr_banks: [u8; 8],
+    //register 8, bit 6 selects RAM and bit 7 enables it
+    prg_ram_control: u8,
+    prg_banks: [u8; 3],
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+}
+
+impl Fme7Cartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        Fme7Cartridge {
+            cart,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_ram_control: 0,
+            prg_banks: [0; 3],
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+        }
+    }
+    fn read_prg_bank(&self, bank: u8, addr: u16) -> u8 {
+        let bank = bank as usize % (self.cart.prg_rom.len() / 0x2000);
+        self.cart.prg_rom[bank * 0x2000 + (addr as usize & 0x1FFF)]
+    }
+    fn write_register(&mut self, val: u8) {
+        match self.command {
+            0..=7 => self.chr_banks[self.command as usize] = val,
+            8 => self.prg_ram_control = val,
+            9..=0x0B => self.prg_banks[self.command as usize - 9] = val & 0x3F,
+            0x0C => {
+                let mode = match val & 0x03 {
+                    0 => MirrorMode::Vertical,
+                    1 => MirrorMode::Horizontal,
+                    2 => MirrorMode::SingleScreenA,
+                    _ => MirrorMode::SingleScreenB,
+                };
+                self.cart.set_mirroring(mode);
             }
-            Mapper1(mmc1) => {
-                if addr < 0x2000 && mmc1.cart.chr_banks == 0 {
-                    // CHR RAM mode
-                    let chr_mode = (mmc1.control >> 4) & 1;
-                    if chr_mode == 0 {
-                        
-                        mmc1.cart.chr_ram[addr as usize] = val;
-                    } else {
-                        // 4KB mode
-                        let bank = if addr < 0x1000 { mmc1.chr_banks.0 } else { mmc1.chr_banks.1 };
-                        let offset = (bank as usize * 0x1000) + (addr as usize & 0x0FFF);
-                        
-                        mmc1.cart.chr_ram[offset] = val;
-                    }
-                }
+            // Any write to the control register acknowledges the IRQ
+            0x0D => {
+                self.irq_enabled = (val & 0x01) != 0;
+                self.irq_counter_enabled = (val & 0x80) != 0;
+                self.irq_pending = false;
             }
+            0x0E => self.irq_counter = (self.irq_counter & 0xFF00) | val as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | ((val as u16) << 8),
         }
     }
-    pub fn get_mirror_mode(&self) -> MirrorMode {
-        use Mapper::*;
-        match self {
-            None => MirrorMode::Horizontal,
-            Mapper0(cart) => cart.mirror_mode,
-            Mapper1(MMC1Cartridge { cart, .. }) => cart.mirror_mode,
+}
+
+/*
+    Camerica boards bank PRG like UxROM, but the bank register is at
+    $C000-$FFFF. Fire Hawk's board adds single screen mirroring control
+    at $8000-$9FFF. CHR is always 8KB of RAM.
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CamericaCartridge {
+    cart: Cartridge,
+    prg_bank: u8,
+}
+
+impl CamericaCartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        CamericaCartridge { cart, prg_bank: 0 }
+    }
+    fn prg_bank_count(&self) -> usize {
+        self.cart.prg_rom.len() / 0x4000
+    }
+}
+
+/*
+    MMC5 with its PRG and CHR banking, the multiplier and ExRAM. PRG banks
+    are 8KB registers at $5113-$5117, bit 7 picks ROM over RAM. CHR banks are
+    1KB registers at $5120-$5127 for sprites and $5128-$512B for the
+    background, with $5130 as the upper bits. Larger modes use the last
+    register of each window. With 8x16 sprites the background always uses its
+    own set, otherwise the last set written is used for everything.
+    ExRAM mode 1 gives every background tile its own 4KB bank and palette, and
+    the vertical split draws tiles from ExRAM on one side of a column. ExRAM
+    and fill mode nametables, the scanline IRQ and audio aren't emulated yet.
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MMC5Cartridge {
+    cart: Cartridge,
+    prg_mode: u8,
+    chr_mode: u8,
+    exram_mode: u8,
+    //$5113-$5117
+    prg_banks: [u8; 5],
+    //$5120-$5127
+    chr_banks: [u16; 8],
+    //$5128-$512B
+    bg_chr_banks: [u16; 4],
+    //whether $5128-$512B were written after $5120-$5127
+    bg_set_written_last: bool,
+    //snooped from $2000
+    tall_sprites: bool,
+    chr_upper: u8,
+    //$5200-$5202
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    exram: Vec<u8>,
+    multiplicand: u8,
+    multiplier: u8,
+}
+
+impl MMC5Cartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        MMC5Cartridge {
+            cart,
+            prg_mode: 3,
+            chr_mode: 3,
+            exram_mode: 0,
+            prg_banks: [0, 0, 0, 0, 0xFF],
+            chr_banks: [0; 8],
+            bg_chr_banks: [0; 4],
+            bg_set_written_last: false,
+            tall_sprites: false,
+            chr_upper: 0,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            exram: vec![0; 0x400],
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
         }
     }
-    pub fn run_scanline_irq(&mut self) {
-        use Mapper::*;
-        match self {
-            Mapper0(_) => {}
-            _ => todo!("Mapper4"),
+    //whether a $8000-$FFFF address maps ROM, and the 8KB bank index of its window
+    fn prg_bank(&self, addr: u16) -> (bool, usize) {
+        let slot = (addr as usize - 0x8000) / 0x2000;
+        //register index into $5114-$5117 and the window size in 8KB banks for each slot
+        let (reg, size) = match (self.prg_mode, slot) {
+            (0, _) => (3, 4),
+            (1, 0 | 1) => (1, 2),
+            (1, _) => (3, 2),
+            (2, 0 | 1) => (1, 2),
+            (_, slot) => (slot, 1),
+        };
+        let val = self.prg_banks[reg + 1];
+        let first_slot = slot & !(size - 1);
+        let bank = (val as usize & 0x7F & !(size - 1)) + (slot - first_slot);
+        //$5117 always maps ROM
+        (reg == 3 || (val & 0x80) != 0, bank)
+    }
+    fn chr_offset(&self, addr: u16, background: bool) -> usize {
+        //8KB >> mode per bank, each window uses the last register that covers it
+        let size = 0x2000 >> self.chr_mode;
+        let use_bg_set = if self.tall_sprites { background } else { self.bg_set_written_last };
+        let bank = if use_bg_set {
+            //the 4 background registers cover $0000-$0FFF and repeat at $1000, the 8KB mode uses $512B
+            let slot = (addr as usize & 0x0FFF) / size;
+            self.bg_chr_banks[(slot + 1) * (8 >> self.chr_mode).min(4) - 1]
+        } else {
+            let slot = addr as usize / size;
+            self.chr_banks[(slot + 1) * (8 >> self.chr_mode) - 1]
+        };
+        bank as usize * size + (addr as usize % size)
+    }
+    fn read_chr(&self, offset: usize) -> u8 {
+        if self.cart.chr_banks != 0 {
+            self.cart.chr_rom[offset % self.cart.chr_rom.len()]
+        } else {
+            self.cart.chr_ram[offset % self.cart.chr_ram.len()]
         }
     }
-    pub fn irq_pending(&self) -> bool {
-        use Mapper::*;
-        match self {
-            Mapper0(_) => false,
-            _ => todo!("All mappers other besides Mapper0"),
+    fn set_nametable_mapping(&mut self, val: u8) {
+        //the arrangements the regular mirror modes can express, ExRAM and fill mode nametables aren't supported
+        let mode = match val {
+            0x44 => MirrorMode::Vertical,
+            0x50 => MirrorMode::Horizontal,
+            0x00 => MirrorMode::SingleScreenA,
+            0x55 => MirrorMode::SingleScreenB,
+            _ => return,
+        };
+        self.cart.set_mirroring(mode);
+    }
+    //the split only works while ExRAM is used for nametables or attributes
+    fn in_split(&self, column: u32) -> bool {
+        if (self.split_control & 0x80) == 0 || self.exram_mode > 1 {
+            return false;
+        }
+        let threshold = (self.split_control & 0x1F) as u32;
+        if (self.split_control & 0x40) != 0 {
+            column >= threshold
+        } else {
+            column < threshold
         }
     }
+    //tiles on the split side come from ExRAM with their own vertical scroll and 4KB bank
+    fn split_tile(&self, column: u32, scanline: u32) -> BackgroundTile {
+        let y = (scanline + self.split_scroll as u32) % 240;
+        let (row, column) = (y as usize / 8, column as usize & 0x1F);
+        let tile = self.exram[row * 32 + column] as usize;
+        let attribute = self.exram[0x3C0 + (row / 4) * 8 + column / 4];
+        let shift = ((row & 0x02) << 1) | (column & 0x02);
+        let offset = self.split_bank as usize * 0x1000 + tile * 16 + (y as usize % 8);
+        BackgroundTile {
+            pattern: [self.read_chr(offset), self.read_chr(offset + 8)],
+            palette: Some((attribute >> shift) & 0x03),
+        }
+    }
+}
+
+//one register selects both a 32KB PRG bank and an 8KB CHR bank
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GxRomCartridge {
+    cart: Cartridge,
+    prg_bank: u8,
+    chr_bank: u8,
+}
+
+impl GxRomCartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        GxRomCartridge {
+            cart,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NsfCartridge {
+    pub cart: Cartridge,
+    //4KB bank mapped to each slot of $8000-$FFFF, written through $5FF8-$5FFF
+    banks: [u8; 8],
+}
+
+impl NsfCartridge {
+    pub fn with_cartridge(cart: Cartridge) -> Self {
+        //NsfPlayer writes the starting banks on init
+        NsfCartridge {
+            cart,
+            banks: [0, 1, 2, 3, 4, 5, 6, 7],
+        }
+    }
+    fn read_prg(&self, addr: u16) -> u8 {
+        let slot = (addr as usize - 0x8000) / 0x1000;
+        let bank = self.banks[slot] as usize % (self.cart.prg_rom.len() / 0x1000);
+        self.cart.prg_rom[bank * 0x1000 + (addr as usize & 0x0FFF)]
+    }
+}
+
+
+/*
+    Everything the bus and PPU need from a board. A new mapper is a struct
+    holding its Cartridge and registers that implements this, plus a line in
+    Mapper::with_cart. Save states come for free through SaveMapperState as
+    long as the struct derives Serialize and Deserialize.
+*/
+pub trait MapperTrait: SaveMapperState {
+    fn cpu_read(&self, addr: u16) -> u8;
+    fn cpu_write(&mut self, addr: u16, val: u8);
+    fn ppu_read(&self, addr: u16) -> u8;
+    fn ppu_write(&mut self, addr: u16, val: u8);
+    fn cartridge(&self) -> &Cartridge;
+    fn cartridge_mut(&mut self) -> &mut Cartridge;
+    //tags save states so they only load into the same board, the iNES mapper number for roms
+    fn mapper_id(&self) -> u16 {
+        self.cartridge().mapper_id as u16
+    }
+    fn get_mirror_mode(&self) -> MirrorMode {
+        self.cartridge().mirror_mode
+    }
+    //called by the PPU after it fetches the high plane of a pattern, bits 4-11 of addr are the tile id
+    fn notify_chr_fetch(&mut self, _addr: u16) {}
+    //called by the PPU when address line A12 goes from low to high, after being low for low_dots dots
+    fn notify_a12_rise(&mut self, _low_dots: u32) {}
+    //called once per cpu cycle, for IRQ counters that count cycles rather than A12 rises
+    fn clock_cpu_cycle(&mut self) {}
+    //called by the PPU on $2000 writes, for boards that snoop the sprite size
+    fn notify_ppu_control(&mut self, _val: u8) {}
+    /*
+        Called by the PPU for every background pixel, for boards that supply
+        background tiles themselves. nametable_addr is the entry being drawn,
+        pattern_addr the row of its pattern and column, scanline the tile's
+        place on screen. None lets the PPU fetch the tile itself.
+    */
+    fn background_tile(&self, _nametable_addr: u16, _pattern_addr: u16, _column: u32, _scanline: u32) -> Option<BackgroundTile> {
+        None
+    }
+    fn irq_pending(&self) -> bool {
+        false
+    }
+}
+
+//the pattern planes of a background tile row, and its palette if the board picks that too
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackgroundTile {
+    pub pattern: [u8; 2],
+    pub palette: Option<u8>,
+}
+
+//past the 8 bit iNES mapper numbers, for the boards that don't come from an iNES header
+const NO_CARTRIDGE_MAPPER_ID: u16 = 0x100;
+const NSF_MAPPER_ID: u16 = 0x101;
+
+//a mapper's bank registers and ram, tagged with the mapper it was saved from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MapperState {
+    mapper_id: u16,
+    data: Vec<u8>,
+}
+
+pub trait SaveMapperState {
+    fn save_state(&self) -> bincode::Result<MapperState>;
+    fn load_state(&mut self, state: &MapperState) -> bool;
+}
+
+impl<M: MapperTrait + Serialize + DeserializeOwned> SaveMapperState for M {
+    //the rom isn't serialized, it is moved over from the current cartridge on load
+    fn save_state(&self) -> bincode::Result<MapperState> {
+        Ok(MapperState {
+            mapper_id: self.mapper_id(),
+            data: bincode::serialize(self)?,
+        })
+    }
+    fn load_state(&mut self, state: &MapperState) -> bool {
+        if state.mapper_id != self.mapper_id() {
+            return false;
+        }
+        let Ok(mut saved) = bincode::deserialize::<M>(&state.data) else {
+            return false;
+        };
+        saved.cartridge_mut().take_rom(self.cartridge_mut());
+        *self = saved;
+        true
+    }
+}
+
+pub struct Mapper(Box<dyn MapperTrait + Send>);
+
+impl Mapper {
+    pub fn none() -> Self {
+        Mapper(Box::new(NoCartridge { cart: Cartridge::empty() }))
+    }
+    pub fn with_cart(cart: Cartridge) -> Self {
+        if cart.nsf.is_some() {
+            return Mapper(Box::new(NsfCartridge::with_cartridge(cart)));
+        }
+        match cart.mapper_id {
+            0 => Mapper(Box::new(NRomCartridge::with_cartridge(cart))),
+            1 => Mapper(Box::new(MMC1Cartridge::with_cartridge(cart))),
+            2 => Mapper(Box::new(UxRomCartridge::with_cartridge(cart))),
+            3 => Mapper(Box::new(CnRomCartridge::with_cartridge(cart))),
+            4 => Mapper(Box::new(MMC3Cartridge::with_cartridge(cart))),
+            5 => Mapper(Box::new(MMC5Cartridge::with_cartridge(cart))),
+            7 => Mapper(Box::new(AxRomCartridge::with_cartridge(cart))),
+            9 => Mapper(Box::new(MMC2Cartridge::with_cartridge(cart))),
+            23 => Mapper(Box::new(VrcCartridge::with_cartridge(cart))),
+            66 => Mapper(Box::new(GxRomCartridge::with_cartridge(cart))),
+            69 => Mapper(Box::new(Fme7Cartridge::with_cartridge(cart))),
+            71 => Mapper(Box::new(CamericaCartridge::with_cartridge(cart))),
+            id => {
+                eprintln!("Mapper {} is not supported", id);
+                Self::none()
+            }
+        }
+    }
+    pub fn is_supported(mapper_id: u8) -> bool {
+        matches!(mapper_id, 0 | 1 | 2 | 3 | 4 | 5 | 7 | 9 | 23 | 66 | 69 | 71)
+    }
+
+    pub fn cpu_read(&self, addr: u16) -> u8 {
+        self.0.cpu_read(addr)
+    }
+    pub fn cpu_write(&mut self, addr: u16, val: u8) {
+        self.0.cpu_write(addr, val)
+    }
+    pub fn ppu_read(&self, addr: u16) -> u8 {
+        self.0.ppu_read(addr)
+    }
+    pub fn ppu_write(&mut self, addr: u16, val: u8) {
+        self.0.ppu_write(addr, val)
+    }
+    pub fn get_mirror_mode(&self) -> MirrorMode {
+        self.0.get_mirror_mode()
+    }
+    //the mapper without its rom, which is all a save state needs
+    pub fn save_state(&self) -> bincode::Result<MapperState> {
+        self.0.save_state()
+    }
+    //replaces the mapper's registers and ram with saved ones
+    //returns false if the saved state belongs to a different kind of mapper
+    pub fn load_state(&mut self, state: &MapperState) -> bool {
+        self.0.load_state(state)
+    }
+    //writes battery backed ram to disk, does nothing for carts without a battery
+    pub fn save(&self) -> std::io::Result<()> {
+        self.0.cartridge().save()
+    }
+    pub fn notify_chr_fetch(&mut self, addr: u16) {
+        self.0.notify_chr_fetch(addr)
+    }
+    pub fn notify_a12_rise(&mut self, low_dots: u32) {
+        self.0.notify_a12_rise(low_dots)
+    }
+    pub fn clock_cpu_cycle(&mut self) {
+        self.0.clock_cpu_cycle()
+    }
+    pub fn notify_ppu_control(&mut self, val: u8) {
+        self.0.notify_ppu_control(val)
+    }
+    pub fn background_tile(&self, nametable_addr: u16, pattern_addr: u16, column: u32, scanline: u32) -> Option<BackgroundTile> {
+        self.0.background_tile(nametable_addr, pattern_addr, column, scanline)
+    }
+    pub fn irq_pending(&self) -> bool {
+        self.0.irq_pending()
+    }
+}
+
+impl MapperTrait for NoCartridge {
+    fn cpu_read(&self, _addr: u16) -> u8 {
+        0
+    }
+    fn cpu_write(&mut self, _addr: u16, _val: u8) {}
+    fn ppu_read(&self, _addr: u16) -> u8 {
+        0
+    }
+    fn ppu_write(&mut self, _addr: u16, _val: u8) {}
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+    fn mapper_id(&self) -> u16 {
+        NO_CARTRIDGE_MAPPER_ID
+    }
+}
+
+impl MapperTrait for NRomCartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                if self.cart.prg_banks == 1 {
+                    self.cart.prg_rom[addr as usize & 0x3FFF]
+                } else {
+                    self.cart.prg_rom[addr as usize - 0x8000]
+                }
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if (0x6000..=0x7FFF).contains(&addr) {
+            self.cart.prg_ram[addr as usize - 0x6000] = val;
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            if self.cart.chr_banks != 0 {
+                return self.cart.chr_rom[addr as usize];
+            } else {
+                return self.cart.chr_ram[addr as usize];
+            }
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 && self.cart.chr_banks == 0 {
+            self.cart.chr_ram[addr as usize] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+}
+
+impl MapperTrait for MMC1Cartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[(addr as usize) - 0x6000],
+            0x8000..=0xBFFF => {
+                let idx = self.prg_bank_offsets.0.wrapping_add(addr as i32 - 0x8000) as usize;
+                self.cart.prg_rom[idx] // Read from PRG ROM, adjusted for bank offset
+            }
+            0xC000..=0xFFFF => {
+                let idx = self.prg_bank_offsets.1.wrapping_add(addr as i32 - 0xC000) as usize;
+                self.cart.prg_rom[idx] // Read from second PRG bank, adjusted for offset
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x6000 {
+            return;
+        }
+        if addr < 0x8000 {
+            self.cart.prg_ram[addr as usize - 0x6000] = val;
+            return;
+        }
+
+        // Only $8000-$FFFF writes reach here
+        /*
+            The serial port ignores a write on the cycle right after another
+            one. Read-modify-write instructions write twice in a row, which
+            games like Zelda II rely on only counting once.
+        */
+        let consecutive = self.last_write_cycle != u64::MAX && self.cpu_cycle - self.last_write_cycle < 2;
+        self.last_write_cycle = self.cpu_cycle;
+        if consecutive {
+            return;
+        }
+
+        if (val & 0x80) != 0 {
+            self.shift_reg = 0x10;
+            self.control |= 0x0C;
+            self.shift_count = 0;
+            self.apply_banks();
+            return;
+        }
+
+        self.shift_reg = (self.shift_reg >> 1) | ((val & 0x01) << 4);
+        self.shift_count += 1;
+
+        if self.shift_count == 5 {
+            let reg = (addr >> 13) & 0x03;
+
+            match reg {
+                0 => {
+                    self.control = self.shift_reg & 0x1F;
+                    self.apply_mirroring();
+                }
+                1 => self.chr_banks.0 = self.shift_reg & 0x1F,
+                2 => self.chr_banks.1 = self.shift_reg & 0x1F,
+                3 => self.prg_bank = self.shift_reg & 0x0F,
+                _ => {}
+            }
+            self.shift_reg = 0x10;
+            self.shift_count = 0;
+            self.apply_banks();
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr >= 0x2000 {
+            return 0;
+        }
+        let chr_mode = (self.control >> 4) & 1;
+        if self.cart.chr_banks == 0 {
+            // CHR RAM mode
+            if chr_mode == 0 {
+                // 8KB mode
+                self.cart.chr_ram[addr as usize]
+            } else {
+                // 4KB mode
+                let bank = if addr < 0x1000 { self.chr_banks.0 } else { self.chr_banks.1 };
+                let offset = (bank as usize * 0x1000) + (addr as usize & 0x0FFF);
+                self.cart.chr_ram[offset]
+            }
+        } else {
+            // CHR ROM mode
+            if chr_mode == 0 {
+                // 8KB mode
+                let bank = self.chr_banks.0 & 0x1E;
+                let offset = (bank as usize * 0x1000) + (addr as usize & 0x1FFF);
+                self.cart.chr_rom[offset]
+            } else {
+                // 4KB mode
+                let bank = if addr < 0x1000 { self.chr_banks.0 } else { self.chr_banks.1 };
+                let offset = (bank as usize * 0x1000) + (addr as usize & 0x0FFF);
+                self.cart.chr_rom[offset]
+            }
+        }
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 && self.cart.chr_banks == 0 {
+            // CHR RAM mode
+            let chr_mode = (self.control >> 4) & 1;
+            if chr_mode == 0 {
+                self.cart.chr_ram[addr as usize] = val;
+            } else {
+                // 4KB mode
+                let bank = if addr < 0x1000 { self.chr_banks.0 } else { self.chr_banks.1 };
+                let offset = (bank as usize * 0x1000) + (addr as usize & 0x0FFF);
+                self.cart.chr_ram[offset] = val;
+            }
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+    fn clock_cpu_cycle(&mut self) {
+        self.cpu_cycle += 1;
+    }
+}
+
+impl MapperTrait for UxRomCartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xBFFF => {
+                let bank = self.prg_bank as usize % self.prg_bank_count();
+                self.cart.prg_rom[bank * 0x4000 + (addr as usize & 0x3FFF)]
+            }
+            0xC000..=0xFFFF => {
+                // Fixed to the last bank
+                let bank = self.prg_bank_count() - 1;
+                self.cart.prg_rom[bank * 0x4000 + (addr as usize & 0x3FFF)]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000] = val,
+            0x8000..=0xFFFF => self.prg_bank = self.cart.bus_conflict(val, self.cpu_read(addr)) & 0x0F,
+            _ => {}
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            if self.cart.chr_banks != 0 {
+                return self.cart.chr_rom[addr as usize];
+            } else {
+                return self.cart.chr_ram[addr as usize];
+            }
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 && self.cart.chr_banks == 0 {
+            self.cart.chr_ram[addr as usize] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+}
+
+impl MapperTrait for CnRomCartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => self.read_prg(addr),
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000] = val,
+            0x8000..=0xFFFF => self.chr_bank = self.cart.bus_conflict(val, self.read_prg(addr)),
+            _ => {}
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            if self.cart.chr_banks != 0 {
+                let bank = self.chr_bank as usize % self.cart.chr_banks as usize;
+                return self.cart.chr_rom[bank * 0x2000 + (addr as usize & 0x1FFF)];
+            } else {
+                return self.cart.chr_ram[addr as usize];
+            }
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 && self.cart.chr_banks == 0 {
+            self.cart.chr_ram[addr as usize] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+}
+
+impl MapperTrait for MMC3Cartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                let slot = (addr as usize - 0x8000) / 0x2000;
+                self.cart.prg_rom[self.prg_bank_offsets[slot] + (addr as usize & 0x1FFF)]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        let even = (addr & 1) == 0;
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000] = val,
+            0x8000..=0x9FFF => {
+                if even {
+                    self.bank_select = val;
+                } else {
+                    let target = (self.bank_select & 0x07) as usize;
+                    self.bank_registers[target] = val;
+                }
+                self.apply_banks();
+            }
+            // Odd writes are PRG RAM protect, which we don't emulate
+            0xA000..=0xBFFF if even => {
+                let mode = if (val & 1) == 0 {
+                    MirrorMode::Vertical
+                } else {
+                    MirrorMode::Horizontal
+                };
+                self.cart.set_mirroring(mode);
+            }
+            0xC000..=0xDFFF => {
+                if even {
+                    self.irq_latch = val;
+                } else {
+                    self.irq_counter = 0;
+                    self.irq_reload = true;
+                }
+            }
+            0xE000..=0xFFFF => {
+                if even {
+                    self.irq_enabled = false;
+                    self.irq_pending = false;
+                } else {
+                    self.irq_enabled = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            let slot = addr as usize / 0x400;
+            let offset = self.chr_bank_offsets[slot] + (addr as usize & 0x3FF);
+            if self.cart.chr_banks != 0 {
+                return self.cart.chr_rom[offset];
+            } else {
+                return self.cart.chr_ram[offset];
+            }
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 && self.cart.chr_banks == 0 {
+            let slot = addr as usize / 0x400;
+            let offset = self.chr_bank_offsets[slot] + (addr as usize & 0x3FF);
+            self.cart.chr_ram[offset] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+    /*
+        The MMC3 filters A12, a rise only clocks the counter once the line has
+        been low for a few cpu cycles. That drops the rises between the
+        pattern fetches of neighbouring tiles, which are 4 dots apart, as well
+        as the 9 dot gap from the last prefetched tile of a line to the first
+        tile of the next.
+    */
+    fn notify_a12_rise(&mut self, low_dots: u32) {
+        if low_dots >= MMC3_A12_FILTER_DOTS {
+            self.clock_scanline_counter();
+        }
+    }
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+impl MapperTrait for MMC5Cartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[addr as usize - 0x5C00],
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                let (is_rom, bank) = self.prg_bank(addr);
+                if !is_rom {
+                    return self.cart.prg_ram[addr as usize & 0x1FFF];
+                }
+                let bank = bank % (self.cart.prg_rom.len() / 0x2000);
+                self.cart.prg_rom[bank * 0x2000 + (addr as usize & 0x1FFF)]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x5100 => self.prg_mode = val & 0x03,
+            0x5101 => self.chr_mode = val & 0x03,
+            0x5104 => self.exram_mode = val & 0x03,
+            0x5105 => self.set_nametable_mapping(val),
+            0x5113..=0x5117 => self.prg_banks[addr as usize - 0x5113] = val,
+            0x5120..=0x5127 => {
+                self.chr_banks[addr as usize - 0x5120] = ((self.chr_upper as u16) << 8) | val as u16;
+                self.bg_set_written_last = false;
+            }
+            0x5128..=0x512B => {
+                self.bg_chr_banks[addr as usize - 0x5128] = ((self.chr_upper as u16) << 8) | val as u16;
+                self.bg_set_written_last = true;
+            }
+            0x5130 => self.chr_upper = val & 0x03,
+            0x5200 => self.split_control = val,
+            0x5201 => self.split_scroll = val,
+            0x5202 => self.split_bank = val,
+            0x5205 => self.multiplicand = val,
+            0x5206 => self.multiplier = val,
+            //mode 3 makes ExRAM read only
+            0x5C00..=0x5FFF if self.exram_mode != 3 => self.exram[addr as usize - 0x5C00] = val,
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000] = val,
+            0x8000..=0xDFFF if !self.prg_bank(addr).0 => self.cart.prg_ram[addr as usize & 0x1FFF] = val,
+            _ => {}
+        }
+    }
+    //sprite fetches and $2007, the background goes through background_tile
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            return self.read_chr(self.chr_offset(addr, false));
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 && self.cart.chr_banks == 0 {
+            let offset = self.chr_offset(addr, false) % self.cart.chr_ram.len();
+            self.cart.chr_ram[offset] = val;
+        }
+    }
+    fn notify_ppu_control(&mut self, val: u8) {
+        self.tall_sprites = (val & 0x20) != 0;
+    }
+    fn background_tile(&self, nametable_addr: u16, pattern_addr: u16, column: u32, scanline: u32) -> Option<BackgroundTile> {
+        if self.in_split(column) {
+            return Some(self.split_tile(column, scanline));
+        }
+        //extended attributes, each ExRAM byte holds a 4KB bank and a palette for its tile
+        if self.exram_mode == 1 {
+            let ex = self.exram[nametable_addr as usize & 0x3FF];
+            let bank = ((self.chr_upper as usize) << 6) | (ex as usize & 0x3F);
+            let offset = bank * 0x1000 + (pattern_addr as usize & 0x0FFF);
+            return Some(BackgroundTile {
+                pattern: [self.read_chr(offset), self.read_chr(offset + 8)],
+                palette: Some(ex >> 6),
+            });
+        }
+        let offset = self.chr_offset(pattern_addr, true);
+        Some(BackgroundTile {
+            pattern: [self.read_chr(offset), self.read_chr(offset + 8)],
+            palette: None,
+        })
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+}
+
+impl MapperTrait for AxRomCartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank as usize % (self.cart.prg_rom.len() / 0x8000);
+                self.cart.prg_rom[bank * 0x8000 + (addr as usize - 0x8000)]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            let val = self.cart.bus_conflict(val, self.cpu_read(addr));
+            self.prg_bank = val & 0x07;
+            let mode = if (val & 0x10) == 0 {
+                MirrorMode::SingleScreenA
+            } else {
+                MirrorMode::SingleScreenB
+            };
+            self.cart.set_mirroring(mode);
+        }
+    }
+    // AxROM boards always use 8KB of CHR RAM
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            return self.cart.chr_ram[addr as usize];
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 {
+            self.cart.chr_ram[addr as usize] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+}
+
+impl MapperTrait for MMC2Cartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000],
+            0x8000..=0x9FFF => {
+                let bank_count = self.cart.prg_rom.len() / 0x2000;
+                let bank = self.prg_bank as usize % bank_count;
+                self.cart.prg_rom[bank * 0x2000 + (addr as usize & 0x1FFF)]
+            }
+            0xA000..=0xFFFF => {
+                // Fixed to the last three 8KB banks
+                let offset = self.cart.prg_rom.len() - 0x6000;
+                self.cart.prg_rom[offset + (addr as usize - 0xA000)]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000] = val,
+            0xA000..=0xAFFF => self.prg_bank = val & 0x0F,
+            0xB000..=0xBFFF => self.chr_banks[0][0] = val & 0x1F,
+            0xC000..=0xCFFF => self.chr_banks[0][1] = val & 0x1F,
+            0xD000..=0xDFFF => self.chr_banks[1][0] = val & 0x1F,
+            0xE000..=0xEFFF => self.chr_banks[1][1] = val & 0x1F,
+            0xF000..=0xFFFF => {
+                let mode = if (val & 1) == 0 {
+                    MirrorMode::Vertical
+                } else {
+                    MirrorMode::Horizontal
+                };
+                self.cart.set_mirroring(mode);
+            }
+            _ => {}
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            return self.cart.chr_rom[self.chr_offset(addr)];
+        }
+        0
+    }
+    // MMC2 only ever has CHR ROM
+    fn ppu_write(&mut self, _addr: u16, _val: u8) {}
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+    fn notify_chr_fetch(&mut self, addr: u16) {
+        // Latch 0 only reacts to the exact addresses, latch 1 to any row of the tile
+        match addr {
+            0x0FD8 => self.latches[0] = false,
+            0x0FE8 => self.latches[0] = true,
+            0x1FD8..=0x1FDF => self.latches[1] = false,
+            0x1FE8..=0x1FEF => self.latches[1] = true,
+            _ => {}
+        }
+    }
+}
+
+impl MapperTrait for VrcCartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                let bank_count = self.cart.prg_rom.len() / 0x2000;
+                let slot = (addr as usize - 0x8000) / 0x2000;
+                // The swap mode trades the switchable $8000 bank with the fixed second last one at $C000
+                let bank = match (slot, self.prg_swap) {
+                    (0, false) | (2, true) => self.prg_banks[0] as usize,
+                    (1, _) => self.prg_banks[1] as usize,
+                    (3, _) => bank_count - 1,
+                    _ => bank_count - 2,
+                };
+                self.cart.prg_rom[(bank % bank_count) * 0x2000 + (addr as usize & 0x1FFF)]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        let reg = self.register(addr);
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000] = val,
+            0x8000..=0x8FFF => self.prg_banks[0] = val & 0x1F,
+            0x9000..=0x9FFF if self.is_vrc2 || reg == 0 => {
+                let mode = match val & if self.is_vrc2 { 0x01 } else { 0x03 } {
+                    0 => MirrorMode::Vertical,
+                    1 => MirrorMode::Horizontal,
+                    2 => MirrorMode::SingleScreenA,
+                    _ => MirrorMode::SingleScreenB,
+                };
+                self.cart.set_mirroring(mode);
+            }
+            0x9000..=0x9FFF if reg == 2 => self.prg_swap = (val & 0x02) != 0,
+            0xA000..=0xAFFF => self.prg_banks[1] = val & 0x1F,
+            // Each 1KB bank is written a nibble at a time, low then high
+            0xB000..=0xEFFF => {
+                let bank = ((addr as usize - 0xB000) >> 12) * 2 + (reg as usize >> 1);
+                let current = self.chr_banks[bank];
+                self.chr_banks[bank] = if (reg & 1) == 0 {
+                    (current & !0x0F) | (val as u16 & 0x0F)
+                } else {
+                    let high_mask = if self.is_vrc2 { 0x0F } else { 0x1F };
+                    (current & 0x0F) | ((val as u16 & high_mask) << 4)
+                };
+            }
+            0xF000..=0xFFFF if !self.is_vrc2 => match reg {
+                0 => self.irq_latch = (self.irq_latch & 0xF0) | (val & 0x0F),
+                1 => self.irq_latch = (self.irq_latch & 0x0F) | (val << 4),
+                2 => {
+                    self.irq_enable_after_ack = (val & 0x01) != 0;
+                    self.irq_enabled = (val & 0x02) != 0;
+                    self.irq_cycle_mode = (val & 0x04) != 0;
+                    self.irq_pending = false;
+                    if self.irq_enabled {
+                        self.irq_counter = self.irq_latch;
+                        self.irq_prescaler = 341;
+                    }
+                }
+                _ => {
+                    self.irq_pending = false;
+                    self.irq_enabled = self.irq_enable_after_ack;
+                }
+            },
+            _ => {}
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            let bank = self.chr_banks[addr as usize / 0x400] as usize;
+            if self.cart.chr_banks != 0 {
+                return self.cart.chr_rom[(bank * 0x400 + (addr as usize & 0x3FF)) % self.cart.chr_rom.len()];
+            } else {
+                return self.cart.chr_ram[(bank * 0x400 + (addr as usize & 0x3FF)) % self.cart.chr_ram.len()];
+            }
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 && self.cart.chr_banks == 0 {
+            let bank = self.chr_banks[addr as usize / 0x400] as usize;
+            let offset = (bank * 0x400 + (addr as usize & 0x3FF)) % self.cart.chr_ram.len();
+            self.cart.chr_ram[offset] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+    fn clock_cpu_cycle(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_cycle_mode {
+            self.clock_irq_counter();
+            return;
+        }
+        self.irq_prescaler -= 3;
+        if self.irq_prescaler <= 0 {
+            self.irq_prescaler += 341;
+            self.clock_irq_counter();
+        }
+    }
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+impl MapperTrait for Fme7Cartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => {
+                if (self.prg_ram_control & 0x40) == 0 {
+                    self.read_prg_bank(self.prg_ram_control & 0x3F, addr)
+                } else if (self.prg_ram_control & 0x80) != 0 {
+                    self.cart.prg_ram[addr as usize - 0x6000]
+                } else {
+                    0
+                }
+            }
+            0x8000..=0xDFFF => {
+                let slot = (addr as usize - 0x8000) / 0x2000;
+                self.read_prg_bank(self.prg_banks[slot], addr)
+            }
+            // Fixed to the last bank
+            0xE000..=0xFFFF => self.read_prg_bank(0xFF, addr),
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x6000..=0x7FFF if (self.prg_ram_control & 0xC0) == 0xC0 => {
+                self.cart.prg_ram[addr as usize - 0x6000] = val;
+            }
+            0x8000..=0x9FFF => self.command = val & 0x0F,
+            0xA000..=0xBFFF => self.write_register(val),
+            _ => {}
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            let bank = self.chr_banks[addr as usize / 0x400] as usize;
+            if self.cart.chr_banks != 0 {
+                return self.cart.chr_rom[(bank * 0x400 + (addr as usize & 0x3FF)) % self.cart.chr_rom.len()];
+            } else {
+                return self.cart.chr_ram[(bank * 0x400 + (addr as usize & 0x3FF)) % self.cart.chr_ram.len()];
+            }
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 && self.cart.chr_banks == 0 {
+            let bank = self.chr_banks[addr as usize / 0x400] as usize;
+            let offset = (bank * 0x400 + (addr as usize & 0x3FF)) % self.cart.chr_ram.len();
+            self.cart.chr_ram[offset] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+    fn clock_cpu_cycle(&mut self) {
+        if !self.irq_counter_enabled {
+            return;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+        if self.irq_counter == 0xFFFF && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+impl MapperTrait for CamericaCartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xBFFF => {
+                let bank = self.prg_bank as usize % self.prg_bank_count();
+                self.cart.prg_rom[bank * 0x4000 + (addr as usize & 0x3FFF)]
+            }
+            0xC000..=0xFFFF => {
+                // Fixed to the last bank
+                let bank = self.prg_bank_count() - 1;
+                self.cart.prg_rom[bank * 0x4000 + (addr as usize & 0x3FFF)]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x8000..=0x9FFF => {
+                let mode = if (val & 0x10) == 0 {
+                    MirrorMode::SingleScreenA
+                } else {
+                    MirrorMode::SingleScreenB
+                };
+                self.cart.set_mirroring(mode);
+            }
+            0xC000..=0xFFFF => self.prg_bank = val & 0x0F,
+            _ => {}
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            return self.cart.chr_ram[addr as usize];
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 {
+            self.cart.chr_ram[addr as usize] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+}
+
+impl MapperTrait for GxRomCartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank as usize % (self.cart.prg_rom.len() / 0x8000);
+                self.cart.prg_rom[bank * 0x8000 + (addr as usize - 0x8000)]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            let val = self.cart.bus_conflict(val, self.cpu_read(addr));
+            self.prg_bank = (val >> 4) & 0x03;
+            self.chr_bank = val & 0x03;
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            if self.cart.chr_banks != 0 {
+                let bank = self.chr_bank as usize % self.cart.chr_banks as usize;
+                return self.cart.chr_rom[bank * 0x2000 + addr as usize];
+            } else {
+                return self.cart.chr_ram[addr as usize];
+            }
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 && self.cart.chr_banks == 0 {
+            self.cart.chr_ram[addr as usize] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+}
+
+impl MapperTrait for NsfCartridge {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => self.read_prg(addr),
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x5FF8..=0x5FFF => self.banks[addr as usize - 0x5FF8] = val,
+            0x6000..=0x7FFF => self.cart.prg_ram[addr as usize - 0x6000] = val,
+            _ => {}
+        }
+    }
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            return self.cart.chr_ram[addr as usize];
+        }
+        0
+    }
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x2000 {
+            self.cart.chr_ram[addr as usize] = val;
+        }
+    }
+    fn cartridge(&self) -> &Cartridge {
+        &self.cart
+    }
+    fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+    fn mapper_id(&self) -> u16 {
+        NSF_MAPPER_ID
+    }
 }
@@ -0,0 +1,32 @@
+/*
+    A plain rgba color for the viewers and palettes. It mirrors the parts of
+    sdl2's Color the core used so the emulation side builds without SDL2.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+#[allow(non_snake_case)]
+impl Color {
+    pub const BLACK: Color = Color::RGBA(0, 0, 0, 255);
+    pub const WHITE: Color = Color::RGBA(255, 255, 255, 255);
+
+    pub const fn RGB(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+
+    pub const fn RGBA(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl From<Color> for sdl2::pixels::Color {
+    fn from(color: Color) -> Self {
+        sdl2::pixels::Color::RGBA(color.r, color.g, color.b, color.a)
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "sdl")]
+use sdl2::{
+    controller::{Axis, Button},
+    keyboard::Keycode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::apu::{AuChannel, DEFAULT_SAMPLE_RATE};
+
+//keys for one controller, as SDL key names ("X", "Left Shift", "Return"...)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerBindings {
+    pub a: String,
+    pub b: String,
+    pub select: String,
+    pub start: String,
+    pub up: String,
+    pub down: String,
+    pub left: String,
+    pub right: String,
+}
+
+impl PlayerBindings {
+    //names paired with their bit in the controller state
+    fn buttons(&self) -> [(&str, u8); 8] {
+        [
+            (&self.a, 1),
+            (&self.b, 1 << 1),
+            (&self.select, 1 << 2),
+            (&self.start, 1 << 3),
+            (&self.up, 1 << 4),
+            (&self.down, 1 << 5),
+            (&self.left, 1 << 6),
+            (&self.right, 1 << 7),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyBindings {
+    pub player1: PlayerBindings,
+    pub player2: PlayerBindings,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = |keys: [&str; 8]| PlayerBindings {
+            a: keys[0].to_string(),
+            b: keys[1].to_string(),
+            select: keys[2].to_string(),
+            start: keys[3].to_string(),
+            up: keys[4].to_string(),
+            down: keys[5].to_string(),
+            left: keys[6].to_string(),
+            right: keys[7].to_string(),
+        };
+        KeyBindings {
+            player1: bindings(["X", "Z", "Left Shift", "Return", "Up", "Down", "Left", "Right"]),
+            player2: bindings(["H", "G", "T", "Y", "W", "S", "A", "D"]),
+        }
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl KeyBindings {
+    //keycode -> (player, controller bit), names SDL doesn't know are skipped with a warning
+    pub fn key_map(&self) -> HashMap<Keycode, (usize, u8)> {
+        let mut key_map = HashMap::new();
+        for (player, bindings) in [&self.player1, &self.player2].into_iter().enumerate() {
+            for (name, bit) in bindings.buttons() {
+                match Keycode::from_name(name) {
+                    Some(keycode) => {
+                        key_map.insert(keycode, (player, bit));
+                    }
+                    None => eprintln!("Unknown key \"{}\" in key bindings", name),
+                }
+            }
+        }
+        key_map
+    }
+}
+
+/*
+    The [controller] table, maps SDL game controller buttons to NES buttons.
+    Buttons are stored by their SDL names (a, b, x, y, back, start, dpup...)
+    and NES buttons by the names used for the key bindings' fields, capitalized.
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerMapping {
+    //sdl button name -> nes button name
+    pub buttons: HashMap<String, String>,
+    //how far a thumbstick has to move before it counts as a d-pad press
+    pub deadzone: i16,
+}
+
+impl Default for ControllerMapping {
+    //buttons follow the NES layout, so the bottom face button is B and the right one is A
+    fn default() -> Self {
+        let buttons = [
+            ("a", "B"),
+            ("b", "A"),
+            ("x", "B"),
+            ("y", "A"),
+            ("back", "Select"),
+            ("start", "Start"),
+            ("dpup", "Up"),
+            ("dpdown", "Down"),
+            ("dpleft", "Left"),
+            ("dpright", "Right"),
+        ]
+        .into_iter()
+        .map(|(sdl, nes)| (sdl.to_string(), nes.to_string()))
+        .collect();
+        ControllerMapping {
+            buttons,
+            deadzone: 8000,
+        }
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl ControllerMapping {
+    //controller state bit for a button, 0 if it isn't mapped
+    pub fn button_bit(&self, button: Button) -> u8 {
+        self.buttons
+            .get(&button.string())
+            .map_or(0, |nes_button| nes_button_bit(nes_button))
+    }
+    //d-pad bits for a thumbstick axis, only the left stick is used
+    pub fn axis_bits(&self, axis: Axis, value: i16) -> u8 {
+        let (negative, positive) = match axis {
+            Axis::LeftX => (nes_button_bit("Left"), nes_button_bit("Right")),
+            Axis::LeftY => (nes_button_bit("Up"), nes_button_bit("Down")),
+            _ => return 0,
+        };
+        if value < -self.deadzone {
+            negative
+        } else if value > self.deadzone {
+            positive
+        } else {
+            0
+        }
+    }
+    //bits an axis can set, cleared before applying a new axis value
+    pub fn axis_mask(&self, axis: Axis) -> u8 {
+        self.axis_bits(axis, i16::MIN) | self.axis_bits(axis, i16::MAX)
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn nes_button_bit(name: &str) -> u8 {
+    match name {
+        "A" => 1,
+        "B" => 1 << 1,
+        "Select" => 1 << 2,
+        "Start" => 1 << 3,
+        "Up" => 1 << 4,
+        "Down" => 1 << 5,
+        "Left" => 1 << 6,
+        "Right" => 1 << 7,
+        _ => 0,
+    }
+}
+
+//1.0 is the console's own level
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumeSettings {
+    pub master: f32,
+    pub pulse1: f32,
+    pub pulse2: f32,
+    pub triangle: f32,
+    pub noise: f32,
+    pub dmc: f32,
+}
+
+impl Default for VolumeSettings {
+    fn default() -> Self {
+        VolumeSettings {
+            master: 1.0,
+            pulse1: 1.0,
+            pulse2: 1.0,
+            triangle: 1.0,
+            noise: 1.0,
+            dmc: 1.0,
+        }
+    }
+}
+
+impl VolumeSettings {
+    pub fn channels(&self) -> [(AuChannel, f32); 5] {
+        [
+            (AuChannel::Pulse1, self.pulse1),
+            (AuChannel::Pulse2, self.pulse2),
+            (AuChannel::Triangle, self.triangle),
+            (AuChannel::Noise, self.noise),
+            (AuChannel::Dmc, self.dmc),
+        ]
+    }
+}
+
+//the [audio] table, the device may still pick a different sample rate than the one asked for
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub sample_rate: u32,
+    //samples per device callback, lower means less latency but more risk of underruns
+    pub buffer_size: u16,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            buffer_size: 512,
+        }
+    }
+}
+
+pub const MAX_RECENT_FILES: usize = 10;
+
+//roms that loaded, newest first
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecentFiles {
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+impl RecentFiles {
+    //opening a rom that's already listed moves it back to the front
+    pub fn push(&mut self, file_path: &str) {
+        self.files.retain(|path| path != file_path);
+        self.files.insert(0, file_path.to_string());
+        self.files.truncate(MAX_RECENT_FILES);
+    }
+    pub fn get(&self, idx: usize) -> Option<&str> {
+        self.files.get(idx).map(String::as_str)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    pub key_bindings: KeyBindings,
+    #[serde(default)]
+    pub controller: ControllerMapping,
+    //.pal file replacing the built in colors
+    #[serde(default)]
+    pub palette_path: Option<String>,
+    //keeps the emulator from writing over a frame the ui hasn't shown yet, at the cost of a third frame of memory
+    #[serde(default)]
+    pub triple_buffering: bool,
+    #[serde(default)]
+    pub volume: VolumeSettings,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    //plugs a Zapper into port 2, aimed with the mouse and fired with the left button
+    #[serde(default)]
+    pub zapper: bool,
+    #[serde(default)]
+    pub recent_files: RecentFiles,
+    //blurs color the way a composite TV does, F12 toggles it while running
+    #[serde(default)]
+    pub ntsc_filter: bool,
+    //darkens every other line like a CRT, Shift+F12 toggles it while running
+    #[serde(default)]
+    pub crt_effect: bool,
+}
+
+impl Config {
+    //~/.config/rnes/config.toml, or the XDG config directory if one is set
+    pub fn default_path() -> PathBuf {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_default();
+        config_dir.join("rnes").join("config.toml")
+    }
+    pub fn from_file<PathLike: AsRef<Path>>(file_path: PathLike) -> Result<Self, Box<dyn Error>> {
+        Ok(toml::from_str(&std::fs::read_to_string(file_path)?)?)
+    }
+    pub fn write_to_file<PathLike: AsRef<Path>>(&self, file_path: PathLike) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = file_path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(file_path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+    //writes the defaults out when there's no config yet, a broken config is left alone
+    pub fn load_or_create<PathLike: AsRef<Path>>(file_path: PathLike) -> Self {
+        let file_path = file_path.as_ref();
+        if !file_path.exists() {
+            let config = Config::default();
+            if let Err(err) = config.write_to_file(file_path) {
+                eprintln!("Failed to write default config: {err}");
+            }
+            return config;
+        }
+        Config::from_file(file_path).unwrap_or_else(|err| {
+            eprintln!("Failed to read {}, using defaults: {err}", file_path.display());
+            Config::default()
+        })
+    }
+}
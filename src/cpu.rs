@@ -1,6 +1,8 @@
 use crate::bus::Bus;
+use crate::savestate::CpuState;
+use crate::trace::{TraceBuffer, TraceEntry};
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum AddressMode {
     Accumulator,
     Immediate,
@@ -18,7 +20,7 @@ pub enum AddressMode {
 impl AddressMode {
     pub fn decode(self, cpu: &mut CPU) -> (u16, i32) {
         use AddressMode::*;
-        match self {
+        let result = match self {
             Accumulator => {
                 cpu.pc = cpu.pc.wrapping_add(1);
                 (0, 0)
@@ -115,7 +117,9 @@ impl AddressMode {
                 let penalty = Self::get_crosspage_penalty(cpu.pc, effective);
                 (effective, penalty)
             }
-        }
+        };
+        cpu.operand_addr = result.0;
+        result
     }
     fn get_crosspage_penalty(base: u16, effective: u16) -> i32 {
         if (base & 0xFF00) != (effective & 0xFF00) {
@@ -160,6 +164,37 @@ pub struct CPU {
     pub status: u8,
     pub bus: Bus,
     pub ir_disable: bool,
+    //address of the opcode most recently fetched by execute_instruction
+    pub last_instruction_pc: u16,
+    //cycles run since power on
+    pub cpu_cycle: u64,
+    //an IRQ or BRK has pushed pc and status but not yet read its vector
+    pub irq_vector_pending: bool,
+    //set by a KIL opcode, only a reset gets the cpu going again
+    pub halted: bool,
+    //effective address of the last operand decoded
+    pub operand_addr: u16,
+    //recent instructions for post-mortem debugging, off unless set
+    pub trace: Option<TraceBuffer>,
+    pub breakpoints: Vec<u16>,
+    //halted by a breakpoint rather than a KIL opcode
+    pub at_breakpoint: bool,
+    //lets the instruction under a breakpoint run when resuming from it
+    resuming: bool,
+}
+
+/*
+    Result of CPU::step. If an interrupt or DMA ran instead of an instruction,
+    pc_before, opcode and disasm describe the instruction that is still to run.
+*/
+#[derive(Clone, Debug)]
+pub struct CpuStep {
+    pub cycles: i32,
+    pub pc_before: u16,
+    pub opcode: u8,
+    pub addr: u16,
+    //output of disassemble for the instruction at pc_before
+    pub disasm: String,
 }
 
 impl CPU {
@@ -182,6 +217,15 @@ impl CPU {
             bus: Bus::init(),
             status: 0,
             ir_disable: false,
+            last_instruction_pc: 0,
+            cpu_cycle: 0,
+            irq_vector_pending: false,
+            halted: false,
+            operand_addr: 0,
+            trace: None,
+            breakpoints: Vec::new(),
+            at_breakpoint: false,
+            resuming: false,
         };
         cpu.reset();
 
@@ -194,8 +238,147 @@ impl CPU {
         self.y = 0;
         self.sp = 0xFD;
         self.status = 0x24;
+        self.irq_vector_pending = false;
+        self.halted = false;
+        self.at_breakpoint = false;
+        self.resuming = false;
         self.pc = self.bus.read_word(0xFFFC);
     }
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            sp: self.sp,
+            pc: self.pc,
+            status: self.status,
+            ir_disable: self.ir_disable,
+            cpu_cycle: self.cpu_cycle,
+            irq_vector_pending: self.irq_vector_pending,
+            //a breakpoint isn't part of the game's state
+            halted: self.halted && !self.at_breakpoint,
+        }
+    }
+    pub fn load_state(&mut self, state: CpuState) {
+        self.a = state.a;
+        self.x = state.x;
+        self.y = state.y;
+        self.sp = state.sp;
+        self.pc = state.pc;
+        self.status = state.status;
+        self.ir_disable = state.ir_disable;
+        self.cpu_cycle = state.cpu_cycle;
+        self.irq_vector_pending = state.irq_vector_pending;
+        self.halted = state.halted;
+        self.at_breakpoint = false;
+        self.resuming = false;
+    }
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        if !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+    }
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.retain(|&breakpoint| breakpoint != addr);
+    }
+    //clears a breakpoint halt, the next instruction runs even though it's on the breakpoint
+    pub fn resume(&mut self) {
+        if self.at_breakpoint {
+            self.halted = false;
+            self.at_breakpoint = false;
+            self.resuming = true;
+        }
+    }
+    //sets up the stack like a jsr from just before return_addr, so the routine's rts lands there
+    pub fn call_subroutine(&mut self, addr: u16, return_addr: u16) {
+        self.push_word(return_addr.wrapping_sub(1));
+        self.pc = addr;
+    }
+    pub fn trace_line(&self) -> String {
+        disassemble(self, self.last_instruction_pc).0
+    }
+    //state before executing the instruction at pc, in the format of nestest.log
+    pub fn trace_nestest(&self, cycle: u64, ppu_dot: u32, ppu_scanline: u32) -> String {
+        let opcode = self.bus.read_no_side_effects(self.pc);
+        let (mnemonic, mode) = opcode_info(opcode);
+        let len = 1 + mode.map_or(0, AddressMode::operand_len);
+        let bytes = (0..len as u16)
+            .map(|i| format!("{:02X}", self.bus.read_no_side_effects(self.pc.wrapping_add(i))))
+            .collect::<Vec<_>>()
+            .join(" ");
+        //undocumented opcodes put their '*' in the space before the mnemonic
+        let instruction = format!(
+            "{:04X}  {:<8} {:>4} {}",
+            self.pc,
+            bytes,
+            mnemonic,
+            self.nestest_operand(opcode, mode)
+        );
+        format!(
+            "{:<48}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            instruction.trim_end(),
+            self.a,
+            self.x,
+            self.y,
+            self.status,
+            self.sp,
+            ppu_scanline,
+            ppu_dot,
+            cycle
+        )
+    }
+    //operand along with the effective address and the value it currently holds
+    fn nestest_operand(&self, opcode: u8, mode: Option<AddressMode>) -> String {
+        use AddressMode::*;
+        let bus = &self.bus;
+        let lo = bus.read_no_side_effects(self.pc.wrapping_add(1));
+        let hi = bus.read_no_side_effects(self.pc.wrapping_add(2));
+        let word = u16::from_le_bytes([lo, hi]);
+        //pointers in zero page wrap around without carrying into the high byte
+        let zp_word = |ptr: u8| u16::from_le_bytes([bus.read_no_side_effects(ptr as u16), bus.read_no_side_effects(ptr.wrapping_add(1) as u16)]);
+        match mode {
+            None => String::new(),
+            Some(Accumulator) => "A".to_string(),
+            Some(Immediate) => format!("#${:02X}", lo),
+            Some(ZeroPage) => format!("${:02X} = {:02X}", lo, bus.read_no_side_effects(lo as u16)),
+            Some(ZeroPageX) => {
+                let addr = lo.wrapping_add(self.x);
+                format!("${:02X},X @ {:02X} = {:02X}", lo, addr, bus.read_no_side_effects(addr as u16))
+            }
+            Some(ZeroPageY) => {
+                let addr = lo.wrapping_add(self.y);
+                format!("${:02X},Y @ {:02X} = {:02X}", lo, addr, bus.read_no_side_effects(addr as u16))
+            }
+            //jumps don't read their target
+            Some(Absolute) if opcode == 0x4C || opcode == 0x20 => format!("${:04X}", word),
+            Some(Absolute) => format!("${:04X} = {:02X}", word, bus.read_no_side_effects(word)),
+            Some(AbsoluteX) => {
+                let addr = word.wrapping_add(self.x as u16);
+                format!("${:04X},X @ {:04X} = {:02X}", word, addr, bus.read_no_side_effects(addr))
+            }
+            Some(AbsoluteY) => {
+                let addr = word.wrapping_add(self.y as u16);
+                format!("${:04X},Y @ {:04X} = {:02X}", word, addr, bus.read_no_side_effects(addr))
+            }
+            Some(Indirect) => {
+                //same page wrapping bug as the real cpu
+                let hi_addr = (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF);
+                let target = u16::from_le_bytes([bus.read_no_side_effects(word), bus.read_no_side_effects(hi_addr)]);
+                format!("(${:04X}) = {:04X}", word, target)
+            }
+            Some(IndirectX) => {
+                let ptr = lo.wrapping_add(self.x);
+                let addr = zp_word(ptr);
+                format!("(${:02X},X) @ {:02X} = {:04X} = {:02X}", lo, ptr, addr, bus.read_no_side_effects(addr))
+            }
+            Some(IndirectY) => {
+                let base = zp_word(lo);
+                let addr = base.wrapping_add(self.y as u16);
+                format!("(${:02X}),Y = {:04X} @ {:04X} = {:02X}", lo, base, addr, bus.read_no_side_effects(addr))
+            }
+            Some(Relative) => format!("${:04X}", self.pc.wrapping_add(2).wrapping_add_signed(lo as i8 as i16)),
+        }
+    }
     pub fn set_flag(&mut self, flag: u8, to_set: bool) {
         if to_set {
             self.status |= flag;
@@ -239,13 +422,82 @@ impl CPU {
     }
 
     pub fn execute_instruction(&mut self) -> i32 {
+        //no cycles pass, callers have to check halted rather than wait for cycles to add up
+        //a pending vector fetch finishes the interrupt before it, so pc isn't the next instruction yet
+        if !self.resuming && !self.halted && !self.irq_vector_pending && self.breakpoints.contains(&self.pc) {
+            self.halted = true;
+            self.at_breakpoint = true;
+            return 0;
+        }
+        self.resuming = false;
+        let cycles = self.run_instruction();
+        self.cpu_cycle += cycles as u64;
+        if let Some(page) = self.bus.pending_oam_dma.take() {
+            self.bus.write_oam_dma(page, self.cpu_cycle);
+        }
+        cycles
+    }
+    fn record_trace(&mut self) {
+        let (ppu_scanline, ppu_dot) = self.bus.ppu.position();
+        let entry = TraceEntry {
+            pc: self.pc,
+            opcode: self.bus.read_no_side_effects(self.pc),
+            operand: [self.bus.read_no_side_effects(self.pc.wrapping_add(1)), self.bus.read_no_side_effects(self.pc.wrapping_add(2))],
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.status,
+            sp: self.sp as u8,
+            cycle: self.cpu_cycle,
+            ppu_scanline,
+            ppu_dot,
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.push(entry);
+        }
+    }
+    //traced instructions, oldest first
+    pub fn dump_trace(&self) -> Vec<TraceEntry> {
+        self.trace.as_ref().map_or_else(Vec::new, TraceBuffer::entries)
+    }
+    //executes an instruction and describes it, for debuggers that want to inspect every step
+    pub fn step(&mut self) -> CpuStep {
+        let pc_before = self.pc;
+        let opcode = self.bus.read_no_side_effects(pc_before);
+        let disasm = disassemble(self, pc_before).0;
+        //implied instructions don't decode an operand
+        self.operand_addr = 0;
+        let mut cycles = self.execute_instruction();
+        //BRK and IRQ fetch their vector on the next call, the step isn't over until then
+        while self.irq_vector_pending && !self.halted {
+            cycles += self.execute_instruction();
+        }
+        CpuStep {
+            cycles,
+            pc_before,
+            opcode,
+            addr: self.operand_addr,
+            disasm,
+        }
+    }
+    fn run_instruction(&mut self) -> i32 {
+        //cycles still pass so loops counting them end, Emulator::run stops running frames while halted
+        if self.halted {
+            return 2;
+        }
         //Skip cycles for OAM DMA
         if self.bus.extra_cycles > 0 {
             self.bus.extra_cycles -= 1;
+            if self.bus.extra_cycles == 0 {
+                self.bus.dma_active = false;
+            }
             return 1;
         }
         
         
+        if self.irq_vector_pending {
+            return self.fetch_irq_vector();
+        }
         if self.bus.nmi_request {
             self.bus.nmi_request = false;
             return self.nmi();
@@ -256,6 +508,11 @@ impl CPU {
             return self.irq();
         }
 
+        //only calls that run an opcode are traced, not dma stalls or interrupts
+        if self.trace.is_some() {
+            self.record_trace();
+        }
+        self.last_instruction_pc = self.pc;
         let opcode = self.fetch();
         use AddressMode::*;
         use Register::*;
@@ -527,13 +784,25 @@ impl CPU {
             0x7B => self.rra(AddressMode::AbsoluteY, 7),
             0x63 => self.rra(AddressMode::IndirectX, 8),
             0x73 => self.rra(AddressMode::IndirectY, 8),
-            0x32 => {
-                println!("Illegal Halt!!!!!!");
-                0
+            //kil
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => self.kil(),
+            //anc
+            0x0B | 0x2B => self.anc(AddressMode::Immediate, 2),
+            0x4B => self.alr(AddressMode::Immediate, 2),
+            0x6B => self.arr(AddressMode::Immediate, 2),
+            0x8B => self.xaa(AddressMode::Immediate, 2),
+            0xAB => self.lxa(AddressMode::Immediate, 2),
+            0xCB => self.axs(AddressMode::Immediate, 2),
+            0xBB => self.las(AddressMode::AbsoluteY, 4),
+            //unstable stores, they AND the value with the high byte of the address + 1
+            0x9B => {
+                self.sp = (self.a & self.x) as u16;
+                self.unstable_store(AddressMode::AbsoluteY, self.a & self.x, 5)
             }
-            0x0B=>self.aac(),
-            0x2B=>self.aac(),
-            _ => unreachable!("Undocumented opcode reached: 0x{opcode:02X}"),
+            0x9C => self.unstable_store(AddressMode::AbsoluteX, self.y, 5),
+            0x9E => self.unstable_store(AddressMode::AbsoluteY, self.x, 5),
+            0x9F => self.unstable_store(AddressMode::AbsoluteY, self.a & self.x, 5),
+            0x93 => self.unstable_store(AddressMode::IndirectY, self.a & self.x, 6),
         }
     }
 
@@ -644,9 +913,9 @@ impl CPU {
         self.set_flag(Self::FLAG_B, false);
         self.set_flag(Self::FLAG_I, true);
 
-        self.pc = self.bus.read_word(0xFFFE);
-
-        7
+        //BRK shares the IRQ vector and its last 2 cycles, see fetch_irq_vector
+        self.irq_vector_pending = true;
+        5
     }
     fn clf(&mut self, flag: u8) -> i32 {
         self.bus.read(self.pc);
@@ -1253,12 +1522,30 @@ impl CPU {
 
             self.set_flag(Self::FLAG_I, true);
 
-            self.pc = self.bus.read_word(0xFFFE);
-
-            return 7;
+            //the vector is fetched on the next call, after the ppu has caught up with the pushes
+            self.irq_vector_pending = true;
+            return 5;
         }
         0
     }
+    /*
+        Last 2 cycles of an IRQ or BRK. An NMI that came in while pushing
+        hijacks it, the NMI vector is used instead and the NMI is not taken again.
+        An IRQ that came in during a BRK is absorbed by it, the handler runs once
+        with B set in the pushed status and I set masks the IRQ.
+    */
+    fn fetch_irq_vector(&mut self) -> i32 {
+        self.irq_vector_pending = false;
+        self.bus.irq = false;
+        let vector = if self.bus.nmi_request {
+            self.bus.nmi_request = false;
+            0xFFFA
+        } else {
+            0xFFFE
+        };
+        self.pc = self.bus.read_word(vector);
+        2
+    }
     fn nmi(&mut self) -> i32 {
         //dummy-read
         let _ = self.bus.read(self.sp + 0x100);
@@ -1273,14 +1560,371 @@ impl CPU {
 
         7
     }
-    fn aac(&mut self)->i32 {
-        
-        self.a &= if self.get_flag(Self::FLAG_C) {0x01} else {0};
-        self.set_flag(Self::FLAG_C,(self.a & 0x01) != 0);
-
-        self.set_zn(self.a);
-
+    //locks up the cpu until the next reset
+    fn kil(&mut self) -> i32 {
+        self.halted = true;
         2
     }
+    fn anc(&mut self, address_mode: AddressMode, cycles: i32) -> i32 {
+        let (addr, _) = address_mode.decode(self);
+        self.a &= self.bus.read(addr);
+        self.set_zn(self.a);
+        self.set_flag(Self::FLAG_C, (self.a & 0x80) != 0);
+        cycles
+    }
+    fn alr(&mut self, address_mode: AddressMode, cycles: i32) -> i32 {
+        let (addr, _) = address_mode.decode(self);
+        self.a &= self.bus.read(addr);
+        self.set_flag(Self::FLAG_C, (self.a & 0x01) != 0);
+        self.a >>= 1;
+        self.set_zn(self.a);
+        cycles
+    }
+    fn arr(&mut self, address_mode: AddressMode, cycles: i32) -> i32 {
+        let (addr, _) = address_mode.decode(self);
+        let carry_in = if self.get_flag(Self::FLAG_C) { 0x80 } else { 0 };
+        self.a = ((self.a & self.bus.read(addr)) >> 1) | carry_in;
+        self.set_zn(self.a);
+        //carry and overflow come from bits 6 and 5 of the result rather than the shift
+        let bit6 = (self.a & 0x40) != 0;
+        let bit5 = (self.a & 0x20) != 0;
+        self.set_flag(Self::FLAG_C, bit6);
+        self.set_flag(Self::FLAG_V, bit6 != bit5);
+        cycles
+    }
+    /*
+        XAA and LXA mix A with a value that depends on the chip and temperature,
+        $EE is what most test suites expect.
+    */
+    fn xaa(&mut self, address_mode: AddressMode, cycles: i32) -> i32 {
+        let (addr, _) = address_mode.decode(self);
+        self.a = (self.a | 0xEE) & self.x & self.bus.read(addr);
+        self.set_zn(self.a);
+        cycles
+    }
+    fn lxa(&mut self, address_mode: AddressMode, cycles: i32) -> i32 {
+        let (addr, _) = address_mode.decode(self);
+        self.a = (self.a | 0xEE) & self.bus.read(addr);
+        self.x = self.a;
+        self.set_zn(self.a);
+        cycles
+    }
+    fn axs(&mut self, address_mode: AddressMode, cycles: i32) -> i32 {
+        let (addr, _) = address_mode.decode(self);
+        let value = self.bus.read(addr);
+        let and = self.a & self.x;
+        self.set_flag(Self::FLAG_C, and >= value);
+        self.x = and.wrapping_sub(value);
+        self.set_zn(self.x);
+        cycles
+    }
+    fn las(&mut self, address_mode: AddressMode, cycles: i32) -> i32 {
+        let (addr, extra) = address_mode.decode(self);
+        let value = self.bus.read(addr) & self.sp as u8;
+        self.a = value;
+        self.x = value;
+        self.sp = value as u16;
+        self.set_zn(value);
+        cycles + extra
+    }
+    fn unstable_store(&mut self, address_mode: AddressMode, value: u8, cycles: i32) -> i32 {
+        let index = if address_mode == AddressMode::AbsoluteX { self.x } else { self.y };
+        let (addr, extra) = address_mode.decode(self);
+        if extra == 0 {
+            //dummy-read
+            let _ = self.bus.read(addr);
+        }
+        let base_high = (addr.wrapping_sub(index as u16) >> 8) as u8;
+        let result = value & base_high.wrapping_add(1);
+        //crossing a page also puts the result on the high byte of the address
+        let addr = if extra != 0 { ((result as u16) << 8) | (addr & 0xFF) } else { addr };
+        self.bus.write(addr, result);
+        cycles
+    }
     
 }
+
+//mnemonic and addressing mode for an opcode, None for implied instructions.
+//undocumented opcodes are marked with a '*' the same way nestest.log does
+pub fn opcode_info(opcode: u8) -> (&'static str, Option<AddressMode>) {
+    use AddressMode::*;
+    match opcode {
+        0x69 => ("ADC", Some(Immediate)),
+        0x65 => ("ADC", Some(ZeroPage)),
+        0x75 => ("ADC", Some(ZeroPageX)),
+        0x6D => ("ADC", Some(Absolute)),
+        0x7D => ("ADC", Some(AbsoluteX)),
+        0x79 => ("ADC", Some(AbsoluteY)),
+        0x61 => ("ADC", Some(IndirectX)),
+        0x71 => ("ADC", Some(IndirectY)),
+        0x29 => ("AND", Some(Immediate)),
+        0x25 => ("AND", Some(ZeroPage)),
+        0x35 => ("AND", Some(ZeroPageX)),
+        0x2D => ("AND", Some(Absolute)),
+        0x3D => ("AND", Some(AbsoluteX)),
+        0x39 => ("AND", Some(AbsoluteY)),
+        0x21 => ("AND", Some(IndirectX)),
+        0x31 => ("AND", Some(IndirectY)),
+        0x0A => ("ASL", Some(Accumulator)),
+        0x06 => ("ASL", Some(ZeroPage)),
+        0x16 => ("ASL", Some(ZeroPageX)),
+        0x0E => ("ASL", Some(Absolute)),
+        0x1E => ("ASL", Some(AbsoluteX)),
+        0x90 => ("BCC", Some(Relative)),
+        0xB0 => ("BCS", Some(Relative)),
+        0xF0 => ("BEQ", Some(Relative)),
+        0x30 => ("BMI", Some(Relative)),
+        0xD0 => ("BNE", Some(Relative)),
+        0x10 => ("BPL", Some(Relative)),
+        0x70 => ("BVS", Some(Relative)),
+        0x50 => ("BVC", Some(Relative)),
+        0x24 => ("BIT", Some(ZeroPage)),
+        0x2C => ("BIT", Some(Absolute)),
+        0x00 => ("BRK", None),
+        0x18 => ("CLC", None),
+        0xD8 => ("CLD", None),
+        0x58 => ("CLI", None),
+        0xB8 => ("CLV", None),
+        0xC9 => ("CMP", Some(Immediate)),
+        0xC5 => ("CMP", Some(ZeroPage)),
+        0xD5 => ("CMP", Some(ZeroPageX)),
+        0xCD => ("CMP", Some(Absolute)),
+        0xDD => ("CMP", Some(AbsoluteX)),
+        0xD9 => ("CMP", Some(AbsoluteY)),
+        0xC1 => ("CMP", Some(IndirectX)),
+        0xD1 => ("CMP", Some(IndirectY)),
+        0xE0 => ("CPX", Some(Immediate)),
+        0xE4 => ("CPX", Some(ZeroPage)),
+        0xEC => ("CPX", Some(Absolute)),
+        0xC0 => ("CPY", Some(Immediate)),
+        0xC4 => ("CPY", Some(ZeroPage)),
+        0xCC => ("CPY", Some(Absolute)),
+        0xC6 => ("DEC", Some(ZeroPage)),
+        0xD6 => ("DEC", Some(ZeroPageX)),
+        0xCE => ("DEC", Some(Absolute)),
+        0xDE => ("DEC", Some(AbsoluteX)),
+        0xCA => ("DEX", None),
+        0x88 => ("DEY", None),
+        0xE6 => ("INC", Some(ZeroPage)),
+        0xF6 => ("INC", Some(ZeroPageX)),
+        0xEE => ("INC", Some(Absolute)),
+        0xFE => ("INC", Some(AbsoluteX)),
+        0xE8 => ("INX", None),
+        0xC8 => ("INY", None),
+        0x49 => ("EOR", Some(Immediate)),
+        0x45 => ("EOR", Some(ZeroPage)),
+        0x55 => ("EOR", Some(ZeroPageX)),
+        0x4D => ("EOR", Some(Absolute)),
+        0x5D => ("EOR", Some(AbsoluteX)),
+        0x59 => ("EOR", Some(AbsoluteY)),
+        0x41 => ("EOR", Some(IndirectX)),
+        0x51 => ("EOR", Some(IndirectY)),
+        0x4C => ("JMP", Some(Absolute)),
+        0x6C => ("JMP", Some(Indirect)),
+        0x20 => ("JSR", Some(Absolute)),
+        0xA9 => ("LDA", Some(Immediate)),
+        0xA5 => ("LDA", Some(ZeroPage)),
+        0xB5 => ("LDA", Some(ZeroPageX)),
+        0xAD => ("LDA", Some(Absolute)),
+        0xBD => ("LDA", Some(AbsoluteX)),
+        0xB9 => ("LDA", Some(AbsoluteY)),
+        0xA1 => ("LDA", Some(IndirectX)),
+        0xB1 => ("LDA", Some(IndirectY)),
+        0xA2 => ("LDX", Some(Immediate)),
+        0xA6 => ("LDX", Some(ZeroPage)),
+        0xB6 => ("LDX", Some(ZeroPageY)),
+        0xAE => ("LDX", Some(Absolute)),
+        0xBE => ("LDX", Some(AbsoluteY)),
+        0xA0 => ("LDY", Some(Immediate)),
+        0xA4 => ("LDY", Some(ZeroPage)),
+        0xB4 => ("LDY", Some(ZeroPageX)),
+        0xAC => ("LDY", Some(Absolute)),
+        0xBC => ("LDY", Some(AbsoluteX)),
+        0xEA => ("NOP", None),
+        0x4A => ("LSR", Some(Accumulator)),
+        0x46 => ("LSR", Some(ZeroPage)),
+        0x56 => ("LSR", Some(ZeroPageX)),
+        0x4E => ("LSR", Some(Absolute)),
+        0x5E => ("LSR", Some(AbsoluteX)),
+        0x09 => ("ORA", Some(Immediate)),
+        0x05 => ("ORA", Some(ZeroPage)),
+        0x15 => ("ORA", Some(ZeroPageX)),
+        0x0D => ("ORA", Some(Absolute)),
+        0x1D => ("ORA", Some(AbsoluteX)),
+        0x19 => ("ORA", Some(AbsoluteY)),
+        0x01 => ("ORA", Some(IndirectX)),
+        0x11 => ("ORA", Some(IndirectY)),
+        0x48 => ("PHA", None),
+        0x08 => ("PHP", None),
+        0x68 => ("PLA", None),
+        0x28 => ("PLP", None),
+        0x2A => ("ROL", Some(Accumulator)),
+        0x26 => ("ROL", Some(ZeroPage)),
+        0x36 => ("ROL", Some(ZeroPageX)),
+        0x2E => ("ROL", Some(Absolute)),
+        0x3E => ("ROL", Some(AbsoluteX)),
+        0x6A => ("ROR", Some(Accumulator)),
+        0x66 => ("ROR", Some(ZeroPage)),
+        0x76 => ("ROR", Some(ZeroPageX)),
+        0x6E => ("ROR", Some(Absolute)),
+        0x7E => ("ROR", Some(AbsoluteX)),
+        0x40 => ("RTI", None),
+        0x60 => ("RTS", None),
+        0xE9 => ("SBC", Some(Immediate)),
+        0xE5 => ("SBC", Some(ZeroPage)),
+        0xF5 => ("SBC", Some(ZeroPageX)),
+        0xED => ("SBC", Some(Absolute)),
+        0xFD => ("SBC", Some(AbsoluteX)),
+        0xF9 => ("SBC", Some(AbsoluteY)),
+        0xE1 => ("SBC", Some(IndirectX)),
+        0xF1 => ("SBC", Some(IndirectY)),
+        0x38 => ("SEC", None),
+        0xF8 => ("SED", None),
+        0x78 => ("SEI", None),
+        0x85 => ("STA", Some(ZeroPage)),
+        0x95 => ("STA", Some(ZeroPageX)),
+        0x8D => ("STA", Some(Absolute)),
+        0x9D => ("STA", Some(AbsoluteX)),
+        0x99 => ("STA", Some(AbsoluteY)),
+        0x81 => ("STA", Some(IndirectX)),
+        0x91 => ("STA", Some(IndirectY)),
+        0x86 => ("STX", Some(ZeroPage)),
+        0x96 => ("STX", Some(ZeroPageY)),
+        0x8E => ("STX", Some(Absolute)),
+        0x84 => ("STY", Some(ZeroPage)),
+        0x94 => ("STY", Some(ZeroPageX)),
+        0x8C => ("STY", Some(Absolute)),
+        0xAA => ("TAX", None),
+        0xA8 => ("TAY", None),
+        0x8A => ("TXA", None),
+        0x98 => ("TYA", None),
+        0xBA => ("TSX", None),
+        0x9A => ("TXS", None),
+        //undocumented
+        0xA7 => ("*LAX", Some(ZeroPage)),
+        0xB7 => ("*LAX", Some(ZeroPageY)),
+        0xAF => ("*LAX", Some(Absolute)),
+        0xBF => ("*LAX", Some(AbsoluteY)),
+        0xA3 => ("*LAX", Some(IndirectX)),
+        0xB3 => ("*LAX", Some(IndirectY)),
+        0x87 => ("*SAX", Some(ZeroPage)),
+        0x97 => ("*SAX", Some(ZeroPageY)),
+        0x8F => ("*SAX", Some(Absolute)),
+        0x83 => ("*SAX", Some(IndirectX)),
+        0x04 | 0x44 | 0x64 => ("*NOP", Some(ZeroPage)),
+        0x0C => ("*NOP", Some(Absolute)),
+        0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => ("*NOP", Some(ZeroPageX)),
+        0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => ("*NOP", Some(AbsoluteX)),
+        0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => ("*NOP", None),
+        0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => ("*NOP", Some(Immediate)),
+        0xEB => ("*SBC", Some(Immediate)),
+        0xC7 => ("*DCP", Some(ZeroPage)),
+        0xD7 => ("*DCP", Some(ZeroPageX)),
+        0xCF => ("*DCP", Some(Absolute)),
+        0xDF => ("*DCP", Some(AbsoluteX)),
+        0xDB => ("*DCP", Some(AbsoluteY)),
+        0xC3 => ("*DCP", Some(IndirectX)),
+        0xD3 => ("*DCP", Some(IndirectY)),
+        0xE7 => ("*ISB", Some(ZeroPage)),
+        0xF7 => ("*ISB", Some(ZeroPageX)),
+        0xEF => ("*ISB", Some(Absolute)),
+        0xFF => ("*ISB", Some(AbsoluteX)),
+        0xFB => ("*ISB", Some(AbsoluteY)),
+        0xE3 => ("*ISB", Some(IndirectX)),
+        0xF3 => ("*ISB", Some(IndirectY)),
+        0x07 => ("*SLO", Some(ZeroPage)),
+        0x17 => ("*SLO", Some(ZeroPageX)),
+        0x0F => ("*SLO", Some(Absolute)),
+        0x1F => ("*SLO", Some(AbsoluteX)),
+        0x1B => ("*SLO", Some(AbsoluteY)),
+        0x03 => ("*SLO", Some(IndirectX)),
+        0x13 => ("*SLO", Some(IndirectY)),
+        0x27 => ("*RLA", Some(ZeroPage)),
+        0x37 => ("*RLA", Some(ZeroPageX)),
+        0x2F => ("*RLA", Some(Absolute)),
+        0x3F => ("*RLA", Some(AbsoluteX)),
+        0x3B => ("*RLA", Some(AbsoluteY)),
+        0x23 => ("*RLA", Some(IndirectX)),
+        0x33 => ("*RLA", Some(IndirectY)),
+        0x47 => ("*SRE", Some(ZeroPage)),
+        0x57 => ("*SRE", Some(ZeroPageX)),
+        0x4F => ("*SRE", Some(Absolute)),
+        0x5F => ("*SRE", Some(AbsoluteX)),
+        0x5B => ("*SRE", Some(AbsoluteY)),
+        0x43 => ("*SRE", Some(IndirectX)),
+        0x53 => ("*SRE", Some(IndirectY)),
+        0x67 => ("*RRA", Some(ZeroPage)),
+        0x77 => ("*RRA", Some(ZeroPageX)),
+        0x6F => ("*RRA", Some(Absolute)),
+        0x7F => ("*RRA", Some(AbsoluteX)),
+        0x7B => ("*RRA", Some(AbsoluteY)),
+        0x63 => ("*RRA", Some(IndirectX)),
+        0x73 => ("*RRA", Some(IndirectY)),
+        0x0B | 0x2B => ("*ANC", Some(Immediate)),
+        0x4B => ("*ALR", Some(Immediate)),
+        0x6B => ("*ARR", Some(Immediate)),
+        0x8B => ("*XAA", Some(Immediate)),
+        0xAB => ("*LXA", Some(Immediate)),
+        0xCB => ("*AXS", Some(Immediate)),
+        0xBB => ("*LAS", Some(AbsoluteY)),
+        0x9B => ("*TAS", Some(AbsoluteY)),
+        0x9C => ("*SHY", Some(AbsoluteX)),
+        0x9E => ("*SHX", Some(AbsoluteY)),
+        0x9F => ("*AHX", Some(AbsoluteY)),
+        0x93 => ("*AHX", Some(IndirectY)),
+        0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => ("*KIL", None),
+    }
+}
+
+impl AddressMode {
+    //bytes following the opcode
+    pub fn operand_len(self) -> u8 {
+        use AddressMode::*;
+        match self {
+            Accumulator => 0,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndirectX | IndirectY | Relative => 1,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 2,
+        }
+    }
+}
+
+//decodes the instruction at addr without side effects, returning the text and its length in bytes
+pub fn disassemble(cpu: &CPU, addr: u16) -> (String, u8) {
+    let opcode = cpu.bus.read_no_side_effects(addr);
+    let (mnemonic, mode) = opcode_info(opcode);
+    let len = 1 + mode.map_or(0, AddressMode::operand_len);
+    let lo = cpu.bus.read_no_side_effects(addr.wrapping_add(1));
+    let hi = cpu.bus.read_no_side_effects(addr.wrapping_add(2));
+
+    let operand = format_operand(mode, addr, lo, hi);
+    let bytes = [opcode, lo, hi][..len as usize]
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let text = format!("${:04X}  {:<8}  {} {}", addr, bytes, mnemonic, operand);
+    (text.trim_end().to_string(), len)
+}
+
+//operand in assembler syntax, addr is where the instruction starts
+pub fn format_operand(mode: Option<AddressMode>, addr: u16, lo: u8, hi: u8) -> String {
+    use AddressMode::*;
+    let word = u16::from_le_bytes([lo, hi]);
+    match mode {
+        None => String::new(),
+        Some(Accumulator) => "A".to_string(),
+        Some(Immediate) => format!("#${:02X}", lo),
+        Some(ZeroPage) => format!("${:02X}", lo),
+        Some(ZeroPageX) => format!("${:02X},X", lo),
+        Some(ZeroPageY) => format!("${:02X},Y", lo),
+        Some(Absolute) => format!("${:04X}", word),
+        Some(AbsoluteX) => format!("${:04X},X", word),
+        Some(AbsoluteY) => format!("${:04X},Y", word),
+        Some(Indirect) => format!("(${:04X})", word),
+        Some(IndirectX) => format!("(${:02X},X)", lo),
+        Some(IndirectY) => format!("(${:02X}),Y", lo),
+        //branches show their target rather than the raw offset
+        Some(Relative) => format!("${:04X}", addr.wrapping_add(2).wrapping_add_signed(lo as i8 as i16)),
+    }
+}
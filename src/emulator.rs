@@ -1,56 +1,446 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use crossbeam_channel::Receiver;
+use crossbeam_channel::{Receiver, Sender};
 
 
 
 use crate::{
-    cartridge::{Cartridge, Mapper},
+    bus::Watchpoint,
+    cartridge::{Cartridge, CartridgeError, Mapper, Region},
+    config::RecentFiles,
     cpu::CPU,
-    ui::frame_buffer::DoubleBuffer,
+    input::{FrameInput, InputPlayback, InputRecording},
+    ntsc_filter::NtscFilter,
+    pattern_viewer::PatternTableViewer,
+    rewind::{DEFAULT_REWIND_CAPACITY, RewindBuffer},
+    savestate::{self, SaveState},
+    trace::TraceBuffer,
+    frame_buffer::SharedFramebuffer,
 };
 
-use crate::ui::UiEvent;
+use crate::event::UiEvent;
 
 pub struct Emulator {
     cpu: CPU,
     cartridge_loaded: bool,
+    paused: bool,
+    throttled: bool,
+    region: Region,
+    rom_path: Option<PathBuf>,
+    rom_sha1: String,
+    recording: Option<InputRecording>,
+    playback: Option<InputPlayback>,
     event_receive: Receiver<UiEvent>,
     fps_counter: u32,
     fps_multiplier: f64,
-    framebuffer: Arc<DoubleBuffer>,
+    //frames emulated since one was last handed to the ui
+    frames_since_present: u32,
+    framebuffer: Arc<SharedFramebuffer>,
+    audio_send: Sender<f32>,
+    //events going the other way, for the ui to show
+    ui_send: Option<Sender<UiEvent>>,
+    //rendered after every presented frame while the ui shows it
+    pattern_viewer: Option<PatternTableViewer>,
+    sprite_viewer: bool,
+    palette_viewer: bool,
+    //first address of the page shown by the memory viewer
+    memory_viewer_base: Option<u16>,
+    rewind_buffer: RewindBuffer,
+    //kept in step with the ui's copy, which is the one written to the config
+    recent_files: RecentFiles,
+    //applied to frames on their way to the ui, the ppu's own frame stays plain RGB
+    ntsc_filter: Option<NtscFilter>,
+    //save slot last written or loaded, 0 until one is
+    current_slot: u8,
 }
 
 impl Emulator {
-    pub fn new(event_receive: Receiver<UiEvent>, framebuffer: Arc<DoubleBuffer>) -> Self {
+    pub fn new(
+        event_receive: Receiver<UiEvent>,
+        framebuffer: Arc<SharedFramebuffer>,
+        audio_send: Sender<f32>,
+        sample_rate: u32,
+    ) -> Self {
+        let mut cpu = CPU::init();
+        cpu.bus.apu.set_sample_rate(sample_rate);
         Emulator {
-            cpu: CPU::init(),
+            cpu,
             cartridge_loaded: false,
+            paused: false,
+            throttled: true,
+            region: Region::Ntsc,
+            rom_path: None,
+            rom_sha1: String::new(),
+            recording: None,
+            playback: None,
 
             event_receive,
             fps_counter: 0,
             fps_multiplier: 1.0,
+            frames_since_present: 0,
             framebuffer,
+            audio_send,
+            ui_send: None,
+            pattern_viewer: None,
+            sprite_viewer: false,
+            palette_viewer: false,
+            memory_viewer_base: None,
+            rewind_buffer: RewindBuffer::new(DEFAULT_REWIND_CAPACITY),
+            recent_files: RecentFiles::default(),
+            ntsc_filter: None,
+            current_slot: 0,
         }
     }
-    pub fn load_cartridge(&mut self, file_path: String) {
-        if let Ok(cartridge) = Cartridge::from_file(file_path) {
-            let mapper = Mapper::with_cart(cartridge);
-            self.cpu.bus.load_cartridge(mapper);
-            self.cpu.reset();
-            self.cartridge_loaded = true;
+    //logs why the rom couldn't be loaded, the error is passed on for callers that need to act on it
+    pub fn load_cartridge(&mut self, file_path: String) -> Result<(), CartridgeError> {
+        let cartridge = match Cartridge::from_file(&file_path) {
+            Ok(cartridge) => cartridge,
+            Err(err) => {
+                match &err {
+                    CartridgeError::IoError(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => {
+                        eprintln!("Rom {file_path} doesn't exist")
+                    }
+                    CartridgeError::UnsupportedMapper(mapper_id) => {
+                        eprintln!("Can't run {file_path}, it uses mapper {mapper_id} which isn't emulated yet")
+                    }
+                    CartridgeError::InsufficientData { .. } | CartridgeError::TrainerParseError => {
+                        eprintln!("Rom {file_path} is cut short, it may be a bad dump: {err}")
+                    }
+                    _ => eprintln!("Failed to load rom {file_path}: {err}"),
+                }
+                return Err(err);
+            }
+        };
+        //keep the outgoing game's save before it gets replaced
+        self.save_cartridge();
+        self.stop_recording();
+        self.playback = None;
+        self.rom_sha1 = cartridge.sha1_hex();
+        let sha1 = cartridge.sha1;
+        let region = cartridge.region;
+        let mapper = Mapper::with_cart(cartridge);
+        self.cpu.bus.load_cartridge(mapper);
+        self.cpu.reset();
+        self.cartridge_loaded = true;
+        let rom_path = PathBuf::from(&file_path);
+        self.recent_files.push(&file_path);
+        if let Some(ui_send) = &self.ui_send {
+            let _ = ui_send.send(UiEvent::CartridgeLoaded(file_path, sha1));
+        }
+        self.rom_path = Some(rom_path);
+        self.rewind_buffer.clear();
+        self.set_region(region);
+        Ok(())
+    }
+    //pressing the reset button, the game finds ram the way it left it
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+    }
+    pub fn power_cycle(&mut self) {
+        self.cpu.bus.reset();
+        self.cpu.reset();
+    }
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.cpu.bus.ppu.set_region(region);
+    }
+    //dots for the cycles just run, worked out from the absolute cycle count so PAL's fractions don't drift
+    fn ppu_dots(&self, cycles: i32) -> i32 {
+        let total_cycles = self.cpu.cpu_cycle;
+        let start = total_cycles.saturating_sub(cycles as u64);
+        (self.region.ppu_dots(total_cycles) - self.region.ppu_dots(start)) as i32
+    }
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+    //without throttling frames run as fast as possible, for headless runs
+    pub fn set_throttled(&mut self, throttled: bool) {
+        self.throttled = throttled;
+    }
+    pub fn set_recent_files(&mut self, recent_files: RecentFiles) {
+        self.recent_files = recent_files;
+    }
+    pub fn set_ui_sender(&mut self, ui_send: Sender<UiEvent>) {
+        self.ui_send = Some(ui_send);
+    }
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.cpu.bus.watchpoints.push(watchpoint);
+    }
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.cpu.add_breakpoint(addr);
+    }
+    //one instruction along with the ppu and apu time it takes
+    fn run_instruction(&mut self) -> i32 {
+        let cycles = self.cpu.execute_instruction();
+        self.report_watchpoint_hit();
+        let dots = self.ppu_dots(cycles);
+        self.cpu.bus.tick_ppu(dots);
+        self.cpu.bus.tick_apu(cycles, self.cpu.cpu_cycle);
+        cycles
+    }
+    //one frame's worth of cycles, cut short if a breakpoint is hit
+    pub fn run_frame(&mut self) {
+        let mut cycles = 0;
+        while cycles < self.region.frame_cycles() {
+            let new_cycles = self.run_instruction();
+            if self.cpu.at_breakpoint {
+                self.report_breakpoint();
+                break;
+            }
+            cycles += new_cycles;
+        }
+    }
+    //runs the instruction under the breakpoint and stops again on the next one
+    fn step_instruction(&mut self) {
+        self.cpu.resume();
+        self.run_instruction();
+        self.cpu.halted = true;
+        self.cpu.at_breakpoint = true;
+        self.report_breakpoint();
+    }
+    fn report_breakpoint(&self) {
+        let (scanline, dot) = self.cpu.bus.ppu.position();
+        let state = self.cpu.trace_nestest(self.cpu.cpu_cycle, dot, scanline);
+        match &self.ui_send {
+            Some(ui_send) => {
+                let _ = ui_send.send(UiEvent::BreakpointHit(state));
+            }
+            None => println!("Breakpoint hit: {state}"),
+        }
+    }
+    fn send_debug_views(&self) {
+        let Some(ui_send) = &self.ui_send else {
+            return;
+        };
+        let (ppu, mapper) = (&self.cpu.bus.ppu, self.cpu.bus.mapper());
+        if let Some(viewer) = &self.pattern_viewer {
+            let _ = ui_send.send(UiEvent::PatternTables(viewer.render(ppu, mapper)));
+        }
+        if self.sprite_viewer {
+            let _ = ui_send.send(UiEvent::OamSprites(ppu.render_oam_sprites(mapper)));
+        }
+        if self.palette_viewer {
+            let _ = ui_send.send(UiEvent::Palettes(ppu.render_palettes(), ppu.palette_entries()));
+        }
+        if let Some(base_addr) = self.memory_viewer_base {
+            let page = Box::new(std::array::from_fn(|i| self.cpu.bus.peek_readable(base_addr.wrapping_add(i as u16))));
+            let _ = ui_send.send(UiEvent::MemoryPage(base_addr, page));
+        }
+    }
+    fn report_watchpoint_hit(&mut self) {
+        let Some(hit) = self.cpu.bus.watchpoint_hit.get_mut().take() else {
+            return;
+        };
+        match &self.ui_send {
+            Some(ui_send) => {
+                let _ = ui_send.send(UiEvent::WatchpointHit(hit.addr, hit.val));
+            }
+            //headless runs have no ui to tell
+            None => println!("Watchpoint hit at ${:04X} = ${:02X}", hit.addr, hit.val),
+        }
+    }
+    //for peripherals that give feedback to the player, headless runs have nothing to play it on
+    pub fn rumble(&self, duration_ms: u32, strength: f32) {
+        if let Some(ui_send) = &self.ui_send {
+            let _ = ui_send.send(UiEvent::ControllerRumble { duration_ms, strength });
+        }
+    }
+    //None turns tracing off
+    pub fn set_trace_capacity(&mut self, capacity: Option<usize>) {
+        self.cpu.trace = capacity.map(TraceBuffer::new);
+    }
+    pub fn write_trace(&self, file_path: &str) {
+        if self.cpu.trace.is_none() {
+            eprintln!("Tracing is off, run with --trace to record instructions");
+            return;
         }
+        let mut log = String::new();
+        for entry in self.cpu.dump_trace() {
+            log.push_str(&entry.nestest_line());
+            log.push('\n');
+        }
+        if let Err(err) = std::fs::write(file_path, log) {
+            eprintln!("Failed to write trace: {err}");
+        }
+    }
+    pub fn set_rewind_capacity(&mut self, capacity: usize) {
+        self.rewind_buffer.set_capacity(capacity);
+    }
+    pub fn save_state(&self) -> Result<SaveState, Box<dyn Error>> {
+        SaveState::capture(&self.cpu)
+    }
+    pub fn load_state(&mut self, state: SaveState) -> bool {
+        let restored = state.restore(&mut self.cpu);
+        if !restored {
+            eprintln!("Save state doesn't match the loaded cartridge");
+        }
+        restored
     }
-    pub fn run(&mut self) {
-        let target_fps = 60.0 * self.fps_multiplier;
-        let frame_time = std::time::Duration::from_secs_f64(1.0 / target_fps);
+    fn write_save_state(&self) {
+        let Some(rom_path) = &self.rom_path else {
+            return;
+        };
+        if let Err(err) = self.save_state().and_then(|state| state.write_to_file(savestate::state_path(rom_path))) {
+            eprintln!("Failed to write save state: {err}");
+        }
+    }
+    fn write_slot(&mut self, slot: u8) {
+        let Some(rom_path) = &self.rom_path else {
+            return;
+        };
+        let state = match self.save_state() {
+            Ok(state) => state,
+            Err(err) => {
+                eprintln!("Failed to capture save slot {slot}: {err}");
+                return;
+            }
+        };
+        if let Err(err) = state.write_to_file(savestate::slot_path(rom_path, &self.rom_sha1, slot)) {
+            eprintln!("Failed to write save slot {slot}: {err}");
+            return;
+        }
+        self.current_slot = slot;
+        self.notify_slot_used(slot, true, state.saved_at);
+    }
+    fn load_slot(&mut self, slot: u8) {
+        let Some(rom_path) = &self.rom_path else {
+            return;
+        };
+        let state = match SaveState::read_from_file(savestate::slot_path(rom_path, &self.rom_sha1, slot)) {
+            Ok(state) => state,
+            Err(err) => {
+                eprintln!("Failed to read save slot {slot}: {err}");
+                return;
+            }
+        };
+        let saved_at = state.saved_at;
+        self.stop_playback();
+        if self.load_state(state) {
+            self.current_slot = slot;
+            self.notify_slot_used(slot, false, saved_at);
+        }
+    }
+    pub fn current_slot(&self) -> u8 {
+        self.current_slot
+    }
+    fn notify_slot_used(&self, slot: u8, saved: bool, saved_at: u64) {
+        if let Some(ui_send) = &self.ui_send {
+            let _ = ui_send.send(UiEvent::SlotUsed { slot, saved, saved_at });
+        }
+    }
+    pub fn start_recording(&mut self, file_path: String) {
+        self.stop_recording();
+        let Some(rom_path) = self.rom_path.clone().filter(|_| self.cartridge_loaded) else {
+            self.notify_recording(false);
+            return;
+        };
+        //movies play back from power on, so recording has to start from there too
+        if self.load_cartridge(rom_path.to_string_lossy().into_owned()).is_err() {
+            eprintln!("Failed to reload rom for recording");
+            self.notify_recording(false);
+            return;
+        }
+        let rom_name = rom_path
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match InputRecording::create(&file_path, &rom_name, &self.rom_sha1) {
+            Ok(recording) => self.recording = Some(recording),
+            Err(err) => eprintln!("Failed to start recording: {err}"),
+        }
+        self.notify_recording(self.recording.is_some());
+    }
+    pub fn stop_recording(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+        if let Err(err) = recording.finish() {
+            eprintln!("Failed to finish recording: {err}");
+        }
+        self.notify_recording(false);
+    }
+    fn notify_recording(&self, recording: bool) {
+        if let Some(ui_send) = &self.ui_send {
+            let _ = ui_send.send(UiEvent::RecordingChanged(recording));
+        }
+    }
+    pub fn start_playback(&mut self, file_path: String) {
+        let Some(rom_path) = self.rom_path.clone() else {
+            return;
+        };
+        let playback = match InputPlayback::from_file(&file_path) {
+            Ok(playback) => playback,
+            Err(err) => {
+                eprintln!("Failed to read movie: {err}");
+                return;
+            }
+        };
+        if playback
+            .rom_sha1()
+            .is_some_and(|movie_sha1| !movie_sha1.eq_ignore_ascii_case(&self.rom_sha1))
+        {
+            eprintln!("Movie was recorded with a different rom, not playing it");
+            return;
+        }
+        //movies start from power on, so the cartridge is reloaded rather than just reset
+        if self.load_cartridge(rom_path.to_string_lossy().into_owned()).is_err() {
+            eprintln!("Failed to reload rom for playback");
+            return;
+        }
+        self.playback = Some(playback);
+    }
+    pub fn stop_playback(&mut self) {
+        self.playback = None;
+    }
+    //overrides whatever the ui sent while a movie is playing
+    fn play_frame(&mut self) {
+        let Some(playback) = &mut self.playback else {
+            return;
+        };
+        match playback.next_frame() {
+            Some(input) => self.cpu.bus.input.borrow_mut().controller_state = input.0,
+            None => self.playback = None,
+        }
+    }
+    fn record_frame(&mut self) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        let input = FrameInput(self.cpu.bus.input.borrow().controller_state);
+        if let Err(err) = recording.record(input) {
+            eprintln!("Failed to record frame, stopping: {err}");
+            self.recording = None;
+            self.notify_recording(false);
+        }
+    }
+    fn shutdown(&mut self) {
+        self.stop_recording();
+        self.save_cartridge();
+    }
+    fn save_cartridge(&self) {
+        if let Err(err) = self.cpu.bus.save_cartridge() {
+            eprintln!("Failed to write save file: {err}");
+        }
+    }
+    //runs until the ui quits, or until frame_limit frames have been emulated
+    pub fn run(&mut self, frame_limit: Option<u64>) {
         let mut last_fps_check = std::time::Instant::now();
         let mut last_frame_time = std::time::Instant::now();
+        let mut frames_run = 0;
 
         'run: loop {
+            if frame_limit.is_some_and(|limit| frames_run >= limit) {
+                self.shutdown();
+                break 'run;
+            }
+            //recalculated every frame since the speed can change while running
+            let target_fps = self.region.frame_rate() * self.fps_multiplier;
+            let frame_time = std::time::Duration::from_secs_f64(1.0 / target_fps);
             let now = std::time::Instant::now();
             let delta = now - last_frame_time;
-            if delta < frame_time {
+            if self.throttled && delta < frame_time {
                 // We're running too fast — sleep to match target FPS
                 std::thread::sleep(frame_time - delta);
                 continue;
@@ -58,22 +448,117 @@ impl Emulator {
 
             last_frame_time = now;
 
+            let mut rewinding = false;
+            let mut advance_frame = false;
             // Poll all input events quickly
             while let Ok(event) = self.event_receive.try_recv() {
                 match event {
-                    UiEvent::Quit => break 'run,
+                    UiEvent::Quit => {
+                        self.shutdown();
+                        break 'run;
+                    }
                     UiEvent::ControllerInput(inp) => {
                         self.cpu.bus.input.borrow_mut().controller_state = inp;
                     }
+                    UiEvent::ControllerInput2(inp) => {
+                        self.cpu.bus.input.borrow_mut().controller2_state = inp;
+                    }
+                    UiEvent::ZapperInput(zapper) => {
+                        self.cpu.bus.input.borrow_mut().zapper = Some(zapper);
+                    }
+                    //load_cartridge already reports what went wrong
                     UiEvent::LoadCart(file_path) => {
-                        self.load_cartridge(file_path);
+                        let _ = self.load_cartridge(file_path);
+                    }
+                    UiEvent::LoadRecentCart(idx) => match self.recent_files.get(idx) {
+                        Some(file_path) => {
+                            let _ = self.load_cartridge(file_path.to_string());
+                        }
+                        None => eprintln!("No recent rom in slot {}", idx + 1),
+                    },
+                    UiEvent::SaveState => self.write_save_state(),
+                    UiEvent::SaveSlot(slot) if self.cartridge_loaded => self.write_slot(slot),
+                    UiEvent::LoadSlot(slot) if self.cartridge_loaded => self.load_slot(slot),
+                    UiEvent::SaveSlot(_) | UiEvent::LoadSlot(_) => {}
+                    UiEvent::SetSpeed(speed) => self.fps_multiplier = speed,
+                    UiEvent::SetVolume(vol) => self.cpu.bus.apu.set_master_volume(vol),
+                    UiEvent::SetChannelVolume(channel, vol) => self.cpu.bus.apu.set_channel_volume(channel, vol),
+                    UiEvent::MuteChannel(channel, muted) => self.cpu.bus.apu.set_channel_muted(channel, muted),
+                    UiEvent::Reset => self.reset(),
+                    UiEvent::PowerCycle => self.power_cycle(),
+                    UiEvent::SetRegion(region) => self.set_region(region),
+                    UiEvent::LoadPalette(file_path) => {
+                        if let Err(err) = self.cpu.bus.ppu.load_palette_file(Path::new(&file_path)) {
+                            eprintln!("Failed to load palette: {err}");
+                        }
+                    }
+                    //unpausing also carries on from a breakpoint
+                    UiEvent::TogglePause if self.cpu.at_breakpoint => self.cpu.resume(),
+                    UiEvent::TogglePause => self.paused = !self.paused,
+                    UiEvent::ToggleNtscFilter => {
+                        self.ntsc_filter = match self.ntsc_filter {
+                            Some(_) => None,
+                            None => Some(NtscFilter::new()),
+                        };
+                    }
+                    //stopped at a breakpoint frame advance steps a single instruction instead
+                    UiEvent::FrameAdvance if self.cpu.at_breakpoint => self.step_instruction(),
+                    UiEvent::FrameAdvance => advance_frame = self.paused,
+                    UiEvent::SetPatternViewer(viewer) => {
+                        self.pattern_viewer = viewer;
+                        //paused frames aren't presented, so draw it now
+                        self.send_debug_views();
+                    }
+                    UiEvent::ShowSpriteViewer(show) => {
+                        self.sprite_viewer = show;
+                        self.send_debug_views();
+                    }
+                    UiEvent::ShowPaletteViewer(show) => {
+                        self.palette_viewer = show;
+                        self.send_debug_views();
+                    }
+                    UiEvent::SetMemoryViewer(base_addr) => {
+                        self.memory_viewer_base = base_addr;
+                        self.send_debug_views();
+                    }
+                    UiEvent::WriteMemory(addr, val) => {
+                        self.cpu.bus.write(addr, val);
+                        self.send_debug_views();
+                    }
+                    UiEvent::AddBreakpoint(addr) => self.cpu.add_breakpoint(addr),
+                    UiEvent::RemoveBreakpoint(addr) => self.cpu.remove_breakpoint(addr),
+                    //the ui sends these faster than frames run, so they're collapsed into one step back
+                    //rewinding would leave the movie out of sync with the game
+                    UiEvent::RewindFrame => rewinding = self.playback.is_none(),
+                    UiEvent::ToggleRecording(file_path) => self.start_recording(file_path),
+                    UiEvent::StopRecording => self.stop_recording(),
+                    UiEvent::StartPlayback(file_path) => self.start_playback(file_path),
+                    UiEvent::StopPlayback => self.stop_playback(),
+                    UiEvent::DumpTrace(file_path) => self.write_trace(&file_path),
+                    UiEvent::LoadState(state) => {
+                        if self.cartridge_loaded {
+                            self.stop_playback();
+                            self.load_state(*state);
+                        }
                     }
+                    //only ever sent to the ui
+                    UiEvent::WatchpointHit(..) | UiEvent::CartridgeLoaded(..) | UiEvent::BreakpointHit(_) | UiEvent::PatternTables(_) | UiEvent::OamSprites(_) | UiEvent::Palettes(..) | UiEvent::MemoryPage(..) | UiEvent::ControllerRumble { .. } | UiEvent::SlotUsed { .. } | UiEvent::RecordingChanged(_) => {}
                 }
             }
 
             if !self.cartridge_loaded {
                 continue;
             }
+            //a KIL opcode locked up the cpu or it's stopped at a breakpoint,
+            //the last frame stays on screen until the rom is reloaded or the breakpoint resumed
+            if self.cpu.halted {
+                frames_run += 1;
+                continue;
+            }
+            //rewinding still works while paused, it steps back a frame at a time
+            if self.paused && !advance_frame && !rewinding {
+                continue;
+            }
 
             self.fps_counter += 1;
 
@@ -83,21 +568,50 @@ impl Emulator {
                 last_fps_check = now;
             }
 
-            // Emulate frame
-            let mut cycles = 0;
-            while cycles < 29781 {
-                let new_cycles = self.cpu.execute_instruction();
-                self.cpu.bus.tick_ppu(new_cycles * 3);
-                cycles += new_cycles;
+            /*
+                States are pushed before a frame runs, so restoring the newest one
+                and running the frame again lands one frame back with the
+                framebuffer showing it.
+            */
+            if rewinding {
+                if let Some(state) = self.rewind_buffer.pop() {
+                    self.load_state(state);
+                }
+            } else {
+                match self.save_state() {
+                    Ok(state) => self.rewind_buffer.push(state),
+                    Err(err) => eprintln!("Couldn't store rewind state: {err}"),
+                }
+                self.play_frame();
+                self.record_frame();
             }
-            let should_send_framebuffer = self.fps_multiplier <= 1.0
-                || self.fps_counter % (self.fps_multiplier.round() as u32) == 0;
+
+            self.run_frame();
+            frames_run += 1;
+            //if the audio queue is full the samples are dropped rather than building up latency
+            //rewound frames are silent, replaying their audio backwards just sounds like noise,
+            //and fast forwarded audio would overrun the queue anyway
+            let audio_enabled = !rewinding && self.fps_multiplier <= 1.0;
+            for sample in self.cpu.bus.apu.take_samples() {
+                if audio_enabled {
+                    let _ = self.audio_send.try_send(sample);
+                }
+            }
+
+            //only every n-th frame is shown when sped up, so the ui still presents at 60hz
+            self.frames_since_present += 1;
+            let should_send_framebuffer = self.frames_since_present as f64 >= self.fps_multiplier;
 
             if should_send_framebuffer {
+                self.frames_since_present = 0;
                 self.framebuffer.write_back_buffer(|buff| {
                     buff.copy_from_slice(&self.cpu.bus.ppu.frame_buffer[..]);
+                    if let Some(filter) = &mut self.ntsc_filter {
+                        filter.process(buff);
+                    }
                 });
                 self.framebuffer.swap_buffers();
+                self.send_debug_views();
             }
         }
     }
@@ -0,0 +1,79 @@
+use crate::color::Color;
+
+use crate::apu::AuChannel;
+use crate::cartridge::Region;
+use crate::input::Zapper;
+use crate::memory_viewer::MemoryPage;
+use crate::pattern_viewer::{PatternTablePixels, PatternTableViewer};
+use crate::ppu::SpriteInfo;
+use crate::savestate::SaveState;
+
+pub enum UiEvent {
+    Quit,
+    LoadCart(String),
+    ControllerInput(u8),
+    ControllerInput2(u8),
+    //mouse aim and button, only sent when the Zapper is enabled
+    ZapperInput(Zapper),
+    SaveState,
+    //numbered 1 to SAVE_SLOTS
+    SaveSlot(u8),
+    LoadSlot(u8),
+    LoadState(Box<SaveState>),
+    RewindFrame,
+    SetSpeed(f64),
+    //master volume, 1.0 is the console's own level
+    SetVolume(f32),
+    SetChannelVolume(AuChannel, f32),
+    MuteChannel(AuChannel, bool),
+    //the console's reset button, ram is left as it was
+    Reset,
+    //turning the console off and on again, ram is cleared
+    PowerCycle,
+    //overrides the region detected from the rom header
+    SetRegion(Region),
+    LoadPalette(String),
+    TogglePause,
+    ToggleNtscFilter,
+    FrameAdvance,
+    ToggleRecording(String),
+    StopRecording,
+    StartPlayback(String),
+    StopPlayback,
+    //writes the recent cpu trace in nestest.log format
+    DumpTrace(String),
+    //loads one of the recent files, 0 is the newest
+    LoadRecentCart(usize),
+    //sent by the emulator whenever a movie recording starts or stops, including when starting fails
+    RecordingChanged(bool),
+    //sent by the emulator once a slot was written or loaded, with when its state was captured
+    SlotUsed { slot: u8, saved: bool, saved_at: u64 },
+    //sent by the emulator with the rom's path and PRG+CHR sha1
+    CartridgeLoaded(String, [u8; 20]),
+    //sent by the emulator with the address and value of the access
+    WatchpointHit(u16, u8),
+    AddBreakpoint(u16),
+    RemoveBreakpoint(u16),
+    //sent by the emulator when it stops, with the cpu state in nestest.log format
+    BreakpointHit(String),
+    //None hides the viewer
+    SetPatternViewer(Option<PatternTableViewer>),
+    //sent by the emulator after each frame while the viewer is shown
+    PatternTables(PatternTablePixels),
+    ShowSpriteViewer(bool),
+    //sent by the emulator after each frame while the sprite viewer is shown
+    OamSprites(Vec<SpriteInfo>),
+    ShowPaletteViewer(bool),
+    //palette ram colors and the entries they come from, sent after each frame while the palette viewer is shown
+    Palettes([Color; 32], [u8; 32]),
+    //None hides the memory viewer, otherwise the first address it shows
+    SetMemoryViewer(Option<u16>),
+    //sent by the emulator after each frame while the memory viewer is shown
+    MemoryPage(u16, MemoryPage),
+    //goes through Bus::write, so writing a register has the same effect as the cpu writing it
+    WriteMemory(u16, u8),
+    //sent by the emulator for peripherals that give feedback, played on the first controller that can rumble
+    ControllerRumble { duration_ms: u32, strength: f32 },
+}
+
+unsafe impl Send for UiEvent {}
@@ -0,0 +1,139 @@
+use crate::color::Color;
+
+/*
+    An 8x8 font for printable ASCII, so overlays don't need SDL2_ttf and
+    debug views can draw into their own pixel buffers.
+    Glyphs are 5x7 in the style of the old HD44780 LCD character set,
+    one byte per row with bit 7 as the leftmost pixel, which leaves a
+    gap between characters and under each line. Characters outside
+    $20-$7E are drawn as '?'.
+*/
+pub const CHAR_SIZE: u32 = 8;
+const FIRST_CHAR: u8 = b' ';
+
+const FONT: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x00, 0x10, 0x00], // !
+    [0x28, 0x28, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x28, 0x28, 0x7C, 0x28, 0x7C, 0x28, 0x28, 0x00], // #
+    [0x10, 0x3C, 0x50, 0x38, 0x14, 0x78, 0x10, 0x00], // $
+    [0x60, 0x64, 0x08, 0x10, 0x20, 0x4C, 0x0C, 0x00], // %
+    [0x30, 0x48, 0x50, 0x20, 0x54, 0x48, 0x34, 0x00], // &
+    [0x30, 0x10, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x08, 0x10, 0x20, 0x20, 0x20, 0x10, 0x08, 0x00], // (
+    [0x20, 0x10, 0x08, 0x08, 0x08, 0x10, 0x20, 0x00], // )
+    [0x00, 0x10, 0x54, 0x38, 0x54, 0x10, 0x00, 0x00], // *
+    [0x00, 0x10, 0x10, 0x7C, 0x10, 0x10, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x30, 0x10, 0x20, 0x00], // ,
+    [0x00, 0x00, 0x00, 0x7C, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x30, 0x00], // .
+    [0x00, 0x04, 0x08, 0x10, 0x20, 0x40, 0x00, 0x00], // /
+    [0x38, 0x44, 0x4C, 0x54, 0x64, 0x44, 0x38, 0x00], // 0
+    [0x10, 0x30, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // 1
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x20, 0x7C, 0x00], // 2
+    [0x7C, 0x08, 0x10, 0x08, 0x04, 0x44, 0x38, 0x00], // 3
+    [0x08, 0x18, 0x28, 0x48, 0x7C, 0x08, 0x08, 0x00], // 4
+    [0x7C, 0x40, 0x78, 0x04, 0x04, 0x44, 0x38, 0x00], // 5
+    [0x18, 0x20, 0x40, 0x78, 0x44, 0x44, 0x38, 0x00], // 6
+    [0x7C, 0x04, 0x08, 0x10, 0x20, 0x20, 0x20, 0x00], // 7
+    [0x38, 0x44, 0x44, 0x38, 0x44, 0x44, 0x38, 0x00], // 8
+    [0x38, 0x44, 0x44, 0x3C, 0x04, 0x08, 0x30, 0x00], // 9
+    [0x00, 0x30, 0x30, 0x00, 0x30, 0x30, 0x00, 0x00], // :
+    [0x00, 0x30, 0x30, 0x00, 0x30, 0x10, 0x20, 0x00], // ;
+    [0x08, 0x10, 0x20, 0x40, 0x20, 0x10, 0x08, 0x00], // <
+    [0x00, 0x00, 0x7C, 0x00, 0x7C, 0x00, 0x00, 0x00], // =
+    [0x20, 0x10, 0x08, 0x04, 0x08, 0x10, 0x20, 0x00], // >
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // ?
+    [0x38, 0x44, 0x04, 0x34, 0x54, 0x54, 0x38, 0x00], // @
+    [0x38, 0x44, 0x44, 0x7C, 0x44, 0x44, 0x44, 0x00], // A
+    [0x78, 0x44, 0x44, 0x78, 0x44, 0x44, 0x78, 0x00], // B
+    [0x38, 0x44, 0x40, 0x40, 0x40, 0x44, 0x38, 0x00], // C
+    [0x70, 0x48, 0x44, 0x44, 0x44, 0x48, 0x70, 0x00], // D
+    [0x7C, 0x40, 0x40, 0x78, 0x40, 0x40, 0x7C, 0x00], // E
+    [0x7C, 0x40, 0x40, 0x78, 0x40, 0x40, 0x40, 0x00], // F
+    [0x38, 0x44, 0x40, 0x5C, 0x44, 0x44, 0x3C, 0x00], // G
+    [0x44, 0x44, 0x44, 0x7C, 0x44, 0x44, 0x44, 0x00], // H
+    [0x38, 0x10, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // I
+    [0x1C, 0x08, 0x08, 0x08, 0x08, 0x48, 0x30, 0x00], // J
+    [0x44, 0x48, 0x50, 0x60, 0x50, 0x48, 0x44, 0x00], // K
+    [0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x7C, 0x00], // L
+    [0x44, 0x6C, 0x54, 0x54, 0x44, 0x44, 0x44, 0x00], // M
+    [0x44, 0x44, 0x64, 0x54, 0x4C, 0x44, 0x44, 0x00], // N
+    [0x38, 0x44, 0x44, 0x44, 0x44, 0x44, 0x38, 0x00], // O
+    [0x78, 0x44, 0x44, 0x78, 0x40, 0x40, 0x40, 0x00], // P
+    [0x38, 0x44, 0x44, 0x44, 0x54, 0x48, 0x34, 0x00], // Q
+    [0x78, 0x44, 0x44, 0x78, 0x50, 0x48, 0x44, 0x00], // R
+    [0x3C, 0x40, 0x40, 0x38, 0x04, 0x04, 0x78, 0x00], // S
+    [0x7C, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00], // T
+    [0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x38, 0x00], // U
+    [0x44, 0x44, 0x44, 0x44, 0x44, 0x28, 0x10, 0x00], // V
+    [0x44, 0x44, 0x44, 0x54, 0x54, 0x54, 0x28, 0x00], // W
+    [0x44, 0x44, 0x28, 0x10, 0x28, 0x44, 0x44, 0x00], // X
+    [0x44, 0x44, 0x44, 0x28, 0x10, 0x10, 0x10, 0x00], // Y
+    [0x7C, 0x04, 0x08, 0x10, 0x20, 0x40, 0x7C, 0x00], // Z
+    [0x38, 0x20, 0x20, 0x20, 0x20, 0x20, 0x38, 0x00], // [
+    [0x00, 0x40, 0x20, 0x10, 0x08, 0x04, 0x00, 0x00], // \
+    [0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x38, 0x00], // ]
+    [0x10, 0x28, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0x00], // _
+    [0x20, 0x10, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x38, 0x04, 0x3C, 0x44, 0x3C, 0x00], // a
+    [0x40, 0x40, 0x58, 0x64, 0x44, 0x44, 0x78, 0x00], // b
+    [0x00, 0x00, 0x38, 0x40, 0x40, 0x44, 0x38, 0x00], // c
+    [0x04, 0x04, 0x34, 0x4C, 0x44, 0x44, 0x3C, 0x00], // d
+    [0x00, 0x00, 0x38, 0x44, 0x7C, 0x40, 0x38, 0x00], // e
+    [0x18, 0x24, 0x20, 0x70, 0x20, 0x20, 0x20, 0x00], // f
+    [0x00, 0x3C, 0x44, 0x44, 0x3C, 0x04, 0x38, 0x00], // g
+    [0x40, 0x40, 0x58, 0x64, 0x44, 0x44, 0x44, 0x00], // h
+    [0x10, 0x00, 0x30, 0x10, 0x10, 0x10, 0x38, 0x00], // i
+    [0x08, 0x00, 0x18, 0x08, 0x08, 0x48, 0x30, 0x00], // j
+    [0x40, 0x40, 0x48, 0x50, 0x60, 0x50, 0x48, 0x00], // k
+    [0x30, 0x10, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // l
+    [0x00, 0x00, 0x68, 0x54, 0x54, 0x44, 0x44, 0x00], // m
+    [0x00, 0x00, 0x58, 0x64, 0x44, 0x44, 0x44, 0x00], // n
+    [0x00, 0x00, 0x38, 0x44, 0x44, 0x44, 0x38, 0x00], // o
+    [0x00, 0x00, 0x78, 0x44, 0x78, 0x40, 0x40, 0x00], // p
+    [0x00, 0x00, 0x34, 0x4C, 0x3C, 0x04, 0x04, 0x00], // q
+    [0x00, 0x00, 0x58, 0x64, 0x40, 0x40, 0x40, 0x00], // r
+    [0x00, 0x00, 0x38, 0x40, 0x38, 0x04, 0x78, 0x00], // s
+    [0x20, 0x20, 0x70, 0x20, 0x20, 0x24, 0x18, 0x00], // t
+    [0x00, 0x00, 0x44, 0x44, 0x44, 0x4C, 0x34, 0x00], // u
+    [0x00, 0x00, 0x44, 0x44, 0x44, 0x28, 0x10, 0x00], // v
+    [0x00, 0x00, 0x44, 0x44, 0x54, 0x54, 0x28, 0x00], // w
+    [0x00, 0x00, 0x44, 0x28, 0x10, 0x28, 0x44, 0x00], // x
+    [0x00, 0x00, 0x44, 0x44, 0x3C, 0x04, 0x38, 0x00], // y
+    [0x00, 0x00, 0x7C, 0x08, 0x10, 0x20, 0x7C, 0x00], // z
+    [0x08, 0x10, 0x10, 0x20, 0x10, 0x10, 0x08, 0x00], // {
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00], // |
+    [0x20, 0x10, 0x10, 0x08, 0x10, 0x10, 0x20, 0x00], // }
+    [0x00, 0x00, 0x20, 0x54, 0x08, 0x00, 0x00, 0x00], // ~
+];
+
+pub fn glyph(c: char) -> [u8; 8] {
+    let idx = match c {
+        ' '..='~' => c as u8 - FIRST_CHAR,
+        _ => b'?' - FIRST_CHAR,
+    };
+    FONT[idx as usize]
+}
+
+//width in pixels of text drawn at the given scale
+pub fn string_width(text: &str, scale: u32) -> u32 {
+    text.chars().count() as u32 * CHAR_SIZE * scale
+}
+
+//into a buffer width pixels wide with the text's top left corner at (x, y), anything past the edges is cut off
+pub fn draw_string(pixels: &mut [Color], width: usize, text: &str, x: usize, y: usize, color: Color) {
+    let height = pixels.len() / width;
+    for (idx, c) in text.chars().enumerate() {
+        let char_x = x + idx * CHAR_SIZE as usize;
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for col in 0..CHAR_SIZE as usize {
+                let (px, py) = (char_x + col, y + row);
+                if bits & (0x80 >> col) != 0 && px < width && py < height {
+                    pixels[py * width + px] = color;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::ppu::{BLACK_FRAME, FRAME_BUFFER_SIZE};
+
+//RGBA bytes, see PPU::frame_buffer
+pub type Framebuffer = Box<[u8; FRAME_BUFFER_SIZE]>;
+
+pub struct DoubleBuffer {
+    buffers: [SyncUnsafeCell; 2],
+    current_idx: AtomicUsize,
+}
+
+impl DoubleBuffer {
+    pub fn new() -> Self {
+        let front = SyncUnsafeCell(UnsafeCell::new(Box::new(BLACK_FRAME)));
+        let back = SyncUnsafeCell(UnsafeCell::new(Box::new(BLACK_FRAME)));
+
+        DoubleBuffer {
+            buffers: [front, back],
+            current_idx: AtomicUsize::new(0),
+        }
+    }
+    pub fn write_back_buffer<F: FnOnce(&mut [u8])>(&self, write_fn: F) {
+        write_fn(unsafe {
+            let idx = 1 - self.current_idx.load(Ordering::Acquire);
+            &mut **self.buffers[idx].0.get()
+        });
+    }
+    pub fn swap_buffers(&self) {
+        let old_idx = self.current_idx.load(Ordering::Acquire);
+        let new_idx = 1 - old_idx;
+        self.current_idx.store(new_idx, Ordering::Release);
+    }
+    pub fn read_front_buffer(&self) -> &[u8] {
+        let idx = self.current_idx.load(Ordering::Acquire);
+        unsafe { &**self.buffers[idx].0.get() }
+    }
+}
+
+/*
+    Three slots so the emulator never has to write over a frame the ui
+    hasn't picked up yet. The ui reads whichever frame was finished last,
+    the emulator writes into the slot that is neither being read nor
+    waiting to be read, then makes it the latest.
+*/
+pub struct TripleBuffer {
+    buffers: [SyncUnsafeCell; 3],
+    front_idx: AtomicUsize,
+    latest_idx: AtomicUsize,
+    //only touched by the emulator thread, remembers the slot between writing and swapping
+    back_idx: AtomicUsize,
+}
+
+impl TripleBuffer {
+    pub fn new() -> Self {
+        let buffer = || SyncUnsafeCell(UnsafeCell::new(Box::new(BLACK_FRAME)));
+
+        TripleBuffer {
+            buffers: [buffer(), buffer(), buffer()],
+            front_idx: AtomicUsize::new(0),
+            latest_idx: AtomicUsize::new(0),
+            back_idx: AtomicUsize::new(1),
+        }
+    }
+    pub fn write_back_buffer<F: FnOnce(&mut [u8])>(&self, write_fn: F) {
+        let front = self.front_idx.load(Ordering::SeqCst);
+        let latest = self.latest_idx.load(Ordering::SeqCst);
+        //once the ui has caught up front and latest are the same slot and either other one is free
+        let idx = if front == latest { (front + 1) % 3 } else { 3 - front - latest };
+        self.back_idx.store(idx, Ordering::Relaxed);
+        write_fn(unsafe { &mut **self.buffers[idx].0.get() });
+    }
+    pub fn swap_buffers(&self) {
+        let idx = self.back_idx.load(Ordering::Relaxed);
+        self.latest_idx.store(idx, Ordering::SeqCst);
+    }
+    pub fn read_front_buffer(&self) -> &[u8] {
+        /*
+            The emulator may pick its next slot from the old front while the
+            new one is being stored, so check latest didn't move in between,
+            otherwise the slot taken here could be the one being written.
+        */
+        let mut idx = self.latest_idx.load(Ordering::SeqCst);
+        loop {
+            self.front_idx.store(idx, Ordering::SeqCst);
+            let latest = self.latest_idx.load(Ordering::SeqCst);
+            if latest == idx {
+                break;
+            }
+            idx = latest;
+        }
+        unsafe { &**self.buffers[idx].0.get() }
+    }
+}
+
+//picked at startup from the config
+pub enum SharedFramebuffer {
+    Double(DoubleBuffer),
+    Triple(TripleBuffer),
+}
+
+impl SharedFramebuffer {
+    pub fn new(triple_buffering: bool) -> Self {
+        if triple_buffering {
+            SharedFramebuffer::Triple(TripleBuffer::new())
+        } else {
+            SharedFramebuffer::Double(DoubleBuffer::new())
+        }
+    }
+    pub fn write_back_buffer<F: FnOnce(&mut [u8])>(&self, write_fn: F) {
+        match self {
+            SharedFramebuffer::Double(buffer) => buffer.write_back_buffer(write_fn),
+            SharedFramebuffer::Triple(buffer) => buffer.write_back_buffer(write_fn),
+        }
+    }
+    pub fn swap_buffers(&self) {
+        match self {
+            SharedFramebuffer::Double(buffer) => buffer.swap_buffers(),
+            SharedFramebuffer::Triple(buffer) => buffer.swap_buffers(),
+        }
+    }
+    pub fn read_front_buffer(&self) -> &[u8] {
+        match self {
+            SharedFramebuffer::Double(buffer) => buffer.read_front_buffer(),
+            SharedFramebuffer::Triple(buffer) => buffer.read_front_buffer(),
+        }
+    }
+}
+
+pub struct SyncUnsafeCell(pub UnsafeCell<Framebuffer>);
+
+unsafe impl Sync for SyncUnsafeCell {}
+unsafe impl Send for SyncUnsafeCell {}
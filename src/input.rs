@@ -1,6 +1,51 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::ppu::PPU;
+use crate::savestate::InputState;
+
+//lines the Zapper's photodiode keeps seeing a spot for after the beam lights it
+const ZAPPER_LIGHT_SCANLINES: u32 = 20;
+//0-255 luma a pixel needs to count as light
+const ZAPPER_LIGHT_THRESHOLD: f32 = 128.0;
+
+//light gun in port 2, aimed at a pixel of the NES screen or away from it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Zapper {
+    pub aim: Option<(usize, usize)>,
+    pub trigger: bool,
+}
+
+impl Zapper {
+    //bit 3 is clear while the gun sees light, bit 4 is set while the trigger is held
+    pub fn read(&self, ppu: &PPU) -> u8 {
+        let light = self.aim.is_some_and(|(x, y)| {
+            //the pixel under the cursor and the ones around it, the gun doesn't see a single dot
+            (y.saturating_sub(1)..=y + 1)
+                .flat_map(|py| (x.saturating_sub(1)..=x + 1).map(move |px| (px, py)))
+                .filter_map(|(px, py)| ppu.recently_drawn_pixel(px, py, ZAPPER_LIGHT_SCANLINES))
+                .any(|[r, g, b, _]| 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32 > ZAPPER_LIGHT_THRESHOLD)
+        });
+        let mut result = 0;
+        if !light {
+            result |= 0x08;
+        }
+        if self.trigger {
+            result |= 0x10;
+        }
+        result
+    }
+}
+
 pub struct Input {
     pub(crate) controller_state: u8,
     pub(crate) controller_shift: u8,
+    pub(crate) controller2_state: u8,
+    pub(crate) controller2_shift: u8,
+    //takes the place of controller 2 once the ui reports it
+    pub(crate) zapper: Option<Zapper>,
 }
 
 impl Input {
@@ -8,16 +53,194 @@ impl Input {
         Input {
             controller_state: 0,
             controller_shift: 0,
+            controller2_state: 0,
+            controller2_shift: 0,
+            zapper: None,
         }
     }
+    pub fn save_state(&self) -> InputState {
+        InputState {
+            controller_state: self.controller_state,
+            controller_shift: self.controller_shift,
+            controller2_state: self.controller2_state,
+            controller2_shift: self.controller2_shift,
+        }
+    }
+    pub fn load_state(&mut self, state: InputState) {
+        self.controller_state = state.controller_state;
+        self.controller_shift = state.controller_shift;
+        self.controller2_state = state.controller2_state;
+        self.controller2_shift = state.controller2_shift;
+    }
     pub fn read(&mut self) -> u8 {
         let result = self.controller_shift & 1;
         self.controller_shift >>= 1;
         result
     }
+    pub fn read2(&mut self) -> u8 {
+        let result = self.controller2_shift & 1;
+        self.controller2_shift >>= 1;
+        result
+    }
+    //the strobe line is shared, so both controllers latch at once
     pub fn write(&mut self, val: u8) {
         if (val & 1) != 0 {
-            self.controller_shift = self.controller_state
+            self.controller_shift = self.controller_state;
+            self.controller2_shift = self.controller2_state;
+        }
+    }
+}
+
+//fm2 lists buttons from bit 7 down to bit 0 of the controller byte
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+//controller state for a single frame of a movie
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FrameInput(pub u8);
+
+impl FrameInput {
+    //only port 0 is used, port 1 and the fourscore port are left empty
+    pub fn to_fm2(self) -> String {
+        let buttons: String = FM2_BUTTONS
+            .iter()
+            .enumerate()
+            .map(|(i, &button)| {
+                if self.0 & (0x80 >> i) != 0 {
+                    button as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        format!("|0|{}|||", buttons)
+    }
+    //any character other than '.' or a space counts as pressed, like FCEUX
+    pub fn from_fm2(line: &str) -> Option<Self> {
+        let mut fields = line.strip_prefix('|')?.split('|');
+        let _commands = fields.next()?;
+        let port0 = fields.next()?.as_bytes();
+        if port0.len() != FM2_BUTTONS.len() {
+            return None;
         }
+        let state = port0
+            .iter()
+            .enumerate()
+            .filter(|&(_, &c)| c != b'.' && c != b' ')
+            .fold(0, |state, (i, _)| state | (0x80 >> i));
+        Some(FrameInput(state))
+    }
+}
+
+/*
+    Writes an .fm2 movie as frames come in.
+    The header follows the FCEUX format, with the rom sha1 stored under
+    its own key since romChecksum is defined as an md5.
+*/
+pub struct InputRecording {
+    writer: BufWriter<File>,
+    frame_count: u32,
+}
+
+impl InputRecording {
+    pub fn create<PathLike: AsRef<Path>>(
+        file_path: PathLike,
+        rom_name: &str,
+        rom_sha1: &str,
+    ) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        writeln!(writer, "version 3")?;
+        writeln!(writer, "emuVersion 1")?;
+        writeln!(writer, "emuName RNES")?;
+        writeln!(writer, "rerecordCount 0")?;
+        writeln!(writer, "palFlag 0")?;
+        writeln!(writer, "romFilename {}", rom_name)?;
+        writeln!(writer, "romSHA1 {}", rom_sha1)?;
+        writeln!(writer, "guid {}", Self::generate_guid())?;
+        writeln!(writer, "fourscore 0")?;
+        writeln!(writer, "microphone 0")?;
+        //1 is a standard controller, 0 means nothing is plugged in
+        writeln!(writer, "port0 1")?;
+        writeln!(writer, "port1 0")?;
+        writeln!(writer, "port2 0")?;
+        writeln!(writer, "FDS 0")?;
+        writeln!(writer, "NewPPU 0")?;
+        Ok(InputRecording {
+            writer,
+            frame_count: 0,
+        })
+    }
+    pub fn record(&mut self, input: FrameInput) -> std::io::Result<()> {
+        writeln!(self.writer, "{}", input.to_fm2())?;
+        self.frame_count += 1;
+        Ok(())
+    }
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+    //only needs to tell movies apart, so the clock is hashed instead of pulling in a uuid crate
+    fn generate_guid() -> String {
+        use std::hash::{BuildHasher, RandomState};
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let state = RandomState::new();
+        let high = state.hash_one(time);
+        let low = state.hash_one(high);
+        format!(
+            "{:08X}-{:04X}-{:04X}-{:04X}-{:012X}",
+            high >> 32,
+            (high >> 16) & 0xFFFF,
+            high & 0xFFFF,
+            low >> 48,
+            low & 0xFFFF_FFFF_FFFF
+        )
+    }
+}
+
+//reads back an .fm2 movie, one controller state per frame
+pub struct InputPlayback {
+    frames: Vec<FrameInput>,
+    position: usize,
+    rom_sha1: Option<String>,
+}
+
+impl InputPlayback {
+    pub fn from_file<PathLike: AsRef<Path>>(file_path: PathLike) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(file_path)?)
+    }
+    pub fn parse(movie: &str) -> Result<Self, Box<dyn Error>> {
+        let mut frames = Vec::new();
+        let mut rom_sha1 = None;
+        for line in movie.lines() {
+            if line.starts_with('|') {
+                let input = FrameInput::from_fm2(line)
+                    .ok_or_else(|| format!("Invalid frame record: {}", line))?;
+                frames.push(input);
+            } else if let Some(value) = line.strip_prefix("romSHA1 ") {
+                rom_sha1 = Some(value.trim().to_string());
+            }
+        }
+        Ok(InputPlayback {
+            frames,
+            position: 0,
+            rom_sha1,
+        })
+    }
+    //movies from other emulators only carry an md5, so this may be missing
+    pub fn rom_sha1(&self) -> Option<&str> {
+        self.rom_sha1.as_deref()
+    }
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    //None once the movie has run out
+    pub fn next_frame(&mut self) -> Option<FrameInput> {
+        let input = self.frames.get(self.position).copied();
+        self.position += 1;
+        input
     }
 }
@@ -0,0 +1,38 @@
+#![allow(dead_code)]
+//the core types are built with new() and have no meaningful default
+#![allow(clippy::new_without_default)]
+
+/*
+    The emulation core. Everything here builds without SDL2 so other
+    frontends, test harnesses and wasm builds can depend on it, the SDL2
+    frontend lives in the binary next to main.rs.
+*/
+pub mod apu;
+pub mod bus;
+pub mod cartridge;
+pub mod color;
+pub mod config;
+pub mod cpu;
+pub mod emulator;
+pub mod event;
+pub mod font;
+pub mod frame_buffer;
+pub mod input;
+pub mod memory_viewer;
+pub mod nsf;
+pub mod ntsc_filter;
+pub mod palette_viewer;
+pub mod pattern_viewer;
+pub mod ppu;
+pub mod rewind;
+pub mod rom_db;
+pub mod savestate;
+pub mod sprite_viewer;
+pub mod trace;
+
+pub use cartridge::{Cartridge, Mapper};
+pub use cpu::CPU;
+pub use emulator::Emulator;
+
+#[cfg(test)]
+mod tests;
@@ -1,40 +1,181 @@
 #![allow(dead_code)]
 
-mod bus;
-mod cartridge;
-mod cpu;
-mod emulator;
-mod input;
-mod ppu;
 mod ui;
 
 use std::sync::Arc;
 
-use crossbeam_channel::unbounded;
-use ui::{RnesUI, UiEvent};
-
-use crate::ui::frame_buffer::DoubleBuffer;
+use crossbeam_channel::{bounded, unbounded};
+use rnes::Emulator;
+use rnes::apu::DEFAULT_SAMPLE_RATE;
+use rnes::bus::Watchpoint;
+use rnes::config::Config;
+use rnes::event::UiEvent;
+use rnes::frame_buffer::SharedFramebuffer;
+use rnes::trace::DEFAULT_TRACE_CAPACITY;
+use sdl2::audio::AudioSpecDesired;
+use ui::audio::{AUDIO_QUEUE_CAPACITY, AudioOutput};
+use ui::{RnesUI, WINDOW_TITLE};
 
+//the ui tests need the binary-only ui module
 #[cfg(test)]
-mod tests;
+#[path = "tests/ui_tests.rs"]
+mod ui_tests;
+
+//frames run in headless mode when --frames isn't given
+const DEFAULT_HEADLESS_FRAMES: u64 = 600;
+//where a headless run with --trace leaves its trace
+const HEADLESS_TRACE_PATH: &str = "trace.log";
+
+struct Args {
+    headless: bool,
+    frames: u64,
+    trace: bool,
+    watchpoints: Vec<Watchpoint>,
+    breakpoints: Vec<u16>,
+    rom_path: Option<String>,
+}
+
+fn parse_args() -> Args {
+    let mut parsed = Args {
+        headless: false,
+        frames: DEFAULT_HEADLESS_FRAMES,
+        trace: false,
+        watchpoints: Vec::new(),
+        breakpoints: Vec::new(),
+        rom_path: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => parsed.headless = true,
+            "--trace" => parsed.trace = true,
+            "--frames" => match args.next().and_then(|frames| frames.parse().ok()) {
+                Some(frames) => parsed.frames = frames,
+                None => eprintln!("--frames expects a frame count"),
+            },
+            "--watch" => match args.next().as_deref().and_then(parse_watchpoint) {
+                Some(watchpoint) => parsed.watchpoints.push(watchpoint),
+                None => eprintln!("--watch expects a hex address or range, like 0300 or 2000-2007"),
+            },
+            "--break" => match args.next().as_deref().and_then(parse_hex_addr) {
+                Some(addr) => parsed.breakpoints.push(addr),
+                None => eprintln!("--break expects a hex address, like C000"),
+            },
+            _ => parsed.rom_path = Some(arg),
+        }
+    }
+    parsed
+}
+
+fn parse_hex_addr(arg: &str) -> Option<u16> {
+    u16::from_str_radix(arg.trim_start_matches('$'), 16).ok()
+}
+
+//watches reads and writes to a single address or an inclusive range
+fn parse_watchpoint(arg: &str) -> Option<Watchpoint> {
+    let (start, end) = match arg.split_once('-') {
+        Some((start, end)) => (parse_hex_addr(start)?, parse_hex_addr(end)?),
+        None => (parse_hex_addr(arg)?, parse_hex_addr(arg)?),
+    };
+    Some(Watchpoint {
+        addr_range: start..=end,
+        on_read: true,
+        on_write: true,
+    })
+}
+
+/*
+    Runs the emulator on this thread without touching SDL.
+    Nothing reads the framebuffer or the audio queue, audio samples are dropped
+    as soon as the queue fills up.
+*/
+fn run_headless(args: Args) {
+    let Some(rom_path) = args.rom_path else {
+        eprintln!("Headless mode needs a rom path");
+        std::process::exit(1);
+    };
+    let (_event_send, event_receive) = unbounded::<UiEvent>();
+    let (audio_send, _) = bounded::<f32>(AUDIO_QUEUE_CAPACITY);
+    let mut emu = Emulator::new(event_receive, Arc::new(SharedFramebuffer::new(false)), audio_send, DEFAULT_SAMPLE_RATE);
+    if emu.load_cartridge(rom_path).is_err() {
+        std::process::exit(1);
+    }
+    emu.set_throttled(false);
+    for watchpoint in args.watchpoints {
+        emu.add_watchpoint(watchpoint);
+    }
+    for addr in args.breakpoints {
+        emu.add_breakpoint(addr);
+    }
+    if args.trace {
+        emu.set_trace_capacity(Some(DEFAULT_TRACE_CAPACITY));
+    }
+    emu.run(Some(args.frames));
+    if args.trace {
+        emu.write_trace(HEADLESS_TRACE_PATH);
+    }
+
+    let cpu = emu.cpu();
+    println!(
+        "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+        cpu.pc, cpu.a, cpu.x, cpu.y, cpu.status, cpu.sp as u8
+    );
+}
 
 fn main() {
-    let buf = Arc::new(DoubleBuffer::new());
+    let args = parse_args();
+    if args.headless {
+        run_headless(args);
+        return;
+    }
+
+    let config = Config::load_or_create(Config::default_path());
+    let buf = Arc::new(SharedFramebuffer::new(config.triple_buffering));
     let buf2 = Arc::clone(&buf);
     let (sx2, rx2) = unbounded::<UiEvent>();
+    let (audio_send, audio_receive) = bounded::<f32>(AUDIO_QUEUE_CAPACITY);
 
-    let emu_thread = std::thread::spawn(move || {
-        use crate::emulator::Emulator;
+    let sdl2 = sdl2::init().unwrap();
+    let video = sdl2.video().unwrap();
+    let audio = sdl2.audio().unwrap();
 
-        let mut emu = Emulator::new(rx2, buf);
+    let desired_spec = AudioSpecDesired {
+        freq: Some(config.audio.sample_rate as i32),
+        channels: Some(1),
+        samples: Some(config.audio.buffer_size),
+    };
+    let audio_device = audio
+        .open_playback(None, &desired_spec, |_| AudioOutput::new(audio_receive))
+        .unwrap();
+    //the device may not give us the rate we asked for
+    let sample_rate = audio_device.spec().freq as u32;
+    audio_device.resume();
+
+    let rom_path = args.rom_path;
+    let trace = args.trace;
+    let watchpoints = args.watchpoints;
+    let recent_files = config.recent_files.clone();
+    let (ui_send, ui_receive) = unbounded::<UiEvent>();
+    let emu_thread = std::thread::spawn(move || {
+        let mut emu = Emulator::new(rx2, buf, audio_send, sample_rate);
+        emu.set_ui_sender(ui_send);
+        emu.set_recent_files(recent_files);
+        for watchpoint in watchpoints {
+            emu.add_watchpoint(watchpoint);
+        }
+        if trace {
+            emu.set_trace_capacity(Some(DEFAULT_TRACE_CAPACITY));
+        }
+        if let Some(path) = rom_path {
+            let _ = emu.load_cartridge(path);
+        }
 
-        emu.run();
+        emu.run(None);
     });
 
-    let sdl2 = sdl2::init().unwrap();
-    let video = sdl2.video().unwrap();
     let canvas = video
-        .window("RNES", 1280, 720)
+        .window(WINDOW_TITLE, 1280, 720)
+        .resizable()
         .build()
         .unwrap()
         .into_canvas()
@@ -43,7 +184,21 @@ fn main() {
 
     let texture_creator = canvas.texture_creator();
 
-    let mut ui = RnesUI::new(1280, 720, sx2, canvas, &texture_creator, buf2);
+    if let Some(palette_path) = &config.palette_path {
+        sx2.send(UiEvent::LoadPalette(palette_path.clone())).unwrap();
+    }
+    if config.ntsc_filter {
+        sx2.send(UiEvent::ToggleNtscFilter).unwrap();
+    }
+    sx2.send(UiEvent::SetVolume(config.volume.master)).unwrap();
+    for (channel, vol) in config.volume.channels() {
+        sx2.send(UiEvent::SetChannelVolume(channel, vol)).unwrap();
+    }
+    for addr in args.breakpoints {
+        sx2.send(UiEvent::AddBreakpoint(addr)).unwrap();
+    }
+    let mut ui = RnesUI::new(1280, 720, sx2, canvas, &texture_creator, buf2, &config);
+    ui.set_emulator_events(ui_receive);
 
     ui.run();
     emu_thread.join().unwrap();
@@ -0,0 +1,139 @@
+use crate::color::Color;
+use crate::font::{self, CHAR_SIZE};
+
+pub const PAGE_SIZE: usize = 256;
+
+//bytes of one page of the cpu address space, None where nothing can be read
+pub type MemoryPage = Box<[Option<u8>; PAGE_SIZE]>;
+
+/*
+    Hex dump layout, all in pixels of the 8x8 font. The first line is the base
+    address followed by the column numbers, then 16 lines of an address and
+    16 bytes each.
+*/
+const CHAR_ADVANCE: usize = CHAR_SIZE as usize;
+const LINE_HEIGHT: usize = 9;
+const PADDING: usize = 2;
+const BYTES_X: usize = PADDING + CHAR_ADVANCE * 5;
+const BYTE_WIDTH: usize = CHAR_ADVANCE * 3;
+pub const MEMORY_VIEW_WIDTH: usize = BYTES_X + BYTE_WIDTH * 16 + PADDING;
+pub const MEMORY_VIEW_HEIGHT: usize = PADDING * 2 + LINE_HEIGHT * 17;
+
+pub type MemoryViewPixels = Box<[Color; MEMORY_VIEW_WIDTH * MEMORY_VIEW_HEIGHT]>;
+
+const LABEL_COLOR: Color = Color::RGB(150, 150, 150);
+const ZERO_PAGE_COLOR: Color = Color::RGB(120, 220, 120);
+const STACK_COLOR: Color = Color::RGB(120, 170, 255);
+const SELECTION_COLOR: Color = Color::RGB(90, 80, 0);
+
+//a page of memory as a hex editor, typing two hex digits over a selected byte writes it
+pub struct MemoryViewer {
+    base_addr: u16,
+    bytes: MemoryPage,
+    selected: Option<u16>,
+    //first digit typed over the selected byte
+    pending_digit: Option<u8>,
+}
+
+impl MemoryViewer {
+    pub fn new() -> Self {
+        MemoryViewer {
+            base_addr: 0,
+            bytes: Box::new([None; PAGE_SIZE]),
+            selected: None,
+            pending_digit: None,
+        }
+    }
+    pub fn base_addr(&self) -> u16 {
+        self.base_addr
+    }
+    pub fn set_bytes(&mut self, base_addr: u16, bytes: MemoryPage) {
+        //pages requested before a scroll can still be on their way
+        if base_addr == self.base_addr {
+            self.bytes = bytes;
+        }
+    }
+    //scrolls by lines of 16 bytes, stopping at either end of the address space
+    pub fn scroll(&mut self, lines: i32) {
+        let last_base = (0x10000 - PAGE_SIZE) as i32;
+        self.base_addr = (self.base_addr as i32 + lines * 16).clamp(0, last_base) as u16;
+    }
+    pub fn is_editing(&self) -> bool {
+        self.selected.is_some()
+    }
+    pub fn select(&mut self, addr: u16) {
+        self.selected = Some(addr);
+        self.pending_digit = None;
+    }
+    pub fn cancel_edit(&mut self) {
+        self.selected = None;
+        self.pending_digit = None;
+    }
+    //returns the write to make once both digits are in, then moves on to the next byte
+    pub fn type_digit(&mut self, digit: u8) -> Option<(u16, u8)> {
+        let addr = self.selected?;
+        let Some(high) = self.pending_digit.take() else {
+            self.pending_digit = Some(digit & 0x0F);
+            return None;
+        };
+        let next = addr.wrapping_add(1);
+        if next.wrapping_sub(self.base_addr) as usize >= PAGE_SIZE {
+            self.scroll(1);
+        }
+        self.selected = Some(next);
+        Some((addr, (high << 4) | (digit & 0x0F)))
+    }
+    //address of the byte under a pixel of the rendered view
+    pub fn addr_at(&self, x: usize, y: usize) -> Option<u16> {
+        let line = y.checked_sub(PADDING)? / LINE_HEIGHT;
+        let column = x.checked_sub(BYTES_X)? / BYTE_WIDTH;
+        if line == 0 || line > 16 || column >= 16 {
+            return None;
+        }
+        Some(self.base_addr.wrapping_add(((line - 1) * 16 + column) as u16))
+    }
+    pub fn render(&self) -> MemoryViewPixels {
+        let mut pixels = Box::new([Color::BLACK; MEMORY_VIEW_WIDTH * MEMORY_VIEW_HEIGHT]);
+        draw_hex(&mut pixels, PADDING, PADDING, self.base_addr as u32, 4, LABEL_COLOR);
+        for column in 0..16 {
+            draw_hex(&mut pixels, BYTES_X + column * BYTE_WIDTH + CHAR_ADVANCE / 2, PADDING, column as u32, 1, LABEL_COLOR);
+        }
+        for line in 0..16 {
+            let y = PADDING + (line + 1) * LINE_HEIGHT;
+            let line_addr = self.base_addr.wrapping_add(line as u16 * 16);
+            draw_hex(&mut pixels, PADDING, y, line_addr as u32, 4, LABEL_COLOR);
+            for column in 0..16 {
+                let addr = line_addr.wrapping_add(column as u16);
+                let x = BYTES_X + column * BYTE_WIDTH;
+                let color = match addr {
+                    0x0000..=0x00FF => ZERO_PAGE_COLOR,
+                    0x0100..=0x01FF => STACK_COLOR,
+                    _ => Color::WHITE,
+                };
+                if self.selected == Some(addr) {
+                    fill_rect(&mut pixels, x - 1, y - 1, CHAR_ADVANCE * 2 + 1, LINE_HEIGHT, SELECTION_COLOR);
+                    if let Some(digit) = self.pending_digit {
+                        draw_hex(&mut pixels, x, y, digit as u32, 1, color);
+                        continue;
+                    }
+                }
+                match self.bytes[line * 16 + column] {
+                    Some(val) => draw_hex(&mut pixels, x, y, val as u32, 2, color),
+                    None => font::draw_string(&mut pixels[..], MEMORY_VIEW_WIDTH, "??", x, y, color),
+                }
+            }
+        }
+        pixels
+    }
+}
+
+fn draw_hex(pixels: &mut MemoryViewPixels, x: usize, y: usize, val: u32, digits: usize, color: Color) {
+    let text = format!("{val:0digits$X}");
+    font::draw_string(&mut pixels[..], MEMORY_VIEW_WIDTH, &text, x, y, color);
+}
+
+fn fill_rect(pixels: &mut MemoryViewPixels, x: usize, y: usize, width: usize, height: usize, color: Color) {
+    for row in y..y + height {
+        pixels[row * MEMORY_VIEW_WIDTH + x..row * MEMORY_VIEW_WIDTH + x + width].fill(color);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{
+    cartridge::{Cartridge, Mapper, NsfHeader},
+    cpu::CPU,
+};
+
+//cpu cycles in an NTSC frame, the play routine is called once per frame
+const FRAME_CYCLES: i32 = 29781;
+//init and play routines return here, nothing is mapped at this address so it's never executed
+const RETURN_ADDR: u16 = 0x5FF0;
+
+/*
+    Minimal NSF player, the music code drives the APU by itself so the
+    player only has to call the init routine once per song and the play
+    routine once per frame. The PPU is never ticked.
+*/
+pub struct NsfPlayer {
+    cpu: CPU,
+    header: NsfHeader,
+}
+
+impl NsfPlayer {
+    pub fn new(cart: Cartridge, sample_rate: u32) -> Self {
+        let header = cart.nsf.clone().expect("NsfPlayer needs a cartridge made by Cartridge::from_nsf");
+        let mut cpu = CPU::init();
+        cpu.bus.apu.set_sample_rate(sample_rate);
+        cpu.bus.load_cartridge(Mapper::with_cart(cart));
+
+        let mut player = NsfPlayer { cpu, header };
+        player.init_song(player.header.starting_song);
+        player
+    }
+    pub fn header(&self) -> &NsfHeader {
+        &self.header
+    }
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+    //restarts from power on and runs the init routine for a song, numbered from 1
+    pub fn init_song(&mut self, song: u8) {
+        self.cpu.bus.reset();
+        self.cpu.reset();
+        let banks = if self.header.uses_bank_switching() {
+            self.header.bank_init
+        } else {
+            [0, 1, 2, 3, 4, 5, 6, 7]
+        };
+        for (slot, bank) in banks.into_iter().enumerate() {
+            self.cpu.bus.write(0x5FF8 + slot as u16, bank);
+        }
+        for addr in 0x6000..=0x7FFF {
+            self.cpu.bus.write(addr, 0);
+        }
+        for addr in 0x4000..=0x4013 {
+            self.cpu.bus.write(addr, 0);
+        }
+        self.cpu.bus.write(0x4015, 0x0F);
+        self.cpu.bus.write(0x4017, 0x40);
+
+        //the init routine takes the song index in A and 0 for NTSC in X
+        self.cpu.a = song.saturating_sub(1);
+        self.cpu.x = 0;
+        self.call(self.header.init_addr);
+    }
+    //runs the play routine and returns the audio produced over the frame
+    pub fn play_frame(&mut self) -> Vec<f32> {
+        let cycles = self.call(self.header.play_addr);
+        //the rest of the frame the cpu would sit idle waiting for the next nmi
+        if cycles < FRAME_CYCLES {
+            let idle_cycles = FRAME_CYCLES - cycles;
+            self.cpu.bus.tick_apu(idle_cycles, self.cpu.cpu_cycle + idle_cycles as u64);
+        }
+        self.cpu.bus.apu.take_samples()
+    }
+    //runs a routine until it returns, giving up after a frame's worth of cycles
+    fn call(&mut self, addr: u16) -> i32 {
+        self.cpu.call_subroutine(addr, RETURN_ADDR);
+        let mut cycles = 0;
+        while self.cpu.pc != RETURN_ADDR && cycles < FRAME_CYCLES {
+            let new_cycles = self.cpu.execute_instruction();
+            self.cpu.bus.tick_apu(new_cycles, self.cpu.cpu_cycle);
+            cycles += new_cycles;
+        }
+        cycles
+    }
+}
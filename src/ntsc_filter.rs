@@ -0,0 +1,65 @@
+use crate::ppu::SCREEN_WIDTH;
+
+/*
+    A rough take on the composite signal. Each pixel is split into YIQ,
+    luma stays sharp while the I and Q chroma channels are averaged over
+    4 pixels of the line, the way the TV's narrow chroma bandwidth smears
+    color into its neighbours, then the result goes back to RGB.
+*/
+const CHROMA_WIDTH: usize = 4;
+
+pub struct NtscFilter {
+    //one line of chroma, so the blur reads the unfiltered values
+    i: Vec<f32>,
+    q: Vec<f32>,
+}
+
+impl NtscFilter {
+    pub fn new() -> Self {
+        NtscFilter {
+            i: vec![0.0; SCREEN_WIDTH],
+            q: vec![0.0; SCREEN_WIDTH],
+        }
+    }
+    //filters an RGBA frame in place, alpha is left alone
+    pub fn process(&mut self, frame: &mut [u8]) {
+        for line in frame.chunks_exact_mut(SCREEN_WIDTH * 4) {
+            for (x, pixel) in line.chunks_exact(4).enumerate() {
+                let (_, i, q) = rgb_to_yiq(pixel[0], pixel[1], pixel[2]);
+                self.i[x] = i;
+                self.q[x] = q;
+            }
+            for (x, pixel) in line.chunks_exact_mut(4).enumerate() {
+                let (y, _, _) = rgb_to_yiq(pixel[0], pixel[1], pixel[2]);
+                //the window leans left so color trails behind the beam like it does on a TV
+                let start = (x + 1).saturating_sub(CHROMA_WIDTH - 1);
+                let end = (x + 2).min(SCREEN_WIDTH);
+                let taps = (end - start) as f32;
+                let i = self.i[start..end].iter().sum::<f32>() / taps;
+                let q = self.q[start..end].iter().sum::<f32>() / taps;
+                let [r, g, b] = yiq_to_rgb(y, i, q);
+                pixel[0] = r;
+                pixel[1] = g;
+                pixel[2] = b;
+            }
+        }
+    }
+}
+
+fn rgb_to_yiq(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    (
+        0.299 * r + 0.587 * g + 0.114 * b,
+        0.596 * r - 0.274 * g - 0.322 * b,
+        0.211 * r - 0.523 * g + 0.312 * b,
+    )
+}
+
+fn yiq_to_rgb(y: f32, i: f32, q: f32) -> [u8; 3] {
+    let channel = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    [
+        channel(y + 0.956 * i + 0.621 * q),
+        channel(y - 0.272 * i - 0.647 * q),
+        channel(y - 1.106 * i + 1.703 * q),
+    ]
+}
@@ -0,0 +1,39 @@
+use crate::color::Color;
+
+/*
+    The backdrop gets a bigger swatch on the left, then the 32 palette ram
+    entries follow in two rows of 16, $3F00-$3F0F for the background and
+    $3F10-$3F1F for sprites.
+*/
+const SWATCH_SIZE: usize = 16;
+const BACKDROP_SIZE: usize = SWATCH_SIZE * 2;
+const BACKDROP_GAP: usize = 8;
+const GRID_X: usize = BACKDROP_SIZE + BACKDROP_GAP;
+pub const PALETTE_VIEW_WIDTH: usize = GRID_X + SWATCH_SIZE * 16;
+pub const PALETTE_VIEW_HEIGHT: usize = SWATCH_SIZE * 2;
+
+pub type PaletteViewPixels = Box<[Color; PALETTE_VIEW_WIDTH * PALETTE_VIEW_HEIGHT]>;
+
+pub fn palette_view(colors: &[Color; 32]) -> PaletteViewPixels {
+    let mut pixels = Box::new([Color::BLACK; PALETTE_VIEW_WIDTH * PALETTE_VIEW_HEIGHT]);
+    for y in 0..PALETTE_VIEW_HEIGHT {
+        for x in 0..PALETTE_VIEW_WIDTH {
+            if let Some(addr) = palette_addr_at(x, y) {
+                pixels[y * PALETTE_VIEW_WIDTH + x] = colors[(addr - 0x3F00) as usize];
+            }
+        }
+    }
+    pixels
+}
+
+//palette ram address under a pixel of the view, the backdrop swatch is $3F00
+pub fn palette_addr_at(x: usize, y: usize) -> Option<u16> {
+    if y >= PALETTE_VIEW_HEIGHT {
+        return None;
+    }
+    match x {
+        0..BACKDROP_SIZE => Some(0x3F00),
+        GRID_X..PALETTE_VIEW_WIDTH => Some(0x3F00 + ((y / SWATCH_SIZE) * 16 + (x - GRID_X) / SWATCH_SIZE) as u16),
+        _ => None,
+    }
+}
@@ -0,0 +1,66 @@
+use crate::color::Color;
+
+use crate::{
+    cartridge::Mapper,
+    ppu::{PPU, rgba_color},
+};
+
+//both tables side by side, table 0 on the left
+pub const PATTERN_VIEW_WIDTH: usize = 256;
+pub const PATTERN_VIEW_HEIGHT: usize = 128;
+
+pub type PatternTablePixels = Box<[Color; PATTERN_VIEW_WIDTH * PATTERN_VIEW_HEIGHT]>;
+
+/*
+    Draws the 512 tiles of the pattern tables the way the background would
+    show them with one of its four palettes. Tiles are numbered 0-511,
+    256 and up are in the table at $1000.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PatternTableViewer {
+    pub palette: u8,
+    pub selected_tile: Option<u16>,
+}
+
+impl PatternTableViewer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    //tile under a pixel of the rendered view
+    pub fn tile_at(x: usize, y: usize) -> u16 {
+        let table = x / 128;
+        (table * 256 + (y / 8) * 16 + (x % 128) / 8) as u16
+    }
+    pub fn render(&self, ppu: &PPU, mapper: &Mapper) -> PatternTablePixels {
+        let mut pixels = Box::new([Color::BLACK; PATTERN_VIEW_WIDTH * PATTERN_VIEW_HEIGHT]);
+        for tile in 0..512u16 {
+            let tile_x = (tile as usize / 256) * 128 + (tile as usize % 16) * 8;
+            let tile_y = (tile as usize % 256 / 16) * 8;
+            for fine_y in 0..8 {
+                //same fetch as PPU::step does for the background
+                let addr = tile * 16 + fine_y as u16;
+                let lo = ppu.read(mapper, addr);
+                let hi = ppu.read(mapper, addr + 8);
+                for fine_x in 0..8 {
+                    let mut color = (lo >> (7 ^ fine_x)) & 1;
+                    color |= ((hi >> (7 ^ fine_x)) & 1) << 1;
+                    let pixel = (tile_y + fine_y) * PATTERN_VIEW_WIDTH + tile_x + fine_x;
+                    pixels[pixel] = rgba_color(ppu.fetch_background_color(color, self.palette & 0x03));
+                }
+            }
+        }
+        if let Some(tile) = self.selected_tile {
+            Self::outline_tile(&mut pixels, tile % 512);
+        }
+        pixels
+    }
+    fn outline_tile(pixels: &mut PatternTablePixels, tile: u16) {
+        let tile_x = (tile as usize / 256) * 128 + (tile as usize % 16) * 8;
+        let tile_y = (tile as usize % 256 / 16) * 8;
+        for i in 0..8 {
+            for (x, y) in [(i, 0), (i, 7), (0, i), (7, i)] {
+                pixels[(tile_y + y) * PATTERN_VIEW_WIDTH + tile_x + x] = Color::WHITE;
+            }
+        }
+    }
+}
@@ -1,15 +1,33 @@
 use std::ops::{BitAndAssign, BitOr};
 use std::{ops::BitAnd, rc::Rc};
 use std::cell::RefCell;
-use sdl2::pixels::Color;
+use std::error::Error;
+use std::path::Path;
+use crate::color::Color;
 
-use crate::cartridge::{Mapper,MirrorMode};
+use serde::{Deserialize, Serialize};
+
+use crate::cartridge::{Mapper,MirrorMode,Region};
+use crate::savestate::PpuState;
 
 pub const SCREEN_WIDTH: usize = 256;
 pub const SCREEN_HEIGHT: usize = 240;
+//4 bytes per pixel, RGBA
+pub const FRAME_BUFFER_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT * 4;
+pub static BLACK_FRAME: [u8; FRAME_BUFFER_SIZE] = {
+    let mut frame = [0; FRAME_BUFFER_SIZE];
+    let mut alpha = 3;
+    while alpha < FRAME_BUFFER_SIZE {
+        frame[alpha] = 255;
+        alpha += 4;
+    }
+    frame
+};
 pub const SCANLINE_DOTS: u32 = 256;
-pub const SCANLINE_END_CYCLE : u32 = 340;
-pub(self) enum PPUPhase {
+//scanlines are 341 dots long, 0 to 340, in both regions
+pub const SCANLINE_END_CYCLE : u32 = 341;
+#[derive(Clone, Copy)]
+enum PPUPhase {
     PreRender,
     Render,
     PostRender,
@@ -17,13 +35,13 @@ pub(self) enum PPUPhase {
 }
 
 #[repr(u8)]
-pub(self) enum StatusFlags {
+enum StatusFlags {
     VBlank = 1 << 7,
     SpriteZeroHit = 1 << 6,
     SpriteOverflow = 1 << 5
 }
 #[repr(u8)]
-pub(self) enum ContolFlags {
+enum ContolFlags {
     GenerateInterrupt = 0x80,
     TallSprites = 0x20,
     BgPage = 0x10,
@@ -31,12 +49,15 @@ pub(self) enum ContolFlags {
 }
 
 #[repr(u8)]
-pub(self) enum MaskFlags {
+enum MaskFlags {
     GreyScale = 1,
     ShowEdgeBG = 2,
     ShowEdgeSprites = 4,
     ShowBackground = 8,
-    ShowSprites = 0x10
+    ShowSprites = 0x10,
+    EmphasizeRed = 0x20,
+    EmphasizeGreen = 0x40,
+    EmphasizeBlue = 0x80
 }
 
 impl BitAnd<u8> for StatusFlags{
@@ -88,7 +109,7 @@ impl BitAndAssign<MaskFlags> for u8 {
     }
 }
 
-
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PPURegisters {
     pub control: u8,
     pub mask: u8,
@@ -104,6 +125,8 @@ pub struct PPURegisters {
     pub vram_addr: u16,
     pub tmp_vram_addr: u16,
     pub data_buffer: u8,
+    //last value driven onto the cpu-ppu data bus, write only registers read back as this
+    pub ppu_bus_latch: u8,
 }
 
 impl PPURegisters {
@@ -123,6 +146,7 @@ impl PPURegisters {
             vram_addr: 0,
             tmp_vram_addr: 0,
             data_buffer: 0,
+            ppu_bus_latch: 0,
         }
     }
     pub fn reset(&mut self) {
@@ -140,7 +164,53 @@ impl PPURegisters {
         self.vram_addr = 0;
         self.tmp_vram_addr = 0;
         self.data_buffer = 0;
+        self.ppu_bus_latch = 0;
+    }
+}
+
+//sprites found so far for the next scanline
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpriteEvaluationState {
+    //OAM indices of up to 8 sprites
+    secondary_oam: Vec<u8>,
+    next_sprite: usize,
+    //byte of the entry read as its y, only drifts from 0 once secondary OAM is full
+    byte_offset: usize,
+    done: bool,
+}
+
+impl SpriteEvaluationState {
+    fn new() -> Self {
+        SpriteEvaluationState {
+            secondary_oam: Vec::with_capacity(8),
+            next_sprite: 0,
+            byte_offset: 0,
+            done: true,
+        }
     }
+    fn start(&mut self, oam_addr: u8) {
+        self.secondary_oam.clear();
+        self.next_sprite = (oam_addr / 4) as usize;
+        self.byte_offset = 0;
+        self.done = false;
+    }
+}
+
+//one OAM entry decoded for the sprite viewer
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteInfo {
+    pub index: u8,
+    pub x: u8,
+    pub y: u8,
+    //raw bytes as stored in OAM, y, tile, attributes and x
+    pub oam: [u8; 4],
+    //8 pixels wide and 8 or 16 tall with flips applied, transparent pixels show the backdrop
+    pub pixels: [Color; 8 * 16],
+    pub height: u8,
+    pub palette: u8,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub behind_background: bool,
 }
 
 pub struct PPU {
@@ -149,49 +219,82 @@ pub struct PPU {
     vram: Vec<u8>,
     palette_ram: [u8; 32],
     pub oam_ram: [u8; 256],
-    back_buffer: Box<[Color; SCREEN_HEIGHT * SCREEN_WIDTH]>,
-    pub frame_buffer: Box<[Color; SCREEN_HEIGHT * SCREEN_WIDTH]>,
+    //RGBA bytes, laid out the same as the ui texture so a frame can be copied straight in
+    back_buffer: Box<[u8; FRAME_BUFFER_SIZE]>,
+    pub frame_buffer: Box<[u8; FRAME_BUFFER_SIZE]>,
     background_priority: Box<[bool; SCREEN_HEIGHT * SCREEN_WIDTH]>,
     scanline: u32,
     scanline_cycle: u32,
     current_phase : PPUPhase,
     even_frame:bool,
-    line_sprites:Vec<u8>
+    line_sprites:Vec<u8>,
+    sprite_evaluation: SpriteEvaluationState,
+    region: Region,
+    //dots run since power on, and the dot the vblank flag was last set on
+    cycle: u64,
+    vblank_set_cycle: u64,
+    //RGBA for every color under every combination of the PPUMASK emphasis bits, see build_palette_table
+    palette_table: Box<[[u8; 4]; 512]>,
+    //address line A12 on the last dot, and how many dots it has been low for
+    a12_high: bool,
+    a12_low_dots: u32,
 }
 
 impl PPU {
     pub fn new() -> Self {
         PPU {
             registers: Rc::new(RefCell::new(PPURegisters::new())),
-            vram: vec![0; 2048],
+            //only four screen carts use the upper 2KB, which is on the cartridge on real hardware
+            vram: vec![0; 4096],
             palette_ram: [0; 32],
             oam_ram: [0; 256],
-            back_buffer: Box::new([Color::BLACK; SCREEN_HEIGHT * SCREEN_WIDTH]),
-            frame_buffer: Box::new([Color::BLACK; SCREEN_HEIGHT * SCREEN_WIDTH]),
+            back_buffer: Box::new(BLACK_FRAME),
+            frame_buffer: Box::new(BLACK_FRAME),
             background_priority: Box::new([false; SCREEN_HEIGHT * SCREEN_WIDTH]),
             scanline: 0,
             scanline_cycle: 0,
             current_phase:PPUPhase::PreRender,
             even_frame:true,
-            line_sprites:Vec::with_capacity(8)
+            line_sprites:Vec::with_capacity(8),
+            sprite_evaluation: SpriteEvaluationState::new(),
+            region: Region::Ntsc,
+            cycle: 0,
+            vblank_set_cycle: u64::MAX,
+            palette_table: Box::new(build_palette_table(&NES_COLOR_PALETTE)),
+            a12_high: false,
+            a12_low_dots: 0,
         }
     }
     pub fn reset(&mut self) {
         self.registers.borrow_mut().reset();
         self.vram.fill(0);
         self.oam_ram.fill(0);
-        self.frame_buffer.fill(Color::BLACK);
+        *self.frame_buffer = BLACK_FRAME;
         self.background_priority.fill(false);
         self.scanline = 0;
         self.scanline_cycle = 0;
+        self.a12_high = false;
+        self.a12_low_dots = 0;
     }
+    //advances a single dot, Bus::tick_ppu calls this once for every dot an instruction took
     pub fn step(
         &mut self,
         mapper: &mut Mapper,
         nmi: &mut bool,
-        _irq: &mut bool
+        irq: &mut bool
     ){
         use PPUPhase::*;
+        self.cycle += 1;
+
+        //A12 stays low outside of rendering, $2006/$2007 accesses aren't tracked
+        let a12 = self.rendering_enabled()
+            && matches!(self.current_phase, PreRender | Render)
+            && self.a12_level();
+        if a12 && !self.a12_high {
+            mapper.notify_a12_rise(self.a12_low_dots);
+        }
+        self.a12_low_dots = if a12 { 0 } else { self.a12_low_dots.saturating_add(1) };
+        self.a12_high = a12;
         
         match self.current_phase {
             PreRender=>{
@@ -200,16 +303,13 @@ impl PPU {
                     let mut reg = self.registers.borrow_mut();
                     reg.status &= !(VBlank | SpriteZeroHit);
                 }
-                else if self.scanline_cycle == SCANLINE_DOTS + 2 && 
-                    self.get_mask_flag(MaskFlags::ShowBackground) &&
-                    self.get_mask_flag(MaskFlags::ShowSprites) {
+                else if self.scanline_cycle == SCANLINE_DOTS + 2 && self.rendering_enabled() {
                         let mut reg = self.registers.borrow_mut();
                         let t = reg.tmp_vram_addr;
                         reg.vram_addr &= !0x41F;
                         reg.vram_addr |= t & 0x41F;
                 }
-                else if (281..=304).contains(&self.scanline_cycle) && self.get_mask_flag(MaskFlags::ShowBackground)
-                && self.get_mask_flag(MaskFlags::ShowSprites) {
+                else if (281..=304).contains(&self.scanline_cycle) && self.rendering_enabled() {
                     let mut reg = self.registers.borrow_mut();
                     let t = reg.tmp_vram_addr;
                     reg.vram_addr &= !0x7BE0;
@@ -233,38 +333,53 @@ impl PPU {
                     let mut sprite_palette_idx = 0;
                     let mut sprite_foreground = false;
                     
+                    let x_fine = (self.registers.borrow().fine_x + x as u8) % 8;
                     if self.get_mask_flag(MaskFlags::ShowBackground) {
-                        let x_fine = (self.registers.borrow().scroll_x + x as u8) % 8;
 
                         if self.get_mask_flag(MaskFlags::ShowEdgeBG) || x >= 8 {
-                            let mut addr = 0x2000 | (vram_addr & 0x0FFF);
-                            let tile = self.read(mapper,addr);
+                            let nametable_addr = 0x2000 | (vram_addr & 0x0FFF);
+                            let tile = self.read(mapper,nametable_addr);
 
-                            addr = tile as u16 * 16 + ((vram_addr >> 12) & 0x07);
+                            let mut addr = tile as u16 * 16 + ((vram_addr >> 12) & 0x07);
                             addr |= self.get_bg_page();
 
-                            let mut bg_color = (self.read(mapper,addr) >> (7 ^ x_fine)) & 1;
-                            bg_color |= ((self.read(mapper,addr + 8) >> (7 ^ x_fine)) & 1) << 1;
+                            let column = (x + self.registers.borrow().fine_x as u32) / 8;
+                            let board_tile = mapper.background_tile(nametable_addr, addr, column, y);
+                            let [lo, hi] = board_tile.map_or_else(|| [self.read(mapper,addr), self.read(mapper,addr + 8)], |tile| tile.pattern);
+                            let mut bg_color = (lo >> (7 ^ x_fine)) & 1;
+                            bg_color |= ((hi >> (7 ^ x_fine)) & 1) << 1;
+                            mapper.notify_chr_fetch(addr + 8);
 
                             self.background_priority[screen_coor] = bg_color != 0;
 
-                            addr = 0x23C0 | (vram_addr & 0x0C00) | ((vram_addr >> 4) & 0x38) | ((vram_addr >> 2) & 0x07);
+                            let palette_idx = match board_tile.and_then(|tile| tile.palette) {
+                                Some(palette) => palette,
+                                None => {
+                                    addr = 0x23C0 | (vram_addr & 0x0C00) | ((vram_addr >> 4) & 0x38) | ((vram_addr >> 2) & 0x07);
 
-                            let attribute = self.read(mapper,addr);
-                            let shift = (((vram_addr >> 4) & 0x04) | (vram_addr & 0x02)) as u8;
+                                    let attribute = self.read(mapper,addr);
+                                    let shift = (((vram_addr >> 4) & 0x04) | (vram_addr & 0x02)) as u8;
 
-                            let palette_idx = (attribute >> shift) & 0x03;
-                            self.back_buffer[screen_coor] = self.fetch_background_color(bg_color, palette_idx);
+                                    (attribute >> shift) & 0x03
+                                }
+                            };
+                            self.put_pixel(screen_coor, self.fetch_background_color(bg_color, palette_idx));
                         }
-                        if x_fine == 7 {
-                            let mut reg = self.registers.borrow_mut();
-                            if (reg.vram_addr & 0x1F) == 31 {
-                                reg.vram_addr &= !0x1F;
-                                reg.vram_addr ^= 0x0400;
-                            }
-                            else {
-                                reg.vram_addr += 1;
-                            }
+                        else {
+                            //clipped pixels are transparent, so they show the backdrop and can't trigger sprite zero hit
+                            self.background_priority[screen_coor] = false;
+                            self.put_pixel(screen_coor, self.fetch_background_color(0, 0));
+                        }
+                    }
+                    //coarse x moves on with either layer enabled, so scroll writes land the same way when only sprites are shown
+                    if x_fine == 7 && self.rendering_enabled() {
+                        let mut reg = self.registers.borrow_mut();
+                        if (reg.vram_addr & 0x1F) == 31 {
+                            reg.vram_addr &= !0x1F;
+                            reg.vram_addr ^= 0x0400;
+                        }
+                        else {
+                            reg.vram_addr += 1;
                         }
                     }
 
@@ -309,6 +424,7 @@ impl PPU {
 
                             sprite_color |= (self.read(mapper,addr) >> x_shift) & 0x01;
                             sprite_color |= ((self.read(mapper,addr + 8) >> x_shift) & 0x01) << 1;
+                            mapper.notify_chr_fetch(addr + 8);
 
                             if sprite_color == 0 {
                                 continue;
@@ -316,8 +432,9 @@ impl PPU {
                             sprite_palette_idx = attribute & 0x03;
                             sprite_foreground = (attribute & 0x20) == 0;
 
+                            //the hit is never flagged on the last dot of the line
                             if !self.get_status_flag(StatusFlags::SpriteZeroHit) && self.get_mask_flag(MaskFlags::ShowBackground) && idx == 0
-                            && self.background_priority[screen_coor] && sprite_color != 0 {
+                            && self.background_priority[screen_coor] && sprite_color != 0 && x != 255 {
                                 let mut reg = self.registers.borrow_mut();
                                 reg.status |= StatusFlags::SpriteZeroHit as u8;
                             } 
@@ -327,14 +444,14 @@ impl PPU {
                         if !self.background_priority[screen_coor] && sprite_color != 0 || (
                             self.background_priority[screen_coor] && sprite_color != 0 && sprite_foreground
                         ) {
-                            self.back_buffer[screen_coor] = self.fetch_sprite_color(sprite_color, sprite_palette_idx);
+                            self.put_pixel(screen_coor, self.fetch_sprite_color(sprite_color, sprite_palette_idx));
                         }
                         else if !self.background_priority[screen_coor] && sprite_color == 0 {
-                            self.back_buffer[screen_coor] = self.fetch_background_color(0, 0);
+                            self.put_pixel(screen_coor, self.fetch_background_color(0, 0));
                         }
                     }
                 }
-                else if self.scanline_cycle == SCANLINE_DOTS + 1 && self.get_mask_flag(MaskFlags::ShowBackground) {
+                else if self.scanline_cycle == SCANLINE_DOTS + 1 && self.rendering_enabled() {
                     let mut reg = self.registers.borrow_mut();
                     if (reg.vram_addr & 0x7000) != 0x7000 {
                         reg.vram_addr += 0x1000;
@@ -354,7 +471,8 @@ impl PPU {
                         reg.vram_addr = (reg.vram_addr & !0x03E0) | (y << 5);
                     }
                 }
-                else if self.scanline_cycle == SCANLINE_DOTS + 2 && self.get_mask_flag(MaskFlags::ShowBackground) && self.get_mask_flag(MaskFlags::ShowSprites)
+                //scroll written to t during the line, x through $2005 or a first $2006 write, takes effect from the next line
+                else if self.scanline_cycle == SCANLINE_DOTS + 2 && self.rendering_enabled()
                 {
                     let mut reg = self.registers.borrow_mut();
                     let t = reg.tmp_vram_addr;
@@ -362,24 +480,22 @@ impl PPU {
                     reg.vram_addr |= t & 0x41F;
                 }
 
+                /*
+                    Secondary OAM is cleared over dots 1-64, then one OAM entry is
+                    evaluated every other dot from 65 on. The sprites found are
+                    drawn on the next scanline.
+                */
+                if self.scanline_cycle == 64 {
+                    let oam_addr = self.registers.borrow().oam_addr;
+                    self.sprite_evaluation.start(oam_addr);
+                }
+                else if (65..=SCANLINE_DOTS).contains(&self.scanline_cycle) && self.scanline_cycle % 2 == 1 {
+                    self.evaluate_next_sprite();
+                }
+
                 if self.scanline_cycle >= SCANLINE_END_CYCLE {
                     self.line_sprites.clear();
-
-                    let range = self.get_sprite_height() as i32;
-                    let mut j = 0;
-                    let oam_addr = self.registers.borrow().oam_addr;
-                    for i in (oam_addr/4) as usize..64 {
-                        let diff = self.scanline as i32 - self.oam_ram[i * 4] as i32;
-                        if 0 <= diff && diff < range {
-                            if j >= 8 {
-                                let mut reg = self.registers.borrow_mut();
-                                reg.status |= StatusFlags::SpriteOverflow as u8;
-                                break;
-                            }
-                            self.line_sprites.push(i as u8);
-                            j += 1;
-                        }
-                    }
+                    std::mem::swap(&mut self.line_sprites, &mut self.sprite_evaluation.secondary_oam);
 
                     self.scanline += 1;
                     self.scanline_cycle = 0;
@@ -398,11 +514,18 @@ impl PPU {
                 }
             }
             VBlank=>{
+                /*
+                    The NMI goes out a dot after the flag is set, so a $2002 read
+                    on the same dot can clear the flag before it does.
+                */
                 if self.scanline_cycle == 1 && self.scanline == 241 {
                     let mut reg = self.registers.borrow_mut();
                     reg.status |= StatusFlags::VBlank as u8;
-
-                    if (reg.control & ContolFlags::GenerateInterrupt as u8) != 0 {
+                    self.vblank_set_cycle = self.cycle;
+                }
+                else if self.scanline_cycle == 2 && self.scanline == 241 {
+                    let reg = self.registers.borrow();
+                    if (reg.status & StatusFlags::VBlank) != 0 && (reg.control & ContolFlags::GenerateInterrupt as u8) != 0 {
                         *nmi = true;
                     }
                 }
@@ -410,7 +533,7 @@ impl PPU {
                     self.scanline += 1;
                     self.scanline_cycle = 0;
                 }
-                if self.scanline >= 261 {
+                if self.scanline >= self.region.pre_render_scanline() {
                     self.current_phase = PreRender;
                     self.scanline = 0;
                     self.even_frame = !self.even_frame;
@@ -418,6 +541,11 @@ impl PPU {
             }
         }
         self.scanline_cycle += 1;
+
+        //mapper IRQs are level triggered, they stay asserted until acknowledged
+        if mapper.irq_pending() {
+            *irq = true;
+        }
     }
 
 
@@ -430,38 +558,45 @@ impl PPU {
                 let mirrored = Self::mirror_vram_addr(mapper, addr) as usize;
                 self.vram[mirrored as usize]
             }
-            0x3F00..=0x3FFF => {
-                let mut mirrored = addr & 0x1F;
-                if mirrored >= 0x10 && (mirrored % 4) == 0 {
-                    mirrored -= 0x10;
-                }
-                self.palette_ram[mirrored as usize]
-            }
+            0x3F00..=0x3FFF => self.palette_ram[Self::palette_index(addr)],
             _ => 0,
         }
     }
     pub fn read_register(&self, mapper: &Mapper, addr: u16) -> u8 {
-        match addr {
-            0x2000 => self.registers.borrow().control,
+        let result = match addr {
             0x2002 => {
                 let mut registers = self.registers.borrow_mut();
-                let result = registers.status;
+                //only the top 3 bits of status are driven, the rest is whatever was left on the bus
+                let mut result = (registers.status & 0xE0) | (registers.ppu_bus_latch & 0x1F);
+                //reading on the dot vblank starts sees it clear, and still clears it so no NMI happens
+                if self.cycle == self.vblank_set_cycle {
+                    result &= !(StatusFlags::VBlank as u8);
+                }
                 registers.status &= !(0x80 | 0x40 | 0x20);
                 registers.address_latch = false;
                 result
             }
+            0x2004 if self.clearing_secondary_oam() => 0xFF,
             0x2004 => self.oam_ram[self.registers.borrow().oam_addr as usize],
-            0x2006 => (self.registers.borrow().ppu_addr >> 8) as u8,
             0x2007 => {
-                let mut result = self.registers.borrow().data_buffer;
                 let ppu_addr = self.registers.borrow().ppu_addr;
                 let control = self.registers.borrow().control;
 
-                self.registers.borrow_mut().data_buffer = self.read(mapper, ppu_addr);
-
-                if ppu_addr >= 0x3F00 {
-                    result = self.registers.borrow().data_buffer;
-                }
+                /*
+                    Palette reads skip the buffer and come back straight away,
+                    with the top 2 bits left from the bus since palette entries
+                    are only 6 bits. The buffer still gets filled, but with the
+                    nametable byte the palette sits on top of.
+                */
+                let result = if ppu_addr & 0x3FFF >= 0x3F00 {
+                    let latch = self.registers.borrow().ppu_bus_latch;
+                    self.registers.borrow_mut().data_buffer = self.read(mapper, (ppu_addr & 0x3FFF) - 0x1000);
+                    (self.read(mapper, ppu_addr) & 0x3F) | (latch & 0xC0)
+                } else {
+                    let buffered = self.registers.borrow().data_buffer;
+                    self.registers.borrow_mut().data_buffer = self.read(mapper, ppu_addr);
+                    buffered
+                };
 
                 self.registers.borrow_mut().ppu_addr = if (control & 0x04) != 0 {
                     ppu_addr.wrapping_add(32)
@@ -471,8 +606,11 @@ impl PPU {
 
                 result
             }
-            _ => 0,
-        }
+            //$2000, $2001, $2003, $2005 and $2006 are write only
+            _ => self.registers.borrow().ppu_bus_latch,
+        };
+        self.registers.borrow_mut().ppu_bus_latch = result;
+        result
     }
     fn write(&mut self, mapper: &mut Mapper, addr: u16, val: u8) {
         let addr = addr & 0x3FFF;
@@ -485,19 +623,15 @@ impl PPU {
                 let mirrored = Self::mirror_vram_addr(mapper, addr);
                 self.vram[mirrored as usize] = val;
             }
-            0x3F00..=0x3FFF => {
-                let mut mirrored = addr & 0x1F;
-                if mirrored >= 0x10 && (mirrored % 4) == 0 {
-                    mirrored -= 0x10;
-                }
-                self.palette_ram[mirrored as usize] = val;
-            }
+            0x3F00..=0x3FFF => self.palette_ram[Self::palette_index(addr)] = val,
             _ => {}
         }
     }
     pub fn write_register(&mut self, mapper: &mut Mapper, addr: u16, val: u8) {
+        self.registers.borrow_mut().ppu_bus_latch = val;
         match addr {
             0x2000 => {
+                mapper.notify_ppu_control(val);
                 let mut reg = self.registers.borrow_mut();
                 reg.control = val;
                 // Update nametable select bits in tmp_vram_addr from PPUCTRL
@@ -561,22 +695,57 @@ impl PPU {
         }
     }
     
-    fn fetch_background_color(&self, color_idx: u8, palette_idx: u8) -> Color {
+    fn put_pixel(&mut self, screen_coor: usize, rgba: [u8; 4]) {
+        let offset = screen_coor * 4;
+        self.back_buffer[offset..offset + 4].copy_from_slice(&rgba);
+    }
+    pub fn fetch_background_color(&self, color_idx: u8, palette_idx: u8) -> [u8; 4] {
         if color_idx == 0 {
             let bg_color_idx = self.palette_ram[0] as usize;
-            return NES_COLOR_PALETTE[bg_color_idx & 63];
+            return self.palette_color(bg_color_idx);
         }
         let palette_base = (palette_idx << 2).wrapping_add(1);
         let palette_ram_idx = palette_base.wrapping_add(color_idx.wrapping_sub(1)) as usize;
         let palette_color_idx = self.palette_ram[palette_ram_idx] as usize;
 
-        NES_COLOR_PALETTE[palette_color_idx & 63]
+        self.palette_color(palette_color_idx)
     }
-    fn fetch_sprite_color(&self, color_idx: u8, palette_idx: u8) -> Color {
+    fn fetch_sprite_color(&self, color_idx: u8, palette_idx: u8) -> [u8; 4] {
         let palette_base = 0x11 + (palette_idx << 2);
         let palette_color_idx =
             self.palette_ram[palette_base as usize + (color_idx - 1) as usize] as usize;
-        NES_COLOR_PALETTE[palette_color_idx & 63]
+        self.palette_color(palette_color_idx)
+    }
+    //the top 3 bits of PPUMASK pick which emphasis tinted block of 64 colors is used
+    fn palette_color(&self, mut palette_color_idx: usize) -> [u8; 4] {
+        //greyscale limits colors to the grey column, $00, $10, $20 and $30
+        if self.get_mask_flag(MaskFlags::GreyScale) {
+            palette_color_idx &= 0x30;
+        }
+        let emphasis = (self.registers.borrow().mask >> 5) as usize;
+        self.palette_table[(emphasis << 6) | (palette_color_idx & 63)]
+    }
+    /*
+        .pal files are RGB triplets, either the 64 base colors or 512 colors
+        holding all 8 emphasis combinations. With only 64 colors the emphasis
+        variants are approximated the same way as for the built in palette.
+    */
+    pub fn load_palette_file(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let data = std::fs::read(path)?;
+        let colors: Vec<Color> = data.chunks_exact(3).map(|rgb| Color::RGBA(rgb[0], rgb[1], rgb[2], 255)).collect();
+        match colors.len() {
+            64 if data.len() == 64 * 3 => {
+                let base: [Color; 64] = colors.try_into().unwrap();
+                *self.palette_table = build_palette_table(&base);
+            }
+            512 if data.len() == 512 * 3 => {
+                for (rgba, color) in self.palette_table.iter_mut().zip(colors) {
+                    *rgba = [color.r, color.g, color.b, color.a];
+                }
+            }
+            _ => return Err(format!("palette files must be 192 or 1536 bytes, got {}", data.len()).into()),
+        }
+        Ok(())
     }
     fn mirror_vram_addr(mapper: &Mapper, addr: u16) -> u16 {
         let offset = addr & 0xFFF;
@@ -590,6 +759,7 @@ impl PPU {
             Horizontal => ((nt_idx / 2) * 0x400 + inner_offset) as u16,
             SingleScreenA => inner_offset as u16,
             SingleScreenB => (0x400 + inner_offset) as u16,
+            FourScreen => offset,
         }
     }
     fn get_status_flag(&self,flag:StatusFlags)->bool {
@@ -598,13 +768,210 @@ impl PPU {
     fn get_mask_flag(&self, flag : MaskFlags) -> bool {
         (self.registers.borrow().mask & flag) != 0
     }
+    pub fn save_state(&self) -> PpuState {
+        PpuState {
+            registers: self.registers.borrow().clone(),
+            vram: self.vram.clone(),
+            palette_ram: self.palette_ram.to_vec(),
+            oam_ram: self.oam_ram.to_vec(),
+            scanline: self.scanline,
+            scanline_cycle: self.scanline_cycle,
+            phase: self.current_phase as u8,
+            even_frame: self.even_frame,
+            line_sprites: self.line_sprites.clone(),
+            sprite_evaluation: self.sprite_evaluation.clone(),
+            cycle: self.cycle,
+            vblank_set_cycle: self.vblank_set_cycle,
+        }
+    }
+    pub fn load_state(&mut self, state: PpuState) {
+        use PPUPhase::*;
+        *self.registers.borrow_mut() = state.registers;
+        self.vram = state.vram;
+        self.palette_ram.copy_from_slice(&state.palette_ram);
+        self.oam_ram.copy_from_slice(&state.oam_ram);
+        self.scanline = state.scanline;
+        self.scanline_cycle = state.scanline_cycle;
+        self.current_phase = match state.phase {
+            0 => PreRender,
+            1 => Render,
+            2 => PostRender,
+            _ => VBlank,
+        };
+        self.even_frame = state.even_frame;
+        self.line_sprites = state.line_sprites;
+        self.sprite_evaluation = state.sprite_evaluation;
+        self.cycle = state.cycle;
+        self.vblank_set_cycle = state.vblank_set_cycle;
+    }
+    /*
+        Level of address line A12 on this dot of a rendering scanline. Every
+        tile takes 8 dots, a nametable and an attribute fetch that keep A12
+        low followed by the two pattern plane fetches. Those are on the
+        background's pattern table over dots 1-256 and 321-336 and on the
+        sprites' over 257-320. The renderer doesn't fetch on this schedule,
+        so the level is worked out from where the fetches would be.
+    */
+    fn a12_level(&self) -> bool {
+        let dot = self.scanline_cycle;
+        let pattern_fetch = dot > 0 && (dot - 1) % 8 >= 4;
+        match dot {
+            1..=256 | 321..=336 => pattern_fetch && self.get_bg_page() != 0,
+            257..=320 => pattern_fetch && self.sprite_fetch_a12((dot - 257) as usize / 8),
+            _ => false,
+        }
+    }
+    //8x16 sprites pick their pattern table by tile, unused slots fetch tile $FF
+    fn sprite_fetch_a12(&self, slot: usize) -> bool {
+        if self.get_sprite_height() == 8 {
+            return self.get_sprite_page() != 0;
+        }
+        let tile = match self.sprite_evaluation.secondary_oam.get(slot) {
+            Some(&sprite) => self.oam_ram[sprite as usize * 4 + 1],
+            None => 0xFF,
+        };
+        (tile & 1) != 0
+    }
+    fn rendering_enabled(&self) -> bool {
+        self.get_mask_flag(MaskFlags::ShowBackground) || self.get_mask_flag(MaskFlags::ShowSprites)
+    }
+    fn evaluate_next_sprite(&mut self) {
+        let range = self.get_sprite_height() as i32;
+        let eval = &mut self.sprite_evaluation;
+        if eval.done {
+            return;
+        }
+        let i = eval.next_sprite;
+        let diff = self.scanline as i32 - self.oam_ram[i * 4 + eval.byte_offset] as i32;
+        let in_range = 0 <= diff && diff < range;
+        if eval.secondary_oam.len() < 8 {
+            if in_range {
+                eval.secondary_oam.push(i as u8);
+            }
+        }
+        else if in_range {
+            self.registers.borrow_mut().status |= StatusFlags::SpriteOverflow as u8;
+            eval.done = true;
+            return;
+        }
+        else {
+            /*
+                The hardware bug: with 8 sprites found, a miss increments the byte
+                offset along with the sprite index. Later entries get their tile,
+                attributes or x compared as if they were y, so overflow is both
+                missed and reported falsely.
+            */
+            eval.byte_offset = (eval.byte_offset + 1) & 3;
+        }
+        eval.next_sprite += 1;
+        eval.done = eval.next_sprite >= 64;
+    }
+    //while secondary OAM is being cleared reads of $2004 see the $FF being written
+    fn clearing_secondary_oam(&self) -> bool {
+        matches!(self.current_phase, PPUPhase::Render)
+            && self.rendering_enabled()
+            && (1..=64).contains(&self.scanline_cycle)
+    }
+    /*
+        Color of (x, y) in the frame being drawn, if the beam went past it no
+        more than `scanlines` lines ago. A light gun only sees a spot while
+        it's freshly lit, so older pixels and ones not drawn yet are None.
+    */
+    pub fn recently_drawn_pixel(&self, x: usize, y: usize, scanlines: u32) -> Option<[u8; 4]> {
+        if matches!(self.current_phase, PPUPhase::PreRender) || x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+            return None;
+        }
+        let line = self.scanline as usize;
+        let drawn = line > y || (line == y && self.scanline_cycle as usize > x + 1);
+        if !drawn || line - y > scanlines as usize {
+            return None;
+        }
+        let offset = (y * SCREEN_WIDTH + x) * 4;
+        self.back_buffer[offset..offset + 4].try_into().ok()
+    }
+    //scanline and dot the next step will run
+    pub fn position(&self) -> (u32, u32) {
+        (self.scanline, self.scanline_cycle)
+    }
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+    //the PAL PPU never skips a dot on odd frames
     fn even_frame_adjustment(&self)->u32 {
-        if !self.even_frame && self.get_mask_flag(MaskFlags::ShowBackground) && self.get_mask_flag(MaskFlags::ShowSprites){
+        if self.region == Region::Ntsc && !self.even_frame && self.get_mask_flag(MaskFlags::ShowBackground) && self.get_mask_flag(MaskFlags::ShowSprites){
             1
         } else {
             0
         }
     }
+    /*
+        $3F10, $3F14, $3F18 and $3F1C have no storage of their own, they are
+        the same bytes as $3F00, $3F04, $3F08 and $3F0C for reads and writes
+        alike. A sprite's color 0 is transparent so the sprite copies are
+        never drawn, but writing $3F10 does change the backdrop.
+    */
+    fn palette_index(addr: u16) -> usize {
+        let idx = (addr & 0x1F) as usize;
+        if idx >= 0x10 && idx.is_multiple_of(4) { idx - 0x10 } else { idx }
+    }
+    //palette ram as the PPU sees it
+    pub fn palette_entries(&self) -> [u8; 32] {
+        std::array::from_fn(|i| self.palette_ram[Self::palette_index(i as u16)])
+    }
+    //colors of palette ram ignoring greyscale and emphasis, NES_COLOR_PALETTE unless a palette file was loaded
+    pub fn render_palettes(&self) -> [Color; 32] {
+        self.palette_entries().map(|entry| rgba_color(self.palette_table[(entry & 0x3F) as usize]))
+    }
+    //decodes every OAM entry the way step draws sprites, with the current sprite page and size
+    pub fn render_oam_sprites(&self, mapper: &Mapper) -> Vec<SpriteInfo> {
+        let sprite_height = self.get_sprite_height();
+        let backdrop = rgba_color(self.fetch_background_color(0, 0));
+        self.oam_ram
+            .chunks_exact(4)
+            .enumerate()
+            .map(|(index, entry)| {
+                let (y, tile, attribute, x) = (entry[0], entry[1] as u16, entry[2], entry[3]);
+                let mut pixels = [Color::BLACK; 8 * 16];
+                for row in 0..sprite_height {
+                    let mut y_offset = row;
+                    if (attribute & 0x80) != 0 {
+                        y_offset ^= sprite_height - 1;
+                    }
+                    let addr = if sprite_height == 8 {
+                        tile * 16 + y_offset as u16 + self.get_sprite_page()
+                    } else {
+                        let tile_offset = if y_offset >= 8 { 1 } else { 0 };
+                        (((tile & 0xFE) + tile_offset) * 16 + (y_offset & 7) as u16) | ((tile & 1) << 12)
+                    };
+                    let (lo, hi) = (self.read(mapper, addr), self.read(mapper, addr + 8));
+                    for column in 0..8 {
+                        let mut x_shift = column;
+                        if (attribute & 0x40) == 0 {
+                            x_shift ^= 7;
+                        }
+                        let color = ((lo >> x_shift) & 1) | (((hi >> x_shift) & 1) << 1);
+                        pixels[row as usize * 8 + column] = if color == 0 {
+                            backdrop
+                        } else {
+                            rgba_color(self.fetch_sprite_color(color, attribute & 0x03))
+                        };
+                    }
+                }
+                SpriteInfo {
+                    index: index as u8,
+                    x,
+                    y,
+                    oam: [entry[0], entry[1], entry[2], entry[3]],
+                    pixels,
+                    height: sprite_height as u8,
+                    palette: attribute & 0x03,
+                    flip_horizontal: (attribute & 0x40) != 0,
+                    flip_vertical: (attribute & 0x80) != 0,
+                    behind_background: (attribute & 0x20) != 0,
+                }
+            })
+            .collect()
+    }
     fn get_bg_page(&self) -> u16 {
         if self.registers.borrow().control & 0x10 == 0 {
             0
@@ -629,7 +996,40 @@ impl PPU {
     }
 }
 
-const NES_COLOR_PALETTE: [Color; 64] = [
+/*
+    Emphasis darkens the channels that aren't emphasized, index bit 0 is red,
+    bit 1 green and bit 2 blue, matching PPUMASK bits 5-7.
+*/
+pub fn build_emphasis_palettes(base: &[Color; 64]) -> [[Color; 64]; 8] {
+    const ATTENUATION: f32 = 0.85;
+    let mut palettes = [*base; 8];
+    for (emphasis, palette) in palettes.iter_mut().enumerate() {
+        let (red, green, blue) = (emphasis & 1 != 0, emphasis & 2 != 0, emphasis & 4 != 0);
+        //each channel is dimmed once for every other channel being emphasized
+        let scale = |value: u8, dims: usize| (value as f32 * ATTENUATION.powi(dims as i32)) as u8;
+        for color in palette.iter_mut() {
+            color.r = scale(color.r, green as usize + blue as usize);
+            color.g = scale(color.g, red as usize + blue as usize);
+            color.b = scale(color.b, red as usize + green as usize);
+        }
+    }
+    palettes
+}
+
+//the 8 emphasis palettes one after another as RGBA, so a color is looked up with (emphasis << 6) | index
+pub fn build_palette_table(base: &[Color; 64]) -> [[u8; 4]; 512] {
+    let palettes = build_emphasis_palettes(base);
+    std::array::from_fn(|i| {
+        let color = palettes[i >> 6][i & 63];
+        [color.r, color.g, color.b, color.a]
+    })
+}
+
+pub fn rgba_color(rgba: [u8; 4]) -> Color {
+    Color::RGBA(rgba[0], rgba[1], rgba[2], rgba[3])
+}
+
+pub const NES_COLOR_PALETTE: [Color; 64] = [
     Color::RGBA(84, 84, 84, 255),
     Color::RGBA(0, 30, 116, 255),
     Color::RGBA(8, 16, 144, 255),
@@ -0,0 +1,64 @@
+use std::collections::VecDeque;
+
+use crate::savestate::SaveState;
+
+//10 seconds at 60 fps
+pub const DEFAULT_REWIND_CAPACITY: usize = 600;
+
+/*
+    Ring buffer of per-frame save states, the oldest state is dropped
+    once it's full. States are kept in their compressed to_bytes form,
+    a few KB each instead of roughly 20KB, so the capacity trades less
+    memory for how far back a game can be rewound.
+*/
+pub struct RewindBuffer {
+    states: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.states.len() > capacity {
+            self.states.pop_front();
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+    pub fn push(&mut self, state: SaveState) {
+        if self.capacity == 0 {
+            return;
+        }
+        let state = match state.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("Couldn't store rewind state: {e}");
+                return;
+            }
+        };
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(state);
+    }
+    pub fn pop(&mut self) -> Option<SaveState> {
+        //the bytes came from to_bytes, so decoding only fails if SaveState itself is broken
+        SaveState::from_bytes(&self.states.pop_back()?).ok()
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+
+use crate::cartridge::Region;
+use crate::config::Config;
+
+/*
+    Lookup of known dumps keyed by the PRG+CHR sha1, the same hash NesCartDB
+    lists for each cartridge. The file is a gzipped JSON object of lowercase
+    hex sha1 to RomInfo.
+
+    No game database is redistributed with RNES. tools/build_rom_db.py turns
+    a NesCartDB export into roms.json.gz next to config.toml, which is read
+    the first time a rom is looked up. Without it games are shown by their hash.
+*/
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RomInfo {
+    pub title: String,
+    pub region: Region,
+    pub mapper: u8,
+    pub has_battery: bool,
+}
+
+pub struct RomDatabase {
+    entries: HashMap<[u8; 20], RomInfo>,
+}
+
+impl RomDatabase {
+    pub fn from_gzip(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut json = String::new();
+        GzDecoder::new(bytes).read_to_string(&mut json)?;
+        let hex_entries: HashMap<String, RomInfo> = serde_json::from_str(&json)?;
+        let mut entries = HashMap::with_capacity(hex_entries.len());
+        for (hex, info) in hex_entries {
+            let sha1 = parse_sha1(&hex).ok_or_else(|| format!("bad sha1 in rom database: {hex}"))?;
+            entries.insert(sha1, info);
+        }
+        Ok(RomDatabase { entries })
+    }
+    pub fn from_file<PathLike: AsRef<Path>>(file_path: PathLike) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_gzip(&std::fs::read(file_path)?)
+    }
+    //~/.config/rnes/roms.json.gz, next to config.toml
+    pub fn default_path() -> PathBuf {
+        Config::default_path().with_file_name("roms.json.gz")
+    }
+    //empty when there's no database, a broken one is reported and treated the same way
+    pub fn load_or_empty<PathLike: AsRef<Path>>(file_path: PathLike) -> Self {
+        let file_path = file_path.as_ref();
+        if !file_path.exists() {
+            return RomDatabase { entries: HashMap::new() };
+        }
+        Self::from_file(file_path).unwrap_or_else(|err| {
+            eprintln!("Failed to read the rom database {}: {err}", file_path.display());
+            RomDatabase { entries: HashMap::new() }
+        })
+    }
+    //the user's database, read the first time a rom is looked up
+    fn installed() -> &'static RomDatabase {
+        static INSTALLED: OnceLock<RomDatabase> = OnceLock::new();
+        INSTALLED.get_or_init(|| RomDatabase::load_or_empty(Self::default_path()))
+    }
+    pub fn lookup(sha1: &[u8; 20]) -> Option<RomInfo> {
+        Self::installed().get(sha1).cloned()
+    }
+    pub fn get(&self, sha1: &[u8; 20]) -> Option<&RomInfo> {
+        self.entries.get(sha1)
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+    let mut sha1 = [0; 20];
+    for (byte, pair) in sha1.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(sha1)
+}
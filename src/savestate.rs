@@ -0,0 +1,156 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apu::{DmcChannel, FrameCounter, NoiseChannel, PulseChannel, TriangleChannel},
+    cartridge::MapperState,
+    cpu::CPU,
+    ppu::{PPURegisters, SpriteEvaluationState},
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CpuState {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub status: u8,
+    pub ir_disable: bool,
+    pub cpu_cycle: u64,
+    pub irq_vector_pending: bool,
+    pub halted: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BusState {
+    pub ram: Vec<u8>,
+    pub extra_cycles: i32,
+    pub dma_active: bool,
+    pub irq: bool,
+    pub nmi_request: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PpuState {
+    pub registers: PPURegisters,
+    pub vram: Vec<u8>,
+    pub palette_ram: Vec<u8>,
+    pub oam_ram: Vec<u8>,
+    pub scanline: u32,
+    pub scanline_cycle: u32,
+    //PPUPhase stays private to the ppu module, so it's stored by index
+    pub phase: u8,
+    pub even_frame: bool,
+    pub line_sprites: Vec<u8>,
+    pub sprite_evaluation: SpriteEvaluationState,
+    pub cycle: u64,
+    pub vblank_set_cycle: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApuState {
+    pub pulse1: PulseChannel,
+    pub pulse2: PulseChannel,
+    pub triangle: TriangleChannel,
+    pub noise: NoiseChannel,
+    pub dmc: DmcChannel,
+    pub frame_counter: FrameCounter,
+    pub cycle: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputState {
+    pub controller_state: u8,
+    pub controller_shift: u8,
+    pub controller2_state: u8,
+    pub controller2_shift: u8,
+}
+
+pub const STATE_MAGIC: &[u8; 4] = b"RNSS";
+//bump whenever the layout of SaveState changes, older states are rejected instead of misread
+pub const STATE_VERSION: u8 = 4;
+
+/*
+    Snapshot of everything needed to resume emulation.
+    The mapper is stored without its ROM data, on load the ROM of the
+    currently inserted cartridge is moved into it.
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveState {
+    //seconds since the unix epoch when it was captured, shown when a slot is loaded
+    pub saved_at: u64,
+    pub cpu: CpuState,
+    pub bus: BusState,
+    pub ppu: PpuState,
+    pub apu: ApuState,
+    pub mapper: MapperState,
+    pub input: InputState,
+}
+
+impl SaveState {
+    //only fails if the mapper can't be serialized
+    pub fn capture(cpu: &CPU) -> Result<Self, Box<dyn Error>> {
+        Ok(SaveState {
+            saved_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            cpu: cpu.save_state(),
+            bus: cpu.bus.save_state(),
+            ppu: cpu.bus.ppu.save_state(),
+            apu: cpu.bus.apu.save_state(),
+            mapper: cpu.bus.save_mapper_state()?,
+            input: cpu.bus.input.borrow().save_state(),
+        })
+    }
+    //returns false and leaves the cpu untouched if the state was made with a different mapper
+    pub fn restore(self, cpu: &mut CPU) -> bool {
+        if !cpu.bus.load_mapper_state(&self.mapper) {
+            return false;
+        }
+        cpu.load_state(self.cpu);
+        cpu.bus.load_state(self.bus);
+        cpu.bus.ppu.load_state(self.ppu);
+        cpu.bus.apu.load_state(self.apu);
+        cpu.bus.input.borrow_mut().load_state(self.input);
+        true
+    }
+    //the bincode encoding compressed with LZ4, behind STATE_MAGIC and STATE_VERSION
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut bytes = STATE_MAGIC.to_vec();
+        bytes.push(STATE_VERSION);
+        bytes.extend(lz4_flex::compress_prepend_size(&bincode::serialize(self)?));
+        Ok(bytes)
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let Some(compressed) = bytes.strip_prefix(STATE_MAGIC) else {
+            return Err("not an RNES save state".into());
+        };
+        match compressed.split_first() {
+            Some((&STATE_VERSION, compressed)) => Ok(bincode::deserialize(&lz4_flex::decompress_size_prepended(compressed)?)?),
+            Some((version, _)) => Err(format!("unsupported save state version {version}").into()),
+            None => Err("save state is truncated".into()),
+        }
+    }
+    pub fn write_to_file<PathLike: AsRef<Path>>(&self, file_path: PathLike) -> Result<(), Box<dyn Error>> {
+        std::fs::write(file_path, self.to_bytes()?)?;
+        Ok(())
+    }
+    pub fn read_from_file<PathLike: AsRef<Path>>(file_path: PathLike) -> Result<Self, Box<dyn Error>> {
+        Self::from_bytes(&std::fs::read(file_path)?)
+    }
+}
+
+//numbered from 1, on top of the quick save in state_path
+pub const SAVE_SLOTS: u8 = 8;
+
+//save states live next to the rom, like battery saves
+pub fn state_path<PathLike: AsRef<Path>>(rom_path: PathLike) -> PathBuf {
+    rom_path.as_ref().with_extension("state")
+}
+
+//slots are named by the rom's sha1 so renaming the rom doesn't lose them
+pub fn slot_path<PathLike: AsRef<Path>>(rom_path: PathLike, rom_sha1: &str, slot: u8) -> PathBuf {
+    rom_path.as_ref().with_file_name(format!("{rom_sha1}_slot{slot}.rnes"))
+}
@@ -0,0 +1,48 @@
+use crate::color::Color;
+
+use crate::ppu::SpriteInfo;
+
+//64 sprites in 8 rows of 8, each cell fits an 8x16 sprite with a 1 pixel gap
+const CELL_WIDTH: usize = 9;
+const CELL_HEIGHT: usize = 17;
+const GRID_COLUMNS: usize = 8;
+pub const SPRITE_VIEW_WIDTH: usize = CELL_WIDTH * GRID_COLUMNS;
+pub const SPRITE_VIEW_HEIGHT: usize = CELL_HEIGHT * 8;
+
+pub type SpriteGridPixels = Box<[Color; SPRITE_VIEW_WIDTH * SPRITE_VIEW_HEIGHT]>;
+
+//sprites this far down are never drawn, games park unused sprites there
+const OFFSCREEN_Y: u8 = 240;
+
+//lays the decoded sprites out in a grid, sprites below the screen are greyed out
+pub fn sprite_grid(sprites: &[SpriteInfo]) -> SpriteGridPixels {
+    let mut pixels = Box::new([Color::BLACK; SPRITE_VIEW_WIDTH * SPRITE_VIEW_HEIGHT]);
+    for (i, sprite) in sprites.iter().enumerate().take(64) {
+        let cell_x = (i % GRID_COLUMNS) * CELL_WIDTH;
+        let cell_y = (i / GRID_COLUMNS) * CELL_HEIGHT;
+        for row in 0..sprite.height as usize {
+            for column in 0..8 {
+                let mut color = sprite.pixels[row * 8 + column];
+                if sprite.y >= OFFSCREEN_Y {
+                    color = greyed_out(color);
+                }
+                pixels[(cell_y + row) * SPRITE_VIEW_WIDTH + cell_x + column] = color;
+            }
+        }
+    }
+    pixels
+}
+
+//index of the sprite under a pixel of the grid, None for the gaps between cells
+pub fn sprite_at(x: usize, y: usize) -> Option<usize> {
+    if x >= SPRITE_VIEW_WIDTH || y >= SPRITE_VIEW_HEIGHT || x % CELL_WIDTH == 8 || y % CELL_HEIGHT == 16 {
+        return None;
+    }
+    Some((y / CELL_HEIGHT) * GRID_COLUMNS + x / CELL_WIDTH)
+}
+
+fn greyed_out(color: Color) -> Color {
+    let luma = (color.r as u32 * 3 + color.g as u32 * 6 + color.b as u32) / 10;
+    let grey = (luma / 2) as u8;
+    Color::RGBA(grey, grey, grey, color.a)
+}
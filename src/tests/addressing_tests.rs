@@ -0,0 +1,103 @@
+/*
+    Property tests for AddressMode::decode on the real cpu. The code and
+    operands sit in $0400-$07FF and any pointers in $0000-$03FF, so
+    everything the decoder reads is plain ram.
+*/
+use proptest::prelude::*;
+
+use crate::cpu::{AddressMode, CPU};
+
+fn cpu_at(pc: u16, operand: &[u8]) -> CPU {
+    let mut cpu = CPU::init();
+    cpu.pc = pc;
+    for (i, byte) in operand.iter().enumerate() {
+        cpu.bus.write(pc + i as u16, *byte);
+    }
+    cpu
+}
+
+fn page_crossed(a: u16, b: u16) -> i32 {
+    ((a & 0xFF00) != (b & 0xFF00)) as i32
+}
+
+proptest! {
+    #[test]
+    fn zero_page_indexed_stays_in_zero_page(pc in 0x0400u16..0x07FF, base in any::<u8>(), index in any::<u8>()) {
+        let mut cpu = cpu_at(pc, &[base]);
+        cpu.x = index;
+        let (addr, penalty) = AddressMode::ZeroPageX.decode(&mut cpu);
+        prop_assert_eq!(addr, base.wrapping_add(index) as u16);
+        prop_assert_eq!(penalty, 0);
+        prop_assert_eq!(cpu.pc, pc + 1);
+
+        let mut cpu = cpu_at(pc, &[base]);
+        cpu.y = index;
+        let (addr, _) = AddressMode::ZeroPageY.decode(&mut cpu);
+        prop_assert!(addr <= 0xFF);
+        prop_assert_eq!(addr, base.wrapping_add(index) as u16);
+    }
+
+    #[test]
+    fn absolute_indexed_adds_the_register(pc in 0x0400u16..0x07FE, base in any::<u16>(), index in any::<u8>()) {
+        let operand = base.to_le_bytes();
+
+        let mut cpu = cpu_at(pc, &operand);
+        cpu.x = index;
+        let (addr, penalty) = AddressMode::AbsoluteX.decode(&mut cpu);
+        prop_assert_eq!(addr, base.wrapping_add(index as u16));
+        prop_assert_eq!(penalty, page_crossed(base, addr));
+        prop_assert_eq!(cpu.pc, pc + 2);
+
+        let mut cpu = cpu_at(pc, &operand);
+        cpu.y = index;
+        let (addr, penalty) = AddressMode::AbsoluteY.decode(&mut cpu);
+        prop_assert_eq!(addr, base.wrapping_add(index as u16));
+        prop_assert_eq!(penalty, page_crossed(base, addr));
+    }
+
+    #[test]
+    fn indirect_x_pointer_wraps_in_zero_page(pc in 0x0400u16..0x07FF, base in any::<u8>(), index in any::<u8>(), target in any::<u16>()) {
+        let mut cpu = cpu_at(pc, &[base]);
+        cpu.x = index;
+        let ptr = base.wrapping_add(index);
+        cpu.bus.write(ptr as u16, target as u8);
+        cpu.bus.write(ptr.wrapping_add(1) as u16, (target >> 8) as u8);
+        let (addr, _) = AddressMode::IndirectX.decode(&mut cpu);
+        prop_assert_eq!(addr, target);
+    }
+
+    #[test]
+    fn indirect_y_adds_y_after_the_lookup(pc in 0x0400u16..0x07FF, ptr in any::<u8>(), index in any::<u8>(), base in any::<u16>()) {
+        let mut cpu = cpu_at(pc, &[ptr]);
+        cpu.y = index;
+        cpu.bus.write(ptr as u16, base as u8);
+        cpu.bus.write(ptr.wrapping_add(1) as u16, (base >> 8) as u8);
+        let (addr, penalty) = AddressMode::IndirectY.decode(&mut cpu);
+        prop_assert_eq!(addr, base.wrapping_add(index as u16));
+        prop_assert_eq!(penalty, page_crossed(base, addr));
+    }
+
+    #[test]
+    fn indirect_high_byte_never_leaves_the_page(pc in 0x0400u16..0x07FE, ptr in 0x0000u16..0x0400, target in any::<u16>()) {
+        let mut cpu = cpu_at(pc, &ptr.to_le_bytes());
+        //the 6502 bug, the high byte of a pointer at $xxFF comes from $xx00
+        let hi_addr = (ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF);
+        cpu.bus.write(ptr, target as u8);
+        cpu.bus.write(hi_addr, (target >> 8) as u8);
+        let (addr, _) = AddressMode::Indirect.decode(&mut cpu);
+        prop_assert_eq!(addr, target);
+        prop_assert_eq!(cpu.pc, pc + 2);
+    }
+
+    #[test]
+    fn relative_lands_within_a_signed_byte_of_pc(pc in 0x0400u16..0x07FF, offset in any::<u8>()) {
+        let mut cpu = cpu_at(pc, &[offset]);
+        let (addr, penalty) = AddressMode::Relative.decode(&mut cpu);
+        let next = pc + 1;
+        prop_assert_eq!(cpu.pc, next);
+        let distance = addr as i32 - next as i32;
+        prop_assert!((-128..=127).contains(&distance));
+        prop_assert_eq!(distance, offset as i8 as i32);
+        prop_assert_eq!(penalty, page_crossed(next, addr));
+    }
+}
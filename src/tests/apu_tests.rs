@@ -0,0 +1,318 @@
+use crate::apu::APU;
+use crate::config::AudioConfig;
+
+#[test]
+fn pulse_duty_sequence() {
+    let mut apu = APU::new(AudioConfig::default());
+    apu.write_register(0x4015, 0x01);
+    //50% duty, length counter halted, constant volume 15
+    apu.write_register(0x4000, 0b1011_1111);
+    apu.write_register(0x4001, 0x00);
+    apu.write_register(0x4002, 0x08);
+    apu.write_register(0x4003, 0x00);
+
+    //a timer period of 8 steps the sequencer every 9 APU cycles (18 CPU cycles)
+    let mut samples = Vec::new();
+    for _ in 0..8 {
+        samples.push(apu.pulse1.output());
+        for _ in 0..18 {
+            apu.tick();
+        }
+    }
+    assert_eq!(samples, vec![0, 15, 15, 15, 15, 0, 0, 0]);
+}
+
+#[test]
+fn pulse_sweep_negate_differs_between_channels() {
+    let mut apu = APU::new(AudioConfig::default());
+    //negate with a shift of 1
+    apu.write_register(0x4001, 0b1000_1001);
+    apu.write_register(0x4005, 0b1000_1001);
+
+    assert_eq!(apu.pulse1.sweep.target_period(0x100), 0x7F);
+    assert_eq!(apu.pulse2.sweep.target_period(0x100), 0x80);
+}
+
+#[test]
+fn triangle_steps_only_while_linear_counter_is_loaded() {
+    let mut apu = APU::new(AudioConfig::default());
+    apu.write_register(0x4015, 0x04);
+    //control clear, linear counter reload of 1 quarter frame
+    apu.write_register(0x4008, 0x01);
+    apu.write_register(0x400A, 0x02);
+    apu.write_register(0x400B, 0x08);
+
+    //linear counter is still 0, so the sequencer must not advance
+    for _ in 0..30 {
+        apu.triangle.clock_timer();
+    }
+    assert_eq!(apu.triangle.output(), 15);
+
+    apu.clock_linear_counter();
+
+    //period of 2 steps the 32-step sequence every 3 CPU cycles
+    let mut samples = Vec::new();
+    for _ in 0..32 {
+        samples.push(apu.triangle.output());
+        for _ in 0..3 {
+            apu.triangle.clock_timer();
+        }
+    }
+    let expected: Vec<u8> = (0..=15).rev().chain(0..=15).collect();
+    assert_eq!(samples, expected);
+
+    //the reload flag was cleared, so the next clock empties the counter and freezes the sequencer
+    apu.clock_linear_counter();
+    let frozen = apu.triangle.output();
+    for _ in 0..30 {
+        apu.triangle.clock_timer();
+    }
+    assert_eq!(apu.triangle.output(), frozen);
+}
+
+fn noise_sequence_length(apu: &mut APU) -> usize {
+    let start = apu.noise.shift_register;
+    let mut steps = 0;
+    loop {
+        //period index 0 shifts the register every 4 CPU cycles
+        for _ in 0..4 {
+            apu.noise.clock_timer();
+        }
+        steps += 1;
+        if apu.noise.shift_register == start {
+            return steps;
+        }
+    }
+}
+
+#[test]
+fn noise_lfsr_sequence() {
+    let mut apu = APU::new(AudioConfig::default());
+    apu.noise.clock_timer();
+    //bit 0 and bit 1 of the power-up value 1 differ, so a 1 is fed into bit 14
+    assert_eq!(apu.noise.shift_register, 0x4000);
+
+    let mut apu = APU::new(AudioConfig::default());
+    apu.write_register(0x400E, 0x00);
+    assert_eq!(noise_sequence_length(&mut apu), 32767);
+
+    let mut apu = APU::new(AudioConfig::default());
+    apu.write_register(0x400E, 0x80);
+    assert_eq!(noise_sequence_length(&mut apu), 93);
+}
+
+#[test]
+fn dmc_plays_sample_and_raises_irq() {
+    let mut apu = APU::new(AudioConfig::default());
+    //irq enabled, fastest rate, sample of 1 byte at $C000
+    apu.write_register(0x4010, 0x8F);
+    apu.write_register(0x4011, 0x40);
+    apu.write_register(0x4012, 0x00);
+    apu.write_register(0x4013, 0x00);
+    apu.write_register(0x4015, 0x10);
+
+    assert_eq!(apu.dmc.pending_read(), Some(0xC000));
+    apu.dmc.load_sample_byte(0xFF);
+    assert_eq!(apu.dmc.pending_read(), None);
+    assert!(apu.irq_pending());
+
+    //the first 8-bit output cycle is silent, the next one adds 2 to the level per bit
+    for _ in 0..(54 * 16) {
+        apu.dmc.clock_timer();
+    }
+    assert_eq!(apu.dmc.output(), 0x40 + 16);
+
+    apu.write_register(0x4015, 0x00);
+    assert!(!apu.irq_pending());
+}
+
+#[test]
+fn frame_counter_four_step_irq() {
+    let mut apu = APU::new(AudioConfig::default());
+    for _ in 0..29829 {
+        apu.tick();
+    }
+    assert!(apu.irq_pending());
+    assert_eq!(apu.read_status() & 0x40, 0x40);
+    //the read acknowledged the interrupt
+    assert!(!apu.irq_pending());
+
+    //setting the inhibit flag keeps the interrupt from firing
+    apu.write_register(0x4017, 0x40);
+    for _ in 0..29829 {
+        apu.tick();
+    }
+    assert!(!apu.irq_pending());
+}
+
+#[test]
+fn frame_counter_five_step_clocks_immediately() {
+    let mut apu = APU::new(AudioConfig::default());
+    apu.write_register(0x4015, 0x01);
+    apu.write_register(0x4003, 0x18);
+    assert_eq!(apu.pulse1.length_counter.counter, 2);
+
+    apu.write_register(0x4017, 0x80);
+    assert_eq!(apu.pulse1.length_counter.counter, 1);
+
+    //five step mode never raises an interrupt
+    for _ in 0..(37281 * 2) {
+        apu.tick();
+    }
+    assert!(!apu.irq_pending());
+}
+
+#[test]
+fn resampler_produces_one_frame_of_samples_per_frame() {
+    use crate::apu::{CLOCK_RATE, resample::Resampler};
+
+    let mut resampler = Resampler::new(CLOCK_RATE, 44100.0);
+    for _ in 0..(29781 * 2) {
+        resampler.push(0.5);
+    }
+    let samples = resampler.take_samples();
+    //44100 / 60 = 735 per frame, minus the kernel's startup latency
+    assert!((1460..=1470).contains(&samples.len()), "{}", samples.len());
+    //a constant input should come out the other side unchanged
+    for sample in &samples[20..] {
+        assert!((sample - 0.5).abs() < 0.01, "{sample}");
+    }
+}
+
+#[test]
+fn channel_volume_scales_the_mix() {
+    use crate::apu::AuChannel;
+
+    let mut apu = APU::new(AudioConfig::default());
+    apu.write_register(0x4015, 0x01);
+    //constant volume 15, duty 75% so the first step is already high
+    apu.write_register(0x4000, 0b1111_1111);
+    apu.write_register(0x4002, 0x08);
+    apu.write_register(0x4003, 0x00);
+    //the triangle holds its last step when silenced, so it adds to the mix even now
+    apu.set_channel_muted(AuChannel::Triangle, true);
+    let full = apu.mix_output();
+    assert_eq!(full, 95.88 / (8128.0 / 15.0 + 100.0));
+
+    apu.set_channel_muted(AuChannel::Pulse1, true);
+    assert_eq!(apu.mix_output(), 0.0);
+    apu.set_channel_muted(AuChannel::Pulse1, false);
+
+    apu.set_master_volume(0.5);
+    assert_eq!(apu.mix_output(), full * 0.5);
+
+    //halving the channel halves its input to the non-linear DAC, not its output
+    apu.set_master_volume(1.0);
+    apu.set_channel_volume(AuChannel::Pulse1, 0.5);
+    assert_eq!(apu.mix_output(), 95.88 / (8128.0 / 7.5 + 100.0));
+}
+
+#[test]
+fn output_filters_remove_dc_and_pass_the_midrange() {
+    use crate::apu::filter::{Filter, output_filters};
+
+    //a constant level is what the high-pass capacitors block
+    let mut filters = output_filters(44100.0);
+    let mut last = 0.0;
+    for _ in 0..44100 {
+        last = filters.iter_mut().fold(0.5, |sample, filter| filter.process(sample));
+    }
+    assert!(last.abs() < 0.001, "{last}");
+
+    //a 2kHz square wave sits between the cutoffs and keeps most of its swing
+    let mut filters = output_filters(44100.0);
+    let mut peak: f32 = 0.0;
+    for i in 0..44100 {
+        let input = if (i / 11) % 2 == 0 { 0.5 } else { 0.0 };
+        let output = filters.iter_mut().fold(input, |sample, filter| filter.process(sample));
+        if i > 22050 {
+            peak = peak.max(output);
+        }
+    }
+    assert!(peak > 0.2, "{peak}");
+
+    //the low-pass lets a constant level through untouched
+    let mut low_pass = Filter::low_pass(14000.0, 44100.0);
+    for _ in 0..100 {
+        last = low_pass.process(0.5);
+    }
+    assert!((last - 0.5).abs() < 0.001, "{last}");
+}
+
+#[test]
+fn mixer_is_non_linear() {
+    assert_eq!(APU::mix(0, 0, 0, 0, 0), 0.0);
+    //both pulses at 15 come out well short of twice one pulse at 15
+    let one_pulse = APU::mix(15, 0, 0, 0, 0);
+    assert_eq!(one_pulse, 95.88 / (8128.0 / 15.0 + 100.0));
+    assert!(APU::mix(15, 15, 0, 0, 0) < one_pulse * 2.0);
+    //everything at its maximum stays in range
+    let full = APU::mix(15, 15, 15, 15, 127);
+    assert!((0.99..=1.0).contains(&full), "{full}");
+    assert_eq!(
+        APU::mix(0, 0, 15, 0, 0),
+        159.79 / (1.0 / (15.0 / 8227.0) + 100.0)
+    );
+}
+
+#[test]
+fn length_counter_loads_from_the_table() {
+    let mut apu = APU::new(AudioConfig::default());
+    apu.write_register(0x4015, 0x0F);
+    //bits 7-3 of the fourth register index the table, its values aren't in order
+    for (idx, expected) in [(0x00, 10), (0x01, 254), (0x03, 2), (0x08, 160), (0x18, 192), (0x1F, 30)] {
+        apu.write_register(0x4003, idx << 3);
+        apu.write_register(0x4007, idx << 3);
+        apu.write_register(0x400B, idx << 3);
+        apu.write_register(0x400F, idx << 3);
+        assert_eq!(apu.pulse1.length_counter.counter, expected);
+        assert_eq!(apu.pulse2.length_counter.counter, expected);
+        assert_eq!(apu.triangle.length_counter.counter, expected);
+        assert_eq!(apu.noise.length_counter.counter, expected);
+    }
+
+    //a disabled channel ignores the load
+    apu.write_register(0x4015, 0x00);
+    apu.write_register(0x4003, 0x08);
+    assert_eq!(apu.pulse1.length_counter.counter, 0);
+}
+
+#[test]
+fn sweep_bends_the_period_every_divider_reload() {
+    let mut apu = APU::new(AudioConfig::default());
+    apu.write_register(0x4015, 0x01);
+    //constant volume 15, duty 75% so the first step is already high
+    apu.write_register(0x4000, 0b1111_1111);
+    //enabled, divider period 1, shift 1
+    apu.write_register(0x4001, 0x91);
+    apu.write_register(0x4002, 0x00);
+    apu.write_register(0x4003, 0x01);
+
+    //the reload flag is set by the write, the divider starts at 0 so the first clock updates
+    apu.clock_sweeps();
+    assert_eq!(apu.pulse1.timer_period, 0x180);
+    apu.clock_sweeps();
+    assert_eq!(apu.pulse1.timer_period, 0x180);
+    apu.clock_sweeps();
+    assert_eq!(apu.pulse1.timer_period, 0x240);
+    assert_eq!(apu.pulse1.output(), 15);
+
+    //$700 + $380 is past $7FF, the channel goes quiet and the period is left alone
+    apu.write_register(0x4002, 0x00);
+    apu.write_register(0x4003, 0x07);
+    assert_eq!(apu.pulse1.output(), 0);
+    for _ in 0..4 {
+        apu.clock_sweeps();
+    }
+    assert_eq!(apu.pulse1.timer_period, 0x700);
+
+    //a disabled sweep still mutes, and so does a period under 8
+    apu.write_register(0x4001, 0x01);
+    assert_eq!(apu.pulse1.output(), 0);
+    apu.write_register(0x4001, 0x00);
+    apu.write_register(0x4002, 0x07);
+    apu.write_register(0x4003, 0x00);
+    assert_eq!(apu.pulse1.output(), 0);
+    apu.write_register(0x4002, 0x08);
+    assert_eq!(apu.pulse1.output(), 15);
+}
@@ -0,0 +1,128 @@
+/*
+    Runner for blargg's test roms that report through cartridge ram.
+    $6000 holds the status, $80 while running, $81 when the rom wants a reset
+    and otherwise the result code, 0 meaning passed. $6001-$6003 hold DE B0 61
+    once the status is valid and the rom's message is a null terminated string
+    from $6004.
+
+    The multi-test roms like official.nes only report on screen, so those
+    are checked by reading back the text in the first nametable.
+
+    Only official.nes is in the repo, tests for the others are ignored
+    until their roms are added.
+*/
+use std::sync::Arc;
+
+use crossbeam_channel::{bounded, unbounded};
+
+use crate::apu::DEFAULT_SAMPLE_RATE;
+use crate::emulator::Emulator;
+use crate::event::UiEvent;
+use crate::frame_buffer::SharedFramebuffer;
+
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_NEEDS_RESET: u8 = 0x81;
+//10 seconds of emulated time
+const MAX_FRAMES: u32 = 600;
+//official.nes takes a little over 11 seconds to get through all of its tests
+const MULTI_TEST_MAX_FRAMES: u32 = 1200;
+//the roms ask for the reset to come at least 100ms after they request it
+const RESET_DELAY_FRAMES: u32 = 6;
+
+fn read_result(emu: &Emulator) -> String {
+    let bus = &emu.cpu().bus;
+    let bytes: Vec<u8> = (0x6004..0x7000).map(|addr| bus.read_no_side_effects(addr)).take_while(|&byte| byte != 0).collect();
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+fn boot_rom(path: &str) -> Emulator {
+    let (_event_send, event_receive) = unbounded::<UiEvent>();
+    let (audio_send, _audio_receive) = bounded::<f32>(1);
+    let mut emu = Emulator::new(event_receive, Arc::new(SharedFramebuffer::new(false)), audio_send, DEFAULT_SAMPLE_RATE);
+    emu.load_cartridge(path.to_string()).unwrap();
+    emu
+}
+
+//the first nametable as lines of text, the roms' font has its tiles at the ascii codes
+fn screen_text(emu: &Emulator) -> String {
+    let bus = &emu.cpu().bus;
+    let tiles: Vec<u8> = (0x2000..0x23C0).map(|addr| bus.ppu.read(bus.mapper(), addr)).collect();
+    tiles
+        .chunks(32)
+        .map(|row| row.iter().map(|&tile| if tile.is_ascii_graphic() { tile as char } else { ' ' }).collect::<String>())
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn run_multi_test_rom(path: &str) {
+    let mut emu = boot_rom(path);
+    for _ in 0..MULTI_TEST_MAX_FRAMES {
+        emu.run_frame();
+        assert!(!emu.cpu().halted, "{path} locked up");
+        let text = screen_text(&emu);
+        if text.contains("All tests complete") {
+            assert!(!text.contains("Failed"), "{path} failed:\n{text}");
+            return;
+        }
+    }
+    panic!("{path} didn't finish within {MULTI_TEST_MAX_FRAMES} frames:\n{}", screen_text(&emu));
+}
+
+fn run_blargg_rom(path: &str) {
+    let mut emu = boot_rom(path);
+
+    let mut reset_at = None;
+    for frame in 0..MAX_FRAMES {
+        emu.run_frame();
+        assert!(!emu.cpu().halted, "{path} locked up");
+        let bus = &emu.cpu().bus;
+        let signature = [bus.read_no_side_effects(0x6001), bus.read_no_side_effects(0x6002), bus.read_no_side_effects(0x6003)];
+        if signature != [0xDE, 0xB0, 0x61] {
+            continue;
+        }
+        match bus.read_no_side_effects(0x6000) {
+            STATUS_RUNNING => {}
+            STATUS_NEEDS_RESET => match reset_at {
+                Some(reset_frame) if frame >= reset_frame => {
+                    emu.reset();
+                    reset_at = None;
+                }
+                Some(_) => {}
+                None => reset_at = Some(frame + RESET_DELAY_FRAMES),
+            },
+            status => {
+                let result = read_result(&emu);
+                assert_eq!(status, 0, "{path} failed with code {status}: {result}");
+                assert_eq!(result, "Passed", "{path}");
+                return;
+            }
+        }
+    }
+    panic!("{path} didn't finish within {MAX_FRAMES} frames: {}", read_result(&emu));
+}
+
+//instr_test-v5's official_only, every official opcode
+#[test]
+fn blargg_official_instructions() {
+    run_multi_test_rom("test_roms/official.nes");
+}
+
+#[test]
+#[ignore = "rom not in repo"]
+fn blargg_cpu_dummy_reads() {
+    run_blargg_rom("test_roms/blargg/cpu_dummy_reads.nes");
+}
+
+#[test]
+#[ignore = "rom not in repo"]
+fn blargg_instr_timing() {
+    run_blargg_rom("test_roms/blargg/instr_timing.nes");
+}
+
+#[test]
+#[ignore = "rom not in repo"]
+fn blargg_instr_misc() {
+    run_blargg_rom("test_roms/blargg/instr_misc.nes");
+}
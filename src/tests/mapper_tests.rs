@@ -0,0 +1,563 @@
+use crate::cartridge::{BackgroundTile, Cartridge, Mapper};
+
+//builds an iNES image where every byte of a bank holds that bank's index
+fn build_rom(mapper_id: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+    let mut rom = vec![
+        b'N',
+        b'E',
+        b'S',
+        0x1A,
+        prg_banks,
+        chr_banks,
+        (mapper_id & 0x0F) << 4,
+        mapper_id & 0xF0,
+    ];
+    rom.resize(16, 0);
+    for bank in 0..prg_banks {
+        rom.extend(std::iter::repeat_n(bank, 0x4000));
+    }
+    for bank in 0..chr_banks {
+        rom.extend(std::iter::repeat_n(bank, 0x2000));
+    }
+    rom
+}
+
+fn build_mapper(mapper_id: u8, prg_banks: u8, chr_banks: u8) -> Mapper {
+    Mapper::with_cart(Cartridge::from_bytes(build_rom(mapper_id, prg_banks, chr_banks)))
+}
+
+//MMC1 takes a register a bit at a time, a store instruction's worth of cycles apart
+fn mmc1_write_register(mapper: &mut Mapper, addr: u16, val: u8) {
+    for bit in 0..5 {
+        mapper.cpu_write(addr, (val >> bit) & 1);
+        for _ in 0..4 {
+            mapper.clock_cpu_cycle();
+        }
+    }
+}
+
+#[test]
+fn mmc1_ignores_writes_on_consecutive_cycles() {
+    let mut mapper = build_mapper(1, 8, 0);
+    mmc1_write_register(&mut mapper, 0xE000, 3);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    assert_eq!(mapper.cpu_read(0xC000), 7);
+
+    //a read-modify-write writes twice in a row, only the first reaches the shift register
+    mapper.cpu_write(0xE000, 0x80);
+    mapper.cpu_write(0xE000, 0x01);
+    mapper.clock_cpu_cycle();
+    mapper.clock_cpu_cycle();
+    mmc1_write_register(&mut mapper, 0xE000, 5);
+    assert_eq!(mapper.cpu_read(0x8000), 5);
+}
+
+#[test]
+fn mmc1_prg_mode_2_fixes_the_first_bank_at_8000() {
+    let mut mapper = build_mapper(1, 8, 0);
+    mmc1_write_register(&mut mapper, 0x8000, 0x08);
+    mmc1_write_register(&mut mapper, 0xE000, 3);
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+    assert_eq!(mapper.cpu_read(0xC000), 3);
+}
+
+#[test]
+fn uxrom_switches_low_bank_and_fixes_high_bank() {
+    let mut mapper = build_mapper(2, 8, 0);
+
+    //the reset vector lives in the fixed last bank
+    assert_eq!(mapper.cpu_read(0xFFFC), 7);
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+
+    //written over the fixed bank, which holds 7, so the bus conflict keeps the value
+    mapper.cpu_write(0xC000, 3);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    assert_eq!(mapper.cpu_read(0xBFFF), 3);
+
+    //the ROM holds 3 at $8000 now, which wins over the low bits
+    mapper.cpu_write(0x8000, 4);
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+    assert_eq!(mapper.cpu_read(0xC000), 7);
+
+    //CHR is RAM
+    mapper.ppu_write(0x0123, 0xAB);
+    assert_eq!(mapper.ppu_read(0x0123), 0xAB);
+}
+
+#[test]
+fn cnrom_switches_chr_bank_with_bus_conflicts() {
+    let mut rom = build_rom(3, 1, 4);
+    //header is 16 bytes, so this is the byte mapped at $8010
+    rom[16 + 0x10] = 0xFF;
+    let mut mapper = Mapper::with_cart(Cartridge::from_bytes(rom));
+
+    assert_eq!(mapper.ppu_read(0x0000), 0);
+
+    mapper.cpu_write(0x8010, 1);
+    assert_eq!(mapper.ppu_read(0x0000), 1);
+    assert_eq!(mapper.ppu_read(0x1FFF), 1);
+
+    //the ROM holds 0 at $8000, which wins the bus conflict
+    mapper.cpu_write(0x8000, 3);
+    assert_eq!(mapper.ppu_read(0x0000), 0);
+}
+
+#[test]
+fn mmc3_prg_banks_and_scanline_irq() {
+    //8 x 16KB = 16 x 8KB PRG banks
+    let mut mapper = build_mapper(4, 8, 8);
+
+    //R6 = 8KB bank 4, which is the first half of 16KB bank 2
+    mapper.cpu_write(0x8000, 6);
+    mapper.cpu_write(0x8001, 4);
+    assert_eq!(mapper.cpu_read(0x8000), 2);
+    //$C000 is fixed to the second last 8KB bank, $E000 to the last
+    assert_eq!(mapper.cpu_read(0xC000), 7);
+    assert_eq!(mapper.cpu_read(0xE000), 7);
+
+    //IRQ after 3 scanlines
+    mapper.cpu_write(0xC000, 2);
+    mapper.cpu_write(0xC001, 0);
+    mapper.cpu_write(0xE001, 0);
+    mapper.notify_a12_rise(100);
+    mapper.notify_a12_rise(100);
+    assert!(!mapper.irq_pending());
+    mapper.notify_a12_rise(100);
+    assert!(mapper.irq_pending());
+
+    //writing $E000 acknowledges and disables
+    mapper.cpu_write(0xE000, 0);
+    assert!(!mapper.irq_pending());
+}
+
+#[test]
+fn mmc3_prg_mode_swaps_the_fixed_bank_to_8000() {
+    //16 x 8KB PRG banks, the layout Kirby's Adventure switches into
+    let mut mapper = build_mapper(4, 8, 8);
+    mapper.cpu_write(0x8000, 0x46);
+    mapper.cpu_write(0x8001, 4);
+    mapper.cpu_write(0x8000, 0x47);
+    mapper.cpu_write(0x8001, 3);
+    assert_eq!(mapper.cpu_read(0x8000), 7);
+    assert_eq!(mapper.cpu_read(0xA000), 1);
+    assert_eq!(mapper.cpu_read(0xC000), 2);
+    assert_eq!(mapper.cpu_read(0xE000), 7);
+
+    //back to mode 0 without touching R6
+    mapper.cpu_write(0x8000, 0x06);
+    assert_eq!(mapper.cpu_read(0x8000), 2);
+    assert_eq!(mapper.cpu_read(0xC000), 7);
+}
+
+#[test]
+fn axrom_switches_32kb_bank_and_single_screen() {
+    use crate::cartridge::MirrorMode;
+
+    //8 x 16KB = 4 x 32KB PRG banks
+    let mut mapper = build_mapper(7, 8, 0);
+    assert_eq!(mapper.get_mirror_mode(), MirrorMode::SingleScreenA);
+
+    mapper.cpu_write(0x8000, 0x12);
+    //32KB bank 2 is made of 16KB banks 4 and 5
+    assert_eq!(mapper.cpu_read(0x8000), 4);
+    assert_eq!(mapper.cpu_read(0xFFFF), 5);
+    assert_eq!(mapper.get_mirror_mode(), MirrorMode::SingleScreenB);
+}
+
+#[test]
+fn mmc2_switches_chr_bank_on_latch_tiles() {
+    //4 x 8KB = 8 x 4KB CHR banks
+    let mut mapper = build_mapper(9, 8, 4);
+    //$FD bank is 4KB bank 2 (8KB bank 1), $FE bank is 4KB bank 4 (8KB bank 2)
+    mapper.cpu_write(0xB000, 2);
+    mapper.cpu_write(0xC000, 4);
+
+    //latches start out on $FE
+    assert_eq!(mapper.ppu_read(0x0000), 2);
+    mapper.notify_chr_fetch(0x0FD8);
+    assert_eq!(mapper.ppu_read(0x0000), 1);
+    //rows other than the first don't trip latch 0
+    mapper.notify_chr_fetch(0x0FE9);
+    assert_eq!(mapper.ppu_read(0x0000), 1);
+    mapper.notify_chr_fetch(0x0FE8);
+    assert_eq!(mapper.ppu_read(0x0000), 2);
+
+    //fixed banks at the top of PRG
+    assert_eq!(mapper.cpu_read(0xFFFF), 7);
+}
+
+#[test]
+fn gxrom_switches_prg_and_chr_with_one_register() {
+    //8 x 16KB = 4 x 32KB PRG banks
+    let mut rom = build_rom(66, 8, 4);
+    //GxROM has bus conflicts, so write over a byte that lets every bit through
+    rom[16 + 0x10] = 0xFF;
+    let mut mapper = Mapper::with_cart(Cartridge::from_bytes(rom));
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+    assert_eq!(mapper.ppu_read(0x0000), 0);
+
+    mapper.cpu_write(0x8010, 0x23);
+    //32KB bank 2 is made of 16KB banks 4 and 5
+    assert_eq!(mapper.cpu_read(0x8000), 4);
+    assert_eq!(mapper.cpu_read(0xFFFF), 5);
+    assert_eq!(mapper.ppu_read(0x0000), 3);
+    assert_eq!(mapper.ppu_read(0x1FFF), 3);
+}
+
+#[test]
+fn mmc5_banks_prg_and_chr_in_8kb_and_1kb_units() {
+    //16 x 8KB PRG banks and 64 x 1KB CHR banks, 8KB bank n reads n / 2 and 1KB bank n reads n / 8
+    let mut mapper = build_mapper(5, 8, 8);
+    //$5117 starts out on the last bank
+    assert_eq!(mapper.cpu_read(0xFFFC), 7);
+
+    mapper.cpu_write(0x5114, 0x80 | 5);
+    mapper.cpu_write(0x5116, 0x80 | 9);
+    assert_eq!(mapper.cpu_read(0x8000), 2);
+    assert_eq!(mapper.cpu_read(0xC000), 4);
+
+    //16KB mode ignores the low bit of $5115
+    mapper.cpu_write(0x5100, 1);
+    mapper.cpu_write(0x5115, 0x80 | 7);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    assert_eq!(mapper.cpu_read(0xA000), 3);
+
+    mapper.cpu_write(0x5123, 17);
+    assert_eq!(mapper.ppu_read(0x0C00), 2);
+    assert_eq!(mapper.ppu_read(0x0000), 0);
+
+    mapper.cpu_write(0x5104, 2);
+    mapper.cpu_write(0x5C10, 0xAB);
+    assert_eq!(mapper.cpu_read(0x5C10), 0xAB);
+
+    mapper.cpu_write(0x5205, 0x12);
+    mapper.cpu_write(0x5206, 0x34);
+    assert_eq!(mapper.cpu_read(0x5205), 0xA8);
+    assert_eq!(mapper.cpu_read(0x5206), 0x03);
+
+    //with 8x16 sprites the background uses $5128-$512B, repeated at $1000
+    mapper.notify_ppu_control(0x20);
+    mapper.cpu_write(0x512B, 40);
+    assert_eq!(mapper.background_tile(0x2000, 0x1C00, 0, 0), Some(BackgroundTile { pattern: [5, 5], palette: None }));
+    assert_eq!(mapper.ppu_read(0x0C00), 2);
+    //with 8x8 sprites the last set written is used for everything
+    mapper.notify_ppu_control(0x00);
+    assert_eq!(mapper.ppu_read(0x0C00), 5);
+
+    //extended attributes pick a 4KB bank and palette per tile
+    mapper.cpu_write(0x5104, 1);
+    mapper.cpu_write(0x5C05, 0xC0 | 3);
+    assert_eq!(mapper.background_tile(0x2005, 0x0010, 5, 0), Some(BackgroundTile { pattern: [1, 1], palette: Some(3) }));
+
+    //the split draws ExRAM tiles left of column 2 from the $5202 bank
+    mapper.cpu_write(0x5200, 0x80 | 2);
+    mapper.cpu_write(0x5202, 15);
+    mapper.cpu_write(0x5FC0, 0x02);
+    assert_eq!(mapper.background_tile(0x2001, 0x0000, 1, 0), Some(BackgroundTile { pattern: [7, 7], palette: Some(2) }));
+    assert_eq!(mapper.background_tile(0x2005, 0x0010, 5, 0).unwrap().palette, Some(3));
+}
+
+#[test]
+fn vrc4_banks_prg_and_chr_nibbles() {
+    //16 x 8KB PRG banks and 64 x 1KB CHR banks, 8KB bank n reads n / 2 and 1KB bank n reads n / 8
+    let mut mapper = build_mapper(23, 8, 8);
+    mapper.cpu_write(0x8000, 4);
+    mapper.cpu_write(0xA000, 7);
+    assert_eq!(mapper.cpu_read(0x8000), 2);
+    assert_eq!(mapper.cpu_read(0xA000), 3);
+    assert_eq!(mapper.cpu_read(0xC000), 7);
+
+    //swap mode moves the switchable bank to $C000
+    mapper.cpu_write(0x9002, 0x02);
+    assert_eq!(mapper.cpu_read(0x8000), 7);
+    assert_eq!(mapper.cpu_read(0xC000), 2);
+
+    //without a submapper both the A0/A1 and A2/A3 wirings work
+    mapper.cpu_write(0xB001, 0x01);
+    assert_eq!(mapper.ppu_read(0x0000), 2);
+    mapper.cpu_write(0xC008, 0x08);
+    assert_eq!(mapper.ppu_read(0x0C00), 1);
+}
+
+#[test]
+fn vrc4_irq_counts_cpu_cycles() {
+    let mut mapper = build_mapper(23, 8, 8);
+    mapper.cpu_write(0xF000, 0x0E);
+    mapper.cpu_write(0xF001, 0x0F);
+    //enabled, cycle mode
+    mapper.cpu_write(0xF002, 0x06);
+
+    mapper.clock_cpu_cycle();
+    assert!(!mapper.irq_pending());
+    mapper.clock_cpu_cycle();
+    assert!(mapper.irq_pending());
+
+    mapper.cpu_write(0xF003, 0);
+    assert!(!mapper.irq_pending());
+}
+
+#[test]
+fn fme7_banks_through_command_register_and_counts_down_irq() {
+    //16 x 8KB PRG banks and 64 x 1KB CHR banks, 8KB bank n reads n / 2 and 1KB bank n reads n / 8
+    let mut mapper = build_mapper(69, 8, 8);
+    assert_eq!(mapper.cpu_read(0xE000), 7);
+
+    mapper.cpu_write(0x8000, 0x09);
+    mapper.cpu_write(0xA000, 5);
+    assert_eq!(mapper.cpu_read(0x8000), 2);
+    mapper.cpu_write(0x8000, 0x03);
+    mapper.cpu_write(0xA000, 24);
+    assert_eq!(mapper.ppu_read(0x0C00), 3);
+
+    //$6000 maps ROM until register 8 selects and enables RAM
+    mapper.cpu_write(0x8000, 0x08);
+    mapper.cpu_write(0xA000, 6);
+    assert_eq!(mapper.cpu_read(0x6000), 3);
+    mapper.cpu_write(0xA000, 0xC0);
+    mapper.cpu_write(0x6000, 0xAB);
+    assert_eq!(mapper.cpu_read(0x6000), 0xAB);
+
+    //IRQ fires when the counter wraps past 0
+    mapper.cpu_write(0x8000, 0x0E);
+    mapper.cpu_write(0xA000, 1);
+    mapper.cpu_write(0x8000, 0x0F);
+    mapper.cpu_write(0xA000, 0);
+    mapper.cpu_write(0x8000, 0x0D);
+    mapper.cpu_write(0xA000, 0x81);
+    mapper.clock_cpu_cycle();
+    assert!(!mapper.irq_pending());
+    mapper.clock_cpu_cycle();
+    assert!(mapper.irq_pending());
+    mapper.cpu_write(0xA000, 0x81);
+    assert!(!mapper.irq_pending());
+}
+
+#[test]
+fn camerica_switches_prg_at_c000_and_mirroring_at_9000() {
+    use crate::cartridge::MirrorMode;
+
+    let mut mapper = build_mapper(71, 8, 0);
+    assert_eq!(mapper.cpu_read(0xFFFC), 7);
+
+    //writes below $C000 don't touch the bank
+    mapper.cpu_write(0x9000, 3);
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+    mapper.cpu_write(0xC000, 3);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    assert_eq!(mapper.cpu_read(0xC000), 7);
+
+    mapper.cpu_write(0x9000, 0x10);
+    assert_eq!(mapper.get_mirror_mode(), MirrorMode::SingleScreenB);
+    mapper.cpu_write(0x9000, 0x00);
+    assert_eq!(mapper.get_mirror_mode(), MirrorMode::SingleScreenA);
+
+    mapper.ppu_write(0x0123, 0xAB);
+    assert_eq!(mapper.ppu_read(0x0123), 0xAB);
+}
+
+#[test]
+fn battery_ram_round_trips_through_sav_file() {
+    let dir = std::env::temp_dir().join(format!("rnes_battery_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.nes");
+    let mut rom = build_rom(0, 1, 1);
+    //battery flag
+    rom[6] |= 0x02;
+    std::fs::write(&rom_path, &rom).unwrap();
+
+    let mut mapper = Mapper::with_cart(Cartridge::from_file(&rom_path).unwrap());
+    mapper.cpu_write(0x6000, 0xAB);
+    mapper.cpu_write(0x7FFF, 0xCD);
+    mapper.save().unwrap();
+    assert!(dir.join("game.sav").exists());
+
+    let reloaded = Mapper::with_cart(Cartridge::from_file(&rom_path).unwrap());
+    assert_eq!(reloaded.cpu_read(0x6000), 0xAB);
+    assert_eq!(reloaded.cpu_read(0x7FFF), 0xCD);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ips_patch_applies_plain_and_rle_records() {
+    use crate::cartridge::apply_ips_patch;
+
+    let mut rom = vec![0u8; 8];
+    let mut patch = b"PATCH".to_vec();
+    //2 bytes at offset 1
+    patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+    //RLE run of 4 x $CC at offset 6, past the end of the rom
+    patch.extend_from_slice(&[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x04, 0xCC]);
+    patch.extend_from_slice(b"EOF");
+    apply_ips_patch(&mut rom, &patch).unwrap();
+    assert_eq!(rom, [0, 0xAA, 0xBB, 0, 0, 0, 0xCC, 0xCC, 0xCC, 0xCC]);
+
+    //truncation extension after EOF
+    patch.extend_from_slice(&[0x00, 0x00, 0x03]);
+    apply_ips_patch(&mut rom, &patch).unwrap();
+    assert_eq!(rom, [0, 0xAA, 0xBB]);
+
+    assert!(apply_ips_patch(&mut rom, b"PATCH\x00\x00").is_err());
+    assert!(apply_ips_patch(&mut rom, b"NOPE").is_err());
+}
+
+#[test]
+fn four_screen_nametables_are_all_distinct() {
+    use crate::cartridge::MirrorMode;
+    use crate::ppu::PPU;
+
+    let mut rom = build_rom(4, 2, 1);
+    //four screen flag, which wins over the vertical bit
+    rom[6] |= 0x09;
+    let mut mapper = Mapper::with_cart(Cartridge::from_bytes(rom));
+    assert_eq!(mapper.get_mirror_mode(), MirrorMode::FourScreen);
+    //MMC3 mirroring writes are ignored
+    mapper.cpu_write(0xA000, 1);
+    assert_eq!(mapper.get_mirror_mode(), MirrorMode::FourScreen);
+
+    let mut ppu = PPU::new();
+    for (i, nametable) in [0x2000u16, 0x2400, 0x2800, 0x2C00].into_iter().enumerate() {
+        ppu.write_register(&mut mapper, 0x2006, (nametable >> 8) as u8);
+        ppu.write_register(&mut mapper, 0x2006, 0x05);
+        ppu.write_register(&mut mapper, 0x2007, i as u8 + 1);
+    }
+    assert_eq!(ppu.read(&mapper, 0x2005), 1);
+    assert_eq!(ppu.read(&mapper, 0x2405), 2);
+    assert_eq!(ppu.read(&mapper, 0x2805), 3);
+    assert_eq!(ppu.read(&mapper, 0x2C05), 4);
+    //$3000-$3EFF still mirrors $2000-$2EFF
+    assert_eq!(ppu.read(&mapper, 0x3C05), 4);
+}
+
+#[test]
+fn region_is_read_from_nes2_header() {
+    use crate::cartridge::Region;
+
+    let mut rom = build_rom(0, 1, 1);
+    rom[12] = 1;
+    //byte 12 means nothing in an iNES 1.0 header
+    assert_eq!(Cartridge::from_bytes(rom.clone()).region, Region::Ntsc);
+    rom[7] |= 0x08;
+    assert_eq!(Cartridge::from_bytes(rom.clone()).region, Region::Pal);
+    rom[12] = 0;
+    assert_eq!(Cartridge::from_bytes(rom).region, Region::Ntsc);
+}
+
+#[test]
+fn trainer_is_skipped_and_loaded_at_7000() {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0x04, 0];
+    rom.resize(16, 0);
+    rom.extend(std::iter::repeat_n(0xEE, 512));
+    rom.extend(std::iter::repeat_n(0x11, 0x4000));
+    rom.extend(std::iter::repeat_n(0x22, 0x2000));
+    assert_eq!(rom.len(), 16 + 512 + 16384 + 8192);
+    let mapper = Mapper::with_cart(Cartridge::from_bytes(rom));
+
+    assert_eq!(mapper.cpu_read(0x8000), 0x11);
+    assert_eq!(mapper.cpu_read(0xFFFF), 0x11);
+    assert_eq!(mapper.ppu_read(0x0000), 0x22);
+    assert_eq!(mapper.ppu_read(0x1FFF), 0x22);
+    assert_eq!(mapper.cpu_read(0x7000), 0xEE);
+    assert_eq!(mapper.cpu_read(0x71FF), 0xEE);
+    assert_eq!(mapper.cpu_read(0x7200), 0);
+}
+
+#[test]
+fn sha1_covers_prg_and_chr_but_not_the_header() {
+    let cart = Cartridge::from_bytes(build_rom(0, 1, 1));
+    let mut retagged = build_rom(0, 1, 1);
+    //same dump with a different mirroring bit
+    retagged[6] |= 0x01;
+    let retagged = Cartridge::from_bytes(retagged);
+
+    assert_eq!(cart.sha1, cart.prg_chr_sha1());
+    assert_eq!(cart.sha1, retagged.sha1);
+    assert_ne!(cart.rom_sha1(), retagged.rom_sha1());
+    assert_eq!(cart.sha1_hex().len(), 40);
+    assert!(cart.sha1_hex().starts_with(&format!("{:02x}", cart.sha1[0])));
+}
+
+#[test]
+fn load_errors_say_what_went_wrong() {
+    use crate::cartridge::CartridgeError;
+
+    let dir = std::env::temp_dir().join(format!("rnes_load_error_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let load = |name: &str, rom: &[u8]| {
+        let rom_path = dir.join(name);
+        std::fs::write(&rom_path, rom).unwrap();
+        Cartridge::from_file(&rom_path)
+    };
+
+    match Cartridge::from_file(dir.join("missing.nes")) {
+        Err(CartridgeError::IoError(err)) => assert_eq!(err.kind(), std::io::ErrorKind::NotFound),
+        other => panic!("{other:?}"),
+    }
+    assert!(matches!(load("bad.nes", b"NOPE"), Err(CartridgeError::InvalidHeader)));
+    assert!(matches!(load("mmc6.nes", &build_rom(254, 1, 1)), Err(CartridgeError::UnsupportedMapper(254))));
+
+    let rom = build_rom(0, 2, 1);
+    assert!(matches!(
+        load("short.nes", &rom[..rom.len() - 1]),
+        Err(CartridgeError::InsufficientData { expected, got }) if expected == rom.len() && got == rom.len() - 1
+    ));
+    assert!(matches!(load("no_prg.nes", &build_rom(0, 0, 1)), Err(CartridgeError::InvalidHeader)));
+    //trainer flag set on a file that ends right after the header
+    let mut trainer = build_rom(0, 1, 0);
+    trainer[6] |= 0x04;
+    assert!(matches!(load("trainer.nes", &trainer[..16]), Err(CartridgeError::TrainerParseError)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mmc3_filters_a12_rises_that_follow_a_short_low() {
+    let mut mapper = build_mapper(4, 8, 8);
+    mapper.cpu_write(0xC000, 1);
+    mapper.cpu_write(0xC001, 0);
+    mapper.cpu_write(0xE001, 0);
+    //the first rise reloads the counter, the second takes it to 0
+    mapper.notify_a12_rise(100);
+    //4 dots low, the gap between two tiles' pattern fetches
+    mapper.notify_a12_rise(4);
+    mapper.notify_a12_rise(9);
+    assert!(!mapper.irq_pending());
+    mapper.notify_a12_rise(10);
+    assert!(mapper.irq_pending());
+}
+
+//scanline and dot the PPU reports once the MMC3 raises its IRQ, with the counter latch at 2
+fn mmc3_irq_position(control: u8, oam: u8) -> (u32, u32) {
+    use crate::ppu::PPU;
+
+    let mut mapper = build_mapper(4, 8, 8);
+    mapper.cpu_write(0xC000, 2);
+    mapper.cpu_write(0xC001, 0);
+    mapper.cpu_write(0xE001, 0);
+    let mut ppu = PPU::new();
+    ppu.oam_ram.fill(oam);
+    ppu.write_register(&mut mapper, 0x2000, control);
+    ppu.write_register(&mut mapper, 0x2001, 0x18);
+    let (mut nmi, mut irq) = (false, false);
+    for _ in 0..341 * 262 {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+        if irq {
+            return ppu.position();
+        }
+    }
+    panic!("the MMC3 never raised its IRQ");
+}
+
+#[test]
+fn mmc3_counts_one_a12_rise_per_scanline() {
+    //the pre-render line reloads the counter, lines 0 and 1 count it down
+    //$FF puts every sprite below the screen
+    //sprites at $1000, A12 rises with the first sprite fetch
+    assert_eq!(mmc3_irq_position(0x08, 0xFF), (1, 262));
+    //background at $1000, the first rise the filter lets through is the prefetch for the next line
+    assert_eq!(mmc3_irq_position(0x10, 0xFF), (1, 326));
+    //8x16 sprites, empty slots fetch tile $FF from $1000
+    assert_eq!(mmc3_irq_position(0x20, 0xFF), (1, 262));
+    //8 sprites using tile 0 from $0000 cover lines 1-16, fetching them keeps A12 low
+    assert_eq!(mmc3_irq_position(0x20, 0x00), (17, 262));
+}
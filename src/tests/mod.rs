@@ -1,3 +1,13 @@
+mod addressing_tests;
+mod apu_tests;
+mod blargg_tests;
 mod cpu;
 mod cpu_only_tests;
+mod mapper_tests;
+mod movie_tests;
+mod nsf_tests;
+mod ntsc_filter_tests;
+mod ppu_tests;
+mod rom_db_tests;
+mod savestate_tests;
 mod whole_emu_tests;
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use crossbeam_channel::{Receiver, bounded, unbounded};
+
+use crate::apu::DEFAULT_SAMPLE_RATE;
+use crate::emulator::Emulator;
+use crate::event::UiEvent;
+use crate::frame_buffer::SharedFramebuffer;
+use crate::input::{FrameInput, InputPlayback, InputRecording};
+
+fn emulator_with_ui() -> (Emulator, Receiver<UiEvent>) {
+    let (_event_send, event_receive) = unbounded::<UiEvent>();
+    let (audio_send, _audio_receive) = bounded::<f32>(1);
+    let (ui_send, ui_receive) = unbounded::<UiEvent>();
+    let mut emu = Emulator::new(event_receive, Arc::new(SharedFramebuffer::new(false)), audio_send, DEFAULT_SAMPLE_RATE);
+    emu.set_ui_sender(ui_send);
+    (emu, ui_receive)
+}
+
+fn recording_changes(ui_receive: &Receiver<UiEvent>) -> Vec<bool> {
+    ui_receive
+        .try_iter()
+        .filter_map(|event| match event {
+            UiEvent::RecordingChanged(recording) => Some(recording),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn frame_input_formats_buttons_in_fm2_order() {
+    assert_eq!(FrameInput(0).to_fm2(), "|0|........|||");
+    //A and Start
+    assert_eq!(FrameInput(0b0000_1001).to_fm2(), "|0|....T..A|||");
+    assert_eq!(FrameInput(0xFF).to_fm2(), "|0|RLDUTSBA|||");
+}
+
+#[test]
+fn recording_writes_header_then_one_line_per_frame() {
+    let path = std::env::temp_dir().join(format!("rnes_record_test_{}.fm2", std::process::id()));
+    let mut recording = InputRecording::create(&path, "game", "abc123").unwrap();
+    recording.record(FrameInput(0x80)).unwrap();
+    recording.record(FrameInput(0x01)).unwrap();
+    assert_eq!(recording.frame_count(), 2);
+    recording.finish().unwrap();
+
+    let movie = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines: Vec<&str> = movie.lines().collect();
+    assert_eq!(lines[0], "version 3");
+    assert!(lines.contains(&"romSHA1 abc123"));
+    assert!(lines.contains(&"port0 1"));
+    assert_eq!(&lines[lines.len() - 2..], ["|0|R.......|||", "|0|.......A|||"]);
+}
+
+#[test]
+fn playback_parses_header_and_frames() {
+    let movie = "version 3\nromSHA1 abc123\nport0 1\n|0|R.......|||\n|0|...U...A|||\n";
+    let mut playback = InputPlayback::parse(movie).unwrap();
+    assert_eq!(playback.rom_sha1(), Some("abc123"));
+    assert_eq!(playback.frame_count(), 2);
+    assert_eq!(playback.next_frame(), Some(FrameInput(0x80)));
+    assert_eq!(playback.next_frame(), Some(FrameInput(0x11)));
+    assert_eq!(playback.next_frame(), None);
+
+    assert!(InputPlayback::parse("|0|RL|||\n").is_err());
+}
+
+#[test]
+fn recording_starts_from_power_on() {
+    let path = std::env::temp_dir().join(format!("rnes_power_on_movie_{}.fm2", std::process::id()));
+    let (mut emu, ui_receive) = emulator_with_ui();
+    emu.load_cartridge("test_roms/nestest.nes".to_string()).unwrap();
+    for _ in 0..10 {
+        emu.run_frame();
+    }
+    let ram_is_clear = |emu: &Emulator| (0..0x0800).all(|addr| emu.cpu().bus.read_no_side_effects(addr) == 0);
+    assert!(!ram_is_clear(&emu));
+
+    emu.start_recording(path.to_string_lossy().into_owned());
+    assert!(ram_is_clear(&emu));
+    assert_eq!(recording_changes(&ui_receive), [true]);
+
+    emu.stop_recording();
+    assert_eq!(recording_changes(&ui_receive), [false]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn recording_without_a_rom_tells_the_ui_it_didnt_start() {
+    let path = std::env::temp_dir().join(format!("rnes_no_rom_movie_{}.fm2", std::process::id()));
+    let (mut emu, ui_receive) = emulator_with_ui();
+    emu.start_recording(path.to_string_lossy().into_owned());
+    assert_eq!(recording_changes(&ui_receive), [false]);
+    assert!(!path.exists());
+}
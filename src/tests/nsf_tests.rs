@@ -0,0 +1,68 @@
+use crate::cartridge::{Cartridge, Mapper};
+use crate::nsf::NsfPlayer;
+
+fn build_nsf(load_addr: u16, init_addr: u16, play_addr: u16, bank_init: [u8; 8], data: &[u8]) -> Vec<u8> {
+    let mut nsf = vec![0u8; 0x80];
+    nsf[0..5].copy_from_slice(b"NESM\x1A");
+    nsf[0x05] = 1;
+    //3 songs, starting at the second
+    nsf[0x06] = 3;
+    nsf[0x07] = 2;
+    nsf[0x08..0x0A].copy_from_slice(&load_addr.to_le_bytes());
+    nsf[0x0A..0x0C].copy_from_slice(&init_addr.to_le_bytes());
+    nsf[0x0C..0x0E].copy_from_slice(&play_addr.to_le_bytes());
+    nsf[0x70..0x78].copy_from_slice(&bank_init);
+    nsf.extend_from_slice(data);
+    nsf
+}
+
+#[test]
+fn nsf_header_is_parsed() {
+    let cart = Cartridge::from_nsf(build_nsf(0x8000, 0x8000, 0x8003, [0; 8], &[0x60])).unwrap();
+    let header = cart.nsf.unwrap();
+    assert_eq!(header.song_count, 3);
+    assert_eq!(header.starting_song, 2);
+    assert_eq!((header.load_addr, header.init_addr, header.play_addr), (0x8000, 0x8000, 0x8003));
+    assert!(!header.uses_bank_switching());
+
+    assert!(Cartridge::from_nsf(b"NES\x1A".to_vec()).is_err());
+}
+
+#[test]
+fn nsf_banks_switch_through_5ff8() {
+    let mut data = vec![0xAA; 0x1000];
+    data.extend(std::iter::repeat_n(0xBB, 0x1000));
+    //load address $8100 pads the first bank by $100
+    let cart = Cartridge::from_nsf(build_nsf(0x8100, 0x8100, 0x8100, [0, 1, 0, 0, 0, 0, 0, 0], &data)).unwrap();
+    let mut mapper = Mapper::with_cart(cart);
+
+    mapper.cpu_write(0x5FF8, 0);
+    mapper.cpu_write(0x5FF9, 1);
+    assert_eq!(mapper.cpu_read(0x80FF), 0);
+    assert_eq!(mapper.cpu_read(0x8100), 0xAA);
+    assert_eq!(mapper.cpu_read(0x9100), 0xBB);
+
+    mapper.cpu_write(0x5FFF, 2);
+    assert_eq!(mapper.cpu_read(0xF000), 0xBB);
+}
+
+#[test]
+fn player_calls_init_once_and_play_every_frame() {
+    //init: STA $00, RTS   play: INC $01, RTS
+    let code = [0x85, 0x00, 0x60, 0xE6, 0x01, 0x60];
+    let cart = Cartridge::from_nsf(build_nsf(0x8000, 0x8000, 0x8003, [0; 8], &code)).unwrap();
+    let mut player = NsfPlayer::new(cart, 44100);
+
+    //the song index is passed zero based
+    assert_eq!(player.cpu().bus.read_no_side_effects(0x0000), 1);
+    assert_eq!(player.cpu().bus.read_no_side_effects(0x0001), 0);
+
+    for _ in 0..3 {
+        player.play_frame();
+    }
+    assert_eq!(player.cpu().bus.read_no_side_effects(0x0001), 3);
+
+    player.init_song(3);
+    assert_eq!(player.cpu().bus.read_no_side_effects(0x0000), 2);
+    assert_eq!(player.cpu().bus.read_no_side_effects(0x0001), 0);
+}
@@ -0,0 +1,32 @@
+use crate::ntsc_filter::NtscFilter;
+use crate::ppu::{FRAME_BUFFER_SIZE, SCREEN_WIDTH};
+
+fn frame_with_red_pixel(x: usize) -> Vec<u8> {
+    let mut frame = vec![0x80; FRAME_BUFFER_SIZE];
+    frame[x * 4..x * 4 + 3].copy_from_slice(&[0xFF, 0x00, 0x00]);
+    frame
+}
+
+#[test]
+fn grey_has_no_chroma_to_bleed() {
+    let mut frame = vec![0x80; FRAME_BUFFER_SIZE];
+    NtscFilter::new().process(&mut frame);
+    assert!(frame.iter().all(|&byte| byte == 0x80));
+}
+
+#[test]
+fn color_bleeds_into_the_neighbouring_pixels() {
+    let mut frame = frame_with_red_pixel(100);
+    NtscFilter::new().process(&mut frame);
+    let pixel = |x: usize| &frame[x * 4..x * 4 + 4];
+    //the pixels the chroma is spread over turn reddish
+    for x in 99..=102 {
+        assert!(pixel(x)[0] > pixel(x)[1], "pixel {x} is {:?}", pixel(x));
+    }
+    //the red pixel itself loses some of its color
+    assert!(pixel(100)[1] > 0x00);
+    //and it doesn't reach further than the blur, or onto the next line
+    assert_eq!(pixel(97), [0x80; 4]);
+    assert_eq!(pixel(104), [0x80; 4]);
+    assert_eq!(pixel(SCREEN_WIDTH + 100), [0x80; 4]);
+}
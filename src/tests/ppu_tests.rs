@@ -0,0 +1,514 @@
+use crate::color::Color;
+
+use crate::cartridge::{Cartridge, Mapper, Region};
+use crate::input::Zapper;
+use crate::ppu::{PPU, rgba_color};
+
+//renders a frame with the given PPUMASK and returns the top left pixel
+fn render_pixel(ppu: &mut PPU, mapper: &mut Mapper, mask: u8) -> Color {
+    ppu.write_register(mapper, 0x2001, mask);
+    let mut irq = false;
+    ppu.write_register(mapper, 0x2000, 0x80);
+    //one NMI to get in sync and a second once a whole frame has been drawn
+    for _ in 0..2 {
+        let mut nmi = false;
+        while !nmi {
+            ppu.step(mapper, &mut nmi, &mut irq);
+        }
+    }
+    pixel(ppu, 0)
+}
+
+fn pixel(ppu: &PPU, screen_coor: usize) -> Color {
+    let rgba = &ppu.frame_buffer[screen_coor * 4..screen_coor * 4 + 4];
+    rgba_color(rgba.try_into().unwrap())
+}
+
+//NROM with one CHR bank, empty except for the given bytes
+fn nrom_with_chr(bytes: &[(usize, u8)]) -> Mapper {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0];
+    rom.resize(16 + 0x4000, 0);
+    let mut chr = vec![0; 0x2000];
+    for &(addr, val) in bytes {
+        chr[addr] = val;
+    }
+    rom.extend(chr);
+    Mapper::with_cart(Cartridge::from_bytes(rom))
+}
+
+//PPU dots from one vblank NMI to the next, with rendering off
+fn dots_per_frame(region: Region) -> u32 {
+    let mut ppu = PPU::new();
+    let mut mapper = Mapper::none();
+    ppu.set_region(region);
+    ppu.write_register(&mut mapper, 0x2000, 0x80);
+
+    let (mut nmi, mut irq) = (false, false);
+    while !nmi {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+    }
+    nmi = false;
+    let mut dots = 0;
+    while !nmi {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+        dots += 1;
+    }
+    dots
+}
+
+#[test]
+fn pal_frames_have_312_scanlines() {
+    assert_eq!(dots_per_frame(Region::Ntsc), 341 * 262);
+    assert_eq!(dots_per_frame(Region::Pal), 341 * 312);
+}
+
+#[test]
+fn pal_runs_16_dots_every_5_cpu_cycles() {
+    assert_eq!(Region::Ntsc.ppu_dots(29781), 29781 * 3);
+    //the fraction carries over from one instruction to the next
+    let steps: Vec<u64> = (1..=5).map(|cycles| Region::Pal.ppu_dots(cycles) - Region::Pal.ppu_dots(cycles - 1)).collect();
+    assert_eq!(steps, [3, 3, 3, 3, 4]);
+    assert_eq!(Region::Pal.ppu_dots(33247 * 5), 33247 * 16);
+}
+
+#[test]
+fn emphasis_dims_the_other_channels() {
+    use crate::ppu::{NES_COLOR_PALETTE, build_emphasis_palettes};
+
+    let palettes = build_emphasis_palettes(&NES_COLOR_PALETTE);
+    assert_eq!(palettes[0], NES_COLOR_PALETTE);
+
+    //$20 is the light grey at (236, 238, 236)
+    let white = NES_COLOR_PALETTE[0x20];
+    let red = palettes[1][0x20];
+    assert_eq!(red.r, white.r);
+    assert_eq!((red.g, red.b), ((white.g as f32 * 0.85) as u8, (white.b as f32 * 0.85) as u8));
+    //all three emphasized dims every channel twice
+    let all = palettes[7][0x20];
+    assert_eq!(all.r, (white.r as f32 * 0.85 * 0.85) as u8);
+}
+
+#[test]
+fn palette_table_holds_every_emphasis_block() {
+    use crate::ppu::{NES_COLOR_PALETTE, build_emphasis_palettes, build_palette_table};
+
+    let palettes = build_emphasis_palettes(&NES_COLOR_PALETTE);
+    let table = build_palette_table(&NES_COLOR_PALETTE);
+    assert_eq!(rgba_color(table[0x16]), NES_COLOR_PALETTE[0x16]);
+    //red and blue emphasis, color $20
+    assert_eq!(rgba_color(table[(5 << 6) | 0x20]), palettes[5][0x20]);
+    assert_eq!(rgba_color(table[511]), palettes[7][63]);
+}
+
+#[test]
+fn palette_files_replace_the_builtin_colors() {
+    let path = std::env::temp_dir().join(format!("rnes_palette_test_{}.pal", std::process::id()));
+    let mut ppu = PPU::new();
+
+    let palette: Vec<u8> = (0..64u8).flat_map(|i| [i, i, i]).collect();
+    std::fs::write(&path, &palette).unwrap();
+    ppu.load_palette_file(&path).unwrap();
+
+    //a short file is rejected
+    std::fs::write(&path, &palette[..100]).unwrap();
+    assert!(ppu.load_palette_file(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+
+    //background color 0 comes from palette ram $3F00
+    let mut mapper = Mapper::none();
+    ppu.write_register(&mut mapper, 0x2006, 0x3F);
+    ppu.write_register(&mut mapper, 0x2006, 0x00);
+    ppu.write_register(&mut mapper, 0x2007, 0x16);
+    assert_eq!(render_pixel(&mut ppu, &mut mapper, 0x0A), Color::RGBA(0x16, 0x16, 0x16, 255));
+}
+
+#[test]
+fn greyscale_maps_colors_to_the_grey_column() {
+    use crate::ppu::NES_COLOR_PALETTE;
+
+    let mut ppu = PPU::new();
+    let mut mapper = Mapper::none();
+    ppu.write_register(&mut mapper, 0x2006, 0x3F);
+    ppu.write_register(&mut mapper, 0x2006, 0x00);
+    ppu.write_register(&mut mapper, 0x2007, 0x16);
+
+    assert_eq!(render_pixel(&mut ppu, &mut mapper, 0x0A), NES_COLOR_PALETTE[0x16]);
+    //$16 is red, with greyscale it becomes $10
+    assert_eq!(render_pixel(&mut ppu, &mut mapper, 0x0B), NES_COLOR_PALETTE[0x10]);
+}
+
+#[test]
+fn write_only_registers_read_back_the_bus_latch() {
+    let mut ppu = PPU::new();
+    let mut mapper = Mapper::none();
+
+    ppu.write_register(&mut mapper, 0x2003, 0x5A);
+    assert_eq!(ppu.read_register(&mapper, 0x2001), 0x5A);
+    assert_eq!(ppu.read_register(&mapper, 0x2006), 0x5A);
+
+    //status only drives its top 3 bits
+    ppu.write_register(&mut mapper, 0x2000, 0x1F);
+    assert_eq!(ppu.read_register(&mapper, 0x2002) & 0x1F, 0x1F);
+
+    //reads refresh the latch too
+    ppu.write_register(&mut mapper, 0x2003, 0x00);
+    ppu.write_register(&mut mapper, 0x2004, 0xC3);
+    ppu.write_register(&mut mapper, 0x2003, 0x00);
+    assert_eq!(ppu.read_register(&mapper, 0x2004), 0xC3);
+    assert_eq!(ppu.read_register(&mapper, 0x2005), 0xC3);
+}
+
+#[test]
+fn sprite_evaluation_runs_during_the_scanline() {
+    let mut ppu = PPU::new();
+    let mut mapper = Mapper::none();
+    //9 sprites on line 20 overflows, the rest are hidden below the screen
+    ppu.oam_ram.fill(0xFF);
+    for sprite in 0..9 {
+        ppu.oam_ram[sprite * 4] = 20;
+    }
+    ppu.write_register(&mut mapper, 0x2001, 0x18);
+
+    let (mut nmi, mut irq) = (false, false);
+    //the pre-render line, then into dots 1-64 of scanline 0
+    for _ in 0..341 + 10 {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+    }
+    assert_eq!(ppu.read_register(&mapper, 0x2004), 0xFF);
+    for _ in 0..100 {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+    }
+    assert_eq!(ppu.read_register(&mapper, 0x2004), 20);
+    assert_eq!(ppu.read_register(&mapper, 0x2002) & 0x20, 0);
+
+    //through the end of line 20
+    for _ in 0..341 * 21 {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+    }
+    assert_eq!(ppu.read_register(&mapper, 0x2002) & 0x20, 0x20);
+}
+
+//runs through line 20 with the given OAM and returns the sprite overflow flag
+fn sprite_overflow(oam: &[(usize, u8)]) -> bool {
+    let mut ppu = PPU::new();
+    let mut mapper = Mapper::none();
+    ppu.oam_ram.fill(0xFF);
+    for sprite in 0..8 {
+        ppu.oam_ram[sprite * 4] = 20;
+    }
+    for &(addr, val) in oam {
+        ppu.oam_ram[addr] = val;
+    }
+    ppu.write_register(&mut mapper, 0x2001, 0x18);
+
+    let (mut nmi, mut irq) = (false, false);
+    for _ in 0..341 * 22 {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+    }
+    (ppu.registers.borrow().status & 0x20) != 0
+}
+
+#[test]
+fn sprite_overflow_has_the_hardware_byte_offset_bug() {
+    //a 9th sprite straight after the first 8 is found
+    assert!(sprite_overflow(&[(8 * 4, 20)]));
+    //after a miss the next sprite's tile is compared instead of its y, so it is missed
+    assert!(!sprite_overflow(&[(9 * 4, 20)]));
+    //and a sprite off the line is found through its tile
+    assert!(sprite_overflow(&[(9 * 4 + 1, 20)]));
+}
+
+#[test]
+fn reading_status_as_vblank_starts_suppresses_nmi() {
+    let vblank_read = |extra_dots: u32| {
+        let mut ppu = PPU::new();
+        let mut mapper = Mapper::none();
+        ppu.write_register(&mut mapper, 0x2000, 0x80);
+        let (mut nmi, mut irq) = (false, false);
+        while ppu.registers.borrow().status & 0x80 == 0 {
+            ppu.step(&mut mapper, &mut nmi, &mut irq);
+        }
+        for _ in 0..extra_dots {
+            ppu.step(&mut mapper, &mut nmi, &mut irq);
+        }
+        let status = ppu.read_register(&mapper, 0x2002);
+        for _ in 0..10 {
+            ppu.step(&mut mapper, &mut nmi, &mut irq);
+        }
+        (status & 0x80, nmi)
+    };
+    //on the dot the flag is set it reads clear and the NMI never fires
+    assert_eq!(vblank_read(0), (0, false));
+    //a dot later it's too late
+    assert_eq!(vblank_read(1), (0x80, true));
+}
+
+#[test]
+fn pattern_viewer_draws_tiles_with_the_background_palette() {
+    use crate::pattern_viewer::{PATTERN_VIEW_WIDTH, PatternTableViewer};
+
+    //tile 1 of table 1 has a solid top row of color 3
+    let mut mapper = nrom_with_chr(&[(0x1010, 0xFF), (0x1018, 0xFF)]);
+
+    let mut ppu = PPU::new();
+    //background palette 2 is $3F09-$3F0B
+    ppu.write_register(&mut mapper, 0x2006, 0x3F);
+    ppu.write_register(&mut mapper, 0x2006, 0x09);
+    for color in [0x01, 0x02, 0x16] {
+        ppu.write_register(&mut mapper, 0x2007, color);
+    }
+
+    let viewer = PatternTableViewer {
+        palette: 2,
+        selected_tile: None,
+    };
+    let pixels = viewer.render(&ppu, &mapper);
+    //tile 257 starts at x = 128 + 8
+    let red = rgba_color(ppu.fetch_background_color(3, 2));
+    assert_eq!(pixels[136], red);
+    assert_eq!(pixels[PATTERN_VIEW_WIDTH + 136], rgba_color(ppu.fetch_background_color(0, 2)));
+    assert_eq!(PatternTableViewer::tile_at(136, 0), 257);
+
+    let highlighted = PatternTableViewer {
+        selected_tile: Some(0),
+        ..viewer
+    }
+    .render(&ppu, &mapper);
+    assert_eq!(highlighted[0], Color::WHITE);
+    assert_eq!(highlighted[PATTERN_VIEW_WIDTH + 1], pixels[PATTERN_VIEW_WIDTH + 1]);
+}
+
+#[test]
+fn oam_sprites_decode_with_flips_and_size() {
+    use crate::sprite_viewer::{SPRITE_VIEW_WIDTH, sprite_at, sprite_grid};
+
+    //tile 2 has its top left pixel set, tile 3 its bottom right
+    let mut mapper = nrom_with_chr(&[(0x0020, 0x80), (0x0037, 0x01)]);
+    let mut ppu = PPU::new();
+    ppu.write_register(&mut mapper, 0x2006, 0x3F);
+    ppu.write_register(&mut mapper, 0x2006, 0x11);
+    ppu.write_register(&mut mapper, 0x2007, 0x16);
+    //sprite palettes follow the background ones in palette ram
+    let red = rgba_color(ppu.fetch_background_color(1, 4));
+    //sprite 0 flipped both ways, sprite 1 parked below the screen
+    ppu.oam_ram[..8].copy_from_slice(&[0x10, 0x02, 0xC0, 0x20, 0xF0, 0x02, 0x00, 0x00]);
+
+    let sprites = ppu.render_oam_sprites(&mapper);
+    assert_eq!(sprites.len(), 64);
+    assert!(sprites[0].flip_horizontal && sprites[0].flip_vertical);
+    assert_eq!(sprites[0].pixels[7 * 8 + 7], red);
+    assert_eq!(sprites[1].pixels[0], red);
+
+    //8x16 sprites take the even tile on top and the odd one below it
+    ppu.write_register(&mut mapper, 0x2000, 0x20);
+    let tall = ppu.render_oam_sprites(&mapper);
+    assert_eq!(tall[1].height, 16);
+    assert_eq!(tall[1].pixels[15 * 8 + 7], red);
+
+    let grid = sprite_grid(&sprites);
+    assert_eq!(grid[SPRITE_VIEW_WIDTH * 7 + 7], red);
+    assert_ne!(grid[9], red);
+    assert_eq!((grid[9].r, grid[9].g), (grid[9].g, grid[9].b));
+    assert_eq!(sprite_at(9, 0), Some(1));
+    assert_eq!(sprite_at(8, 0), None);
+}
+
+#[test]
+fn palette_viewer_shows_mirrored_palette_ram() {
+    use crate::palette_viewer::{palette_addr_at, palette_view};
+    use crate::ppu::NES_COLOR_PALETTE;
+
+    let mut mapper = Mapper::none();
+    let mut ppu = PPU::new();
+    ppu.write_register(&mut mapper, 0x2006, 0x3F);
+    ppu.write_register(&mut mapper, 0x2006, 0x00);
+    for entry in [0x0F, 0x16, 0x2A, 0x30] {
+        ppu.write_register(&mut mapper, 0x2007, entry);
+    }
+
+    let entries = ppu.palette_entries();
+    assert_eq!(entries[..4], [0x0F, 0x16, 0x2A, 0x30]);
+    //$3F10 mirrors the backdrop
+    assert_eq!(entries[0x10], 0x0F);
+    let colors = ppu.render_palettes();
+    assert_eq!(colors[1], NES_COLOR_PALETTE[0x16]);
+
+    assert_eq!(palette_addr_at(0, 0), Some(0x3F00));
+    assert_eq!(palette_addr_at(34, 0), None);
+    assert_eq!(palette_addr_at(40 + 16 * 2, 16), Some(0x3F12));
+    let pixels = palette_view(&colors);
+    assert_eq!(pixels[40 + 16], colors[1]);
+}
+
+//renders a frame per mask with a solid background and a solid sprite 0 at x, then returns the sprite zero hit flag
+fn sprite_zero_hit(sprite_x: u8, masks: &[u8]) -> bool {
+    let mut ppu = PPU::new();
+    let solid_tile: Vec<(usize, u8)> = (0..8).map(|row| (row, 0xFF)).collect();
+    let mut mapper = nrom_with_chr(&solid_tile);
+    ppu.oam_ram[..4].copy_from_slice(&[50, 0, 0, sprite_x]);
+    ppu.write_register(&mut mapper, 0x2000, 0x80);
+
+    let mut irq = false;
+    //one NMI to get in sync, then one per frame drawn
+    for mask in std::iter::once(masks[0]).chain(masks.iter().copied()) {
+        ppu.write_register(&mut mapper, 0x2001, mask);
+        let mut nmi = false;
+        while !nmi {
+            ppu.step(&mut mapper, &mut nmi, &mut irq);
+        }
+    }
+    (ppu.registers.borrow().status & 0x40) != 0
+}
+
+#[test]
+fn sprite_zero_hit_skips_the_last_dot() {
+    //blargg's right_edge test, a sprite at 255 only has its first column on the last dot
+    assert!(sprite_zero_hit(254, &[0x1E]));
+    assert!(!sprite_zero_hit(255, &[0x1E]));
+}
+
+#[test]
+fn sprite_zero_hit_respects_left_clipping() {
+    //blargg's left_clip test, clipping either layer hides the hit in the first 8 pixels
+    assert!(sprite_zero_hit(0, &[0x1E]));
+    assert!(!sprite_zero_hit(0, &[0x18]));
+    assert!(!sprite_zero_hit(0, &[0x1A]));
+    //a frame with the background shown there first mustn't leave it opaque
+    assert!(!sprite_zero_hit(0, &[0x1E, 0x1C]));
+    //the rest of the sprite is past the clipped area
+    assert!(sprite_zero_hit(1, &[0x18]));
+}
+
+#[test]
+fn scroll_written_mid_frame_splits_the_screen_from_the_next_line() {
+    let mut ppu = PPU::new();
+    //tile 1 is solid, tile 0 is empty
+    let solid_tile: Vec<(usize, u8)> = (16..24).map(|row| (row, 0xFF)).collect();
+    let mut mapper = nrom_with_chr(&solid_tile);
+    //tile 1 down the second column of the nametable, written with the +32 increment
+    ppu.write_register(&mut mapper, 0x2000, 0x04);
+    ppu.write_register(&mut mapper, 0x2006, 0x20);
+    ppu.write_register(&mut mapper, 0x2006, 0x01);
+    for _ in 0..30 {
+        ppu.write_register(&mut mapper, 0x2007, 1);
+    }
+    ppu.write_register(&mut mapper, 0x2006, 0x3F);
+    ppu.write_register(&mut mapper, 0x2006, 0x00);
+    ppu.write_register(&mut mapper, 0x2007, 0x0F);
+    ppu.write_register(&mut mapper, 0x2007, 0x30);
+    //back to the first nametable, the palette address left its bits in t
+    ppu.write_register(&mut mapper, 0x2000, 0x80);
+    ppu.write_register(&mut mapper, 0x2005, 0);
+    ppu.write_register(&mut mapper, 0x2005, 0);
+    //background only, so none of the scroll copies can rely on sprites being shown
+    ppu.write_register(&mut mapper, 0x2001, 0x0A);
+
+    let (mut nmi, mut irq) = (false, false);
+    while !nmi {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+    }
+    while ppu.position() != (100, 100) {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+    }
+    //scroll right by one tile halfway through line 100
+    ppu.write_register(&mut mapper, 0x2005, 8);
+    ppu.write_register(&mut mapper, 0x2005, 0);
+    nmi = false;
+    while !nmi {
+        ppu.step(&mut mapper, &mut nmi, &mut irq);
+    }
+
+    let (backdrop, solid) = (rgba_color(ppu.fetch_background_color(0, 0)), rgba_color(ppu.fetch_background_color(1, 0)));
+    assert_eq!(pixel(&ppu, 50 * 256), backdrop);
+    assert_eq!(pixel(&ppu, 50 * 256 + 8), solid);
+    assert_eq!(pixel(&ppu, 100 * 256), backdrop);
+    assert_eq!(pixel(&ppu, 101 * 256), solid);
+    assert_eq!(pixel(&ppu, 101 * 256 + 8), backdrop);
+}
+
+#[test]
+fn sprite_backdrop_entries_share_storage_with_the_background() {
+    let mut ppu = PPU::new();
+    let mut mapper = Mapper::none();
+    let write_palette = |ppu: &mut PPU, mapper: &mut Mapper, addr: u16, val: u8| {
+        ppu.write_register(mapper, 0x2006, (addr >> 8) as u8);
+        ppu.write_register(mapper, 0x2006, addr as u8);
+        ppu.write_register(mapper, 0x2007, val);
+    };
+    let read_palette = |ppu: &mut PPU, mapper: &mut Mapper, addr: u16| {
+        ppu.write_register(mapper, 0x2006, (addr >> 8) as u8);
+        ppu.write_register(mapper, 0x2006, addr as u8);
+        //palette reads aren't buffered
+        ppu.read_register(mapper, 0x2007)
+    };
+
+    write_palette(&mut ppu, &mut mapper, 0x3F00, 0x01);
+    write_palette(&mut ppu, &mut mapper, 0x3F10, 0x22);
+    //the later write wins for both addresses, there is only one byte
+    assert_eq!(read_palette(&mut ppu, &mut mapper, 0x3F00), 0x22);
+    assert_eq!(read_palette(&mut ppu, &mut mapper, 0x3F10), 0x22);
+
+    write_palette(&mut ppu, &mut mapper, 0x3F04, 0x05);
+    assert_eq!(read_palette(&mut ppu, &mut mapper, 0x3F14), 0x05);
+    write_palette(&mut ppu, &mut mapper, 0x3F1C, 0x0C);
+    assert_eq!(read_palette(&mut ppu, &mut mapper, 0x3F0C), 0x0C);
+
+    //the other sprite entries are separate from the background ones
+    write_palette(&mut ppu, &mut mapper, 0x3F01, 0x11);
+    write_palette(&mut ppu, &mut mapper, 0x3F11, 0x33);
+    assert_eq!(read_palette(&mut ppu, &mut mapper, 0x3F01), 0x11);
+    //and the mirrors of palette ram up to $3FFF land on the same bytes
+    assert_eq!(read_palette(&mut ppu, &mut mapper, 0x3F31), 0x33);
+    assert_eq!(ppu.palette_entries()[0x10], 0x22);
+}
+
+#[test]
+fn zapper_sees_light_only_just_after_the_beam_passes() {
+    let mut ppu = PPU::new();
+    let mut mapper = nrom_with_chr(&[]);
+    //a white backdrop everywhere
+    ppu.write_register(&mut mapper, 0x2006, 0x3F);
+    ppu.write_register(&mut mapper, 0x2006, 0x00);
+    ppu.write_register(&mut mapper, 0x2007, 0x30);
+    ppu.write_register(&mut mapper, 0x2001, 0x0A);
+    let zapper = Zapper { aim: Some((100, 50)), trigger: true };
+    let (mut nmi, mut irq) = (false, false);
+    let mut run_to = |ppu: &mut PPU, line: u32, dot: u32| {
+        while ppu.position() != (line, dot) {
+            ppu.step(&mut mapper, &mut nmi, &mut irq);
+        }
+    };
+
+    //the trigger is bit 4 and a dark screen sets bit 3
+    run_to(&mut ppu, 40, 1);
+    assert_eq!(zapper.read(&ppu), 0x18);
+    run_to(&mut ppu, 51, 1);
+    assert_eq!(zapper.read(&ppu), 0x10);
+    //the photodiode stops seeing the spot once it fades
+    run_to(&mut ppu, 80, 1);
+    assert_eq!(zapper.read(&ppu), 0x18);
+    assert_eq!(Zapper { aim: None, trigger: false }.read(&ppu), 0x08);
+}
+
+#[test]
+fn palette_reads_skip_the_buffer_and_fill_it_from_the_nametable_below() {
+    let mut ppu = PPU::new();
+    let mut mapper = nrom_with_chr(&[]);
+    //$2F00 is what sits under the palette at $3F00
+    ppu.write_register(&mut mapper, 0x2006, 0x2F);
+    ppu.write_register(&mut mapper, 0x2006, 0x00);
+    ppu.write_register(&mut mapper, 0x2007, 0x5A);
+    ppu.write_register(&mut mapper, 0x2006, 0x3F);
+    ppu.write_register(&mut mapper, 0x2006, 0x00);
+    ppu.write_register(&mut mapper, 0x2007, 0x16);
+
+    ppu.write_register(&mut mapper, 0x2006, 0x3F);
+    ppu.write_register(&mut mapper, 0x2006, 0x00);
+    assert_eq!(ppu.read_register(&mapper, 0x2007) & 0x3F, 0x16);
+    assert_eq!(ppu.registers.borrow().data_buffer, 0x5A);
+
+    //the next buffered read hands back the nametable byte, not the palette entry
+    ppu.write_register(&mut mapper, 0x2006, 0x20);
+    ppu.write_register(&mut mapper, 0x2006, 0x00);
+    assert_eq!(ppu.read_register(&mapper, 0x2007), 0x5A);
+}
@@ -0,0 +1,21 @@
+use crate::cartridge::{Cartridge, Region};
+use crate::rom_db::{RomDatabase, RomInfo};
+
+#[test]
+fn known_roms_are_found_by_their_prg_chr_sha1() {
+    let cart = Cartridge::from_file("test_roms/nestest.nes").unwrap();
+    let db = RomDatabase::from_file("test_roms/roms.json.gz").unwrap();
+    let info = db.get(&cart.sha1).expect("nestest is in the database");
+    assert_eq!(
+        *info,
+        RomInfo { title: "nestest".to_string(), region: Region::Ntsc, mapper: 0, has_battery: false }
+    );
+    assert_eq!(db.get(&[0; 20]), None);
+}
+
+#[test]
+fn bad_databases_are_rejected() {
+    assert!(RomDatabase::from_gzip(b"not gzip").is_err());
+    //no database installed isn't an error, every rom is just unknown
+    assert!(RomDatabase::load_or_empty("test_roms/missing_roms.json.gz").is_empty());
+}
@@ -0,0 +1,134 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cartridge::{Cartridge, Mapper};
+use crate::cpu::CPU;
+use crate::rewind::RewindBuffer;
+use crate::savestate::{self, SaveState};
+
+fn run_instructions(cpu: &mut CPU, count: usize) {
+    for _ in 0..count {
+        let cycles = cpu.execute_instruction();
+        cpu.bus.tick_ppu(cycles * 3);
+    }
+}
+
+fn boot_nestest() -> CPU {
+    let cart = Cartridge::from_file("test_roms/nestest.nes").unwrap();
+    let mut cpu = CPU::init();
+    cpu.bus.load_cartridge(Mapper::with_cart(cart));
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn restoring_a_state_replays_identically() {
+    let mut cpu = boot_nestest();
+    run_instructions(&mut cpu, 5000);
+
+    //go through the encoded form to make sure nothing is lost in serialization
+    let bytes = SaveState::capture(&cpu).unwrap().to_bytes().unwrap();
+    run_instructions(&mut cpu, 5000);
+    let expected = SaveState::capture(&cpu).unwrap().to_bytes().unwrap();
+
+    assert!(SaveState::from_bytes(&bytes).unwrap().restore(&mut cpu));
+    run_instructions(&mut cpu, 5000);
+    assert_eq!(SaveState::capture(&cpu).unwrap().to_bytes().unwrap(), expected);
+}
+
+#[test]
+fn state_from_another_mapper_is_rejected() {
+    let mut cpu = boot_nestest();
+    let mut state = SaveState::capture(&cpu).unwrap();
+    state.mapper = Mapper::none().save_state().unwrap();
+    state.cpu.pc = 0x1234;
+
+    assert!(!state.restore(&mut cpu));
+    assert_ne!(cpu.pc, 0x1234);
+}
+
+#[test]
+fn rewind_buffer_drops_oldest_state_when_full() {
+    let mut cpu = boot_nestest();
+    let mut rewind = RewindBuffer::new(3);
+    for frame in 0..5 {
+        cpu.pc = frame;
+        rewind.push(SaveState::capture(&cpu).unwrap());
+    }
+    assert_eq!(rewind.len(), 3);
+
+    let popped: Vec<u16> = std::iter::from_fn(|| rewind.pop()).map(|s| s.cpu.pc).collect();
+    assert_eq!(popped, vec![4, 3, 2]);
+}
+
+#[test]
+fn slots_are_named_by_the_rom_hash_next_to_the_rom() {
+    let path = savestate::slot_path("roms/Game (USA).nes", "4131307f0f69f2a5c54b7d438328c5b2a5ed0820", 3);
+    assert_eq!(path, Path::new("roms/4131307f0f69f2a5c54b7d438328c5b2a5ed0820_slot3.rnes"));
+}
+
+#[test]
+fn states_carry_the_time_they_were_captured() {
+    let cpu = boot_nestest();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let state = SaveState::capture(&cpu).unwrap();
+    assert!(state.saved_at.abs_diff(now) <= 1);
+    let bytes = state.to_bytes().unwrap();
+    assert_eq!(SaveState::from_bytes(&bytes).unwrap().saved_at, state.saved_at);
+}
+
+#[test]
+fn states_are_compressed_at_least_threefold() {
+    let mut cpu = boot_nestest();
+    run_instructions(&mut cpu, 50000);
+    let state = SaveState::capture(&cpu).unwrap();
+    let raw = bincode::serialize(&state).unwrap().len();
+    let compressed = state.to_bytes().unwrap().len();
+    assert!(raw >= compressed * 3, "{raw} bytes only compressed to {compressed}");
+}
+
+#[test]
+fn states_without_the_header_or_from_another_version_are_rejected() {
+    let cpu = boot_nestest();
+    let state = SaveState::capture(&cpu).unwrap();
+    assert!(SaveState::from_bytes(&bincode::serialize(&state).unwrap()).is_err());
+
+    let mut bytes = state.to_bytes().unwrap();
+    assert!(bytes.starts_with(savestate::STATE_MAGIC));
+    bytes[savestate::STATE_MAGIC.len()] = savestate::STATE_VERSION + 1;
+    assert!(SaveState::from_bytes(&bytes).is_err());
+    assert!(SaveState::from_bytes(savestate::STATE_MAGIC).is_err());
+}
+
+//cycles until the 4-step frame counter raises its IRQ
+fn cycles_to_frame_irq(cpu: &mut CPU) -> u32 {
+    let mut cycles = 0;
+    while !cpu.bus.apu.irq_pending() {
+        cpu.bus.tick_apu(1, cpu.cpu_cycle);
+        cycles += 1;
+    }
+    cycles
+}
+
+#[test]
+fn restoring_a_state_restores_frame_counter_timing() {
+    let mut cpu = boot_nestest();
+    cpu.bus.write(0x4017, 0x00);
+    cpu.bus.tick_apu(10000, cpu.cpu_cycle);
+    let state = SaveState::capture(&cpu).unwrap();
+
+    let expected = cycles_to_frame_irq(&mut cpu);
+    //without the apu in the state this would start from after the IRQ instead
+    cpu.bus.tick_apu(10000, cpu.cpu_cycle);
+    assert!(state.restore(&mut cpu));
+    assert!(!cpu.bus.apu.irq_pending());
+    assert_eq!(cycles_to_frame_irq(&mut cpu), expected);
+}
+
+#[test]
+fn mapper_state_leaves_out_the_rom() {
+    let cpu = boot_nestest();
+    let state = cpu.bus.save_mapper_state().unwrap();
+    //8KB each of PRG and CHR RAM plus registers, the 24KB of nestest's ROM would push it well past
+    assert!(bincode::serialize(&state).unwrap().len() < 2 * 0x2000 + 0x400);
+}
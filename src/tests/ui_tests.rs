@@ -0,0 +1,196 @@
+use sdl2::controller::Axis;
+use sdl2::rect::{FRect, Rect};
+
+use rnes::config::{Config, ControllerMapping, MAX_RECENT_FILES};
+use rnes::font;
+use rnes::frame_buffer::TripleBuffer;
+use rnes::ppu::{BLACK_FRAME, SCREEN_WIDTH};
+
+use crate::ui::config::{ScaleMode, UiConfig};
+use crate::ui::crt::scanline_stripes;
+use crate::ui::gif_recorder::{GifRecorder, quantize};
+use crate::ui::font::Font;
+use crate::ui::screenshot::{date_time, framebuffer_to_image};
+use crate::ui::ui::is_loadable_rom;
+
+#[test]
+fn thumbstick_maps_to_dpad_outside_deadzone() {
+    //keys left out of the [controller] table keep their defaults
+    let mapping: ControllerMapping = toml::from_str("deadzone = 1000").unwrap();
+    assert_eq!(mapping.buttons["b"], "A");
+    assert_eq!(mapping.axis_bits(Axis::LeftX, 500), 0);
+    assert_eq!(mapping.axis_bits(Axis::LeftX, -2000), 1 << 6);
+    assert_eq!(mapping.axis_bits(Axis::LeftY, 2000), 1 << 5);
+    assert_eq!(mapping.axis_bits(Axis::RightX, 2000), 0);
+    assert_eq!(mapping.axis_mask(Axis::LeftY), (1 << 4) | (1 << 5));
+}
+
+#[test]
+fn default_config_round_trips_through_toml() {
+    let path = std::env::temp_dir().join(format!("rnes_config_test_{}", std::process::id())).join("config.toml");
+    //a missing file gets the defaults written out
+    let config = Config::load_or_create(&path);
+    assert!(path.exists());
+    assert_eq!(config.key_bindings.player1.a, "X");
+
+    let reloaded = Config::from_file(&path).unwrap();
+    assert_eq!(reloaded.key_bindings.player2.up, "W");
+    assert_eq!(reloaded.controller.buttons["start"], "Start");
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn audio_settings_live_in_their_own_table() {
+    use rnes::config::AudioConfig;
+
+    let written = toml::to_string_pretty(&Config::default()).unwrap();
+    assert!(written.contains("[audio]\nsample_rate = 44100\nbuffer_size = 512"), "{written}");
+
+    //keys left out of the table keep their defaults
+    let audio: AudioConfig = toml::from_str("buffer_size = 256").unwrap();
+    assert_eq!(audio.buffer_size, 256);
+    assert_eq!(audio.sample_rate, 44100);
+}
+
+#[test]
+fn screenshot_is_native_resolution() {
+    let mut framebuffer = BLACK_FRAME.to_vec();
+    let offset = (SCREEN_WIDTH + 2) * 4;
+    framebuffer[offset..offset + 4].copy_from_slice(&[1, 2, 3, 255]);
+    let image = framebuffer_to_image(&framebuffer);
+    assert_eq!(image.dimensions(), (256, 240));
+    assert_eq!(image.get_pixel(2, 1).0, [1, 2, 3, 255]);
+}
+
+#[test]
+fn triple_buffer_never_writes_the_frame_being_shown() {
+    let frame = |shade: u8| move |buff: &mut [u8]| buff.fill(shade);
+    let buffer = TripleBuffer::new();
+    buffer.write_back_buffer(frame(1));
+    buffer.swap_buffers();
+    let shown = buffer.read_front_buffer();
+    assert_eq!(shown[0], 1);
+
+    //the emulator runs ahead while the ui is still showing the first frame
+    buffer.write_back_buffer(frame(2));
+    buffer.swap_buffers();
+    buffer.write_back_buffer(frame(3));
+    buffer.swap_buffers();
+    buffer.write_back_buffer(frame(4));
+    buffer.swap_buffers();
+    assert_eq!(shown[0], 1);
+    assert_eq!(buffer.read_front_buffer()[0], 4);
+}
+
+#[test]
+fn only_roms_are_loaded_from_drops() {
+    assert!(is_loadable_rom("/home/user/roms/Game (USA).nes"));
+    assert!(is_loadable_rom("music.NSF"));
+    assert!(!is_loadable_rom("notes.txt"));
+    assert!(!is_loadable_rom("/home/user/roms/nes"));
+    assert!(!is_loadable_rom("archive.nes.zip"));
+}
+
+#[test]
+fn recent_files_keep_the_newest_ten() {
+    let mut config = Config::default();
+    for idx in 0..12 {
+        config.recent_files.push(&format!("rom{idx}.nes"));
+    }
+    assert_eq!(config.recent_files.files.len(), MAX_RECENT_FILES);
+    assert_eq!(config.recent_files.get(0), Some("rom11.nes"));
+    assert_eq!(config.recent_files.get(9), Some("rom2.nes"));
+    //reopening one moves it to the front instead of listing it twice
+    config.recent_files.push("rom5.nes");
+    assert_eq!(config.recent_files.get(0), Some("rom5.nes"));
+    assert_eq!(config.recent_files.files.iter().filter(|path| *path == "rom5.nes").count(), 1);
+
+    let written: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+    assert_eq!(written.recent_files, config.recent_files);
+}
+
+#[test]
+fn scale_modes_fit_the_screen_differently() {
+    //2.5x the NES screen, with a wider aspect ratio
+    let (width, height) = (800, 600);
+    let integer = UiConfig::new(width, height, ScaleMode::IntegerScale);
+    assert_eq!(integer.dst_rect, Some(FRect::new(144.0, 60.0, 512.0, 480.0)));
+    let pixel_perfect = UiConfig::new(width, height, ScaleMode::PixelPerfect);
+    assert_eq!(pixel_perfect.dst_rect, Some(FRect::new(272.0, 180.0, 256.0, 240.0)));
+    let stretch = UiConfig::new(width, height, ScaleMode::Stretch);
+    assert_eq!(stretch.dst_rect, Some(FRect::new(0.0, 0.0, 800.0, 600.0)));
+    //the mouse maps back through whichever scale is in use
+    assert_eq!(stretch.screen_pixel_at(799, 599), Some((255, 239)));
+    assert_eq!(integer.screen_pixel_at(143, 100), None);
+    assert_eq!(ScaleMode::Stretch.next(), ScaleMode::IntegerScale);
+}
+
+#[test]
+fn crt_stripes_cover_every_other_scaled_line() {
+    let stripes = scanline_stripes(FRect::new(144.0, 60.0, 512.0, 480.0));
+    assert_eq!(stripes.len(), 120);
+    //at 2x each NES line is two pixels tall, the first stripe covers line 1
+    assert_eq!(stripes[0], FRect::new(144.0, 62.0, 512.0, 2.0));
+    assert_eq!(stripes[119], FRect::new(144.0, 538.0, 512.0, 2.0));
+}
+
+#[test]
+fn gif_frames_are_quantized_to_256_colors() {
+    //a handful of colors come through exactly
+    let mut frame = BLACK_FRAME.to_vec();
+    frame[4..8].copy_from_slice(&[0xF8, 0x38, 0x00, 0xFF]);
+    let (palette, indices) = quantize(&frame);
+    assert_eq!(palette.len(), 2 * 3);
+    let idx = indices[1] as usize * 3;
+    assert_eq!(palette[idx..idx + 3], [0xF8, 0x38, 0x00]);
+    assert_eq!(indices.len(), frame.len() / 4);
+
+    //every pixel a different color has to share palette entries
+    let frame: Vec<u8> = (0..4096).flat_map(|i| [(i << 4) as u8, (i & 0xF0) as u8, (i >> 4) as u8, 0xFF]).collect();
+    let (palette, indices) = quantize(&frame);
+    assert_eq!(palette.len(), 256 * 3);
+    //each pixel lands on a nearby color
+    for (pixel, &idx) in frame.chunks_exact(4).zip(&indices) {
+        let entry = &palette[idx as usize * 3..idx as usize * 3 + 3];
+        let distance: i32 = (0..3).map(|c| (pixel[c] as i32 - entry[c] as i32).abs()).sum();
+        assert!(distance < 96, "{pixel:?} mapped to {entry:?}");
+    }
+}
+
+#[test]
+fn gif_recording_keeps_every_fourth_frame_up_to_ten_seconds() {
+    let mut recorder = GifRecorder::new();
+    recorder.capture(&BLACK_FRAME[..]);
+    assert_eq!(recorder.frame_count(), 0);
+    recorder.start();
+    for _ in 0..8 {
+        recorder.capture(&BLACK_FRAME[..]);
+    }
+    assert_eq!(recorder.frame_count(), 2);
+    for _ in 0..1000 {
+        recorder.capture(&BLACK_FRAME[..]);
+    }
+    assert_eq!(recorder.frame_count(), 150);
+    assert!(recorder.is_full());
+}
+
+#[test]
+fn overlay_text_is_drawn_from_the_bitmap_font() {
+    assert_eq!(Font::string_width("60 FPS", 2), 6 * 8 * 2);
+    //the 1 is a 7 tall stem in the cell's 4th column with a flag and a 3 wide base, 10 pixels
+    let rects = Font::string_rects("1", 10, 20, 2);
+    assert_eq!(rects.len(), 10);
+    assert!(rects.contains(&Rect::new(16, 20, 2, 2)));
+    assert!(rects.contains(&Rect::new(14, 32, 2, 2)));
+    //every printable character has a glyph and the rest fall back to '?'
+    assert!((' '..='~').filter(|&c| c != ' ').all(|c| font::glyph(c) != [0; 8]));
+    assert_eq!(font::glyph('\u{e9}'), font::glyph('?'));
+    assert!(Font::string_rects(" ", 0, 0, 1).is_empty());
+}
+
+#[test]
+fn save_times_convert_to_utc_dates() {
+    assert_eq!(date_time(0), (1970, 1, 1, 0, 0, 0));
+    //the leap day of 2024, a little after noon
+    assert_eq!(date_time(1_709_208_000 + 3_723), (2024, 2, 29, 13, 2, 3));
+}
@@ -1,2 +1,468 @@
+use crate::bus::{Watchpoint, WatchpointHit};
+use crate::cartridge::{Cartridge, Mapper};
+use crate::cpu::{CPU, disassemble};
+use crate::trace::TraceBuffer;
+
+fn boot_nestest() -> CPU {
+    let cart = Cartridge::from_file("test_roms/nestest.nes").unwrap();
+    let mut cpu = CPU::init();
+    cpu.bus.load_cartridge(Mapper::with_cart(cart));
+    cpu.reset();
+    cpu
+}
+
+/*
+    Runs the automated mode of nestest, starting at $C000 instead of the reset vector.
+    If test_roms/nestest.log is present every instruction is checked against it,
+    otherwise only the result codes nestest leaves in $02 and $03 are checked.
+*/
 #[test]
-fn run_nestest() {}
+fn run_nestest() {
+    let expected_log = std::fs::read_to_string("test_roms/nestest.log").ok();
+    let mut expected_lines = expected_log.as_deref().map(str::lines);
+
+    let mut cpu = boot_nestest();
+    cpu.pc = 0xC000;
+    //the reset sequence takes 7 cycles, rendering stays off so every scanline is 341 dots
+    let mut cycle: u64 = 7;
+    let mut line = 1;
+    while cpu.pc != 0xC66E {
+        assert!(line < 10_000, "nestest didn't reach $C66E");
+        let ppu_dots = cycle * 3;
+        let trace = cpu.trace_nestest(cycle, (ppu_dots % 341) as u32, (ppu_dots / 341) as u32);
+        if let Some(expected) = expected_lines.as_mut().and_then(Iterator::next) {
+            assert_eq!(trace, expected.trim_end(), "nestest.log line {}", line);
+        }
+        cycle += cpu.execute_instruction() as u64;
+        line += 1;
+    }
+
+    assert_eq!(cpu.bus.read(0x0002), 0, "official opcode error code");
+    assert_eq!(cpu.bus.read(0x0003), 0, "unofficial opcode error code");
+}
+
+#[test]
+fn disassembles_nestest_entry_point() {
+    let cpu = boot_nestest();
+    assert_eq!(disassemble(&cpu, 0xC000), ("$C000  4C F5 C5  JMP $C5F5".to_string(), 3));
+
+    //walk the first few instructions of the automated test
+    let mut addr = 0xC5F5;
+    let mut lines = Vec::new();
+    for _ in 0..3 {
+        let (text, len) = disassemble(&cpu, addr);
+        lines.push(text);
+        addr += len as u16;
+    }
+    assert_eq!(
+        lines,
+        [
+            "$C5F5  A2 00     LDX #$00",
+            "$C5F7  86 00     STX $00",
+            "$C5F9  86 10     STX $10",
+        ]
+    );
+}
+
+#[test]
+fn trace_line_shows_last_executed_instruction() {
+    let mut cpu = boot_nestest();
+    cpu.pc = 0xC000;
+    cpu.execute_instruction();
+    assert_eq!(cpu.pc, 0xC5F5);
+    assert_eq!(cpu.trace_line(), "$C000  4C F5 C5  JMP $C5F5");
+}
+
+#[test]
+fn trace_keeps_the_most_recent_instructions() {
+    let mut cpu = boot_nestest();
+    cpu.pc = 0xC000;
+    cpu.trace = Some(TraceBuffer::new(2));
+    for _ in 0..3 {
+        cpu.execute_instruction();
+    }
+
+    let trace = cpu.dump_trace();
+    assert_eq!(trace.iter().map(|entry| entry.pc).collect::<Vec<_>>(), [0xC5F5, 0xC5F7]);
+    assert_eq!(
+        trace[0].nestest_line(),
+        format!("{:<48}A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:3", "C5F5  A2 00     LDX #$00")
+    );
+}
+
+#[test]
+fn strobe_latches_both_controllers() {
+    let mut cpu = boot_nestest();
+    cpu.bus.input.borrow_mut().controller_state = 0b0000_0001;
+    cpu.bus.input.borrow_mut().controller2_state = 0b1000_0010;
+    cpu.bus.write(0x4016, 1);
+    cpu.bus.write(0x4016, 0);
+
+    let player1: Vec<u8> = (0..8).map(|_| cpu.bus.read(0x4016)).collect();
+    let player2: Vec<u8> = (0..8).map(|_| cpu.bus.read(0x4017)).collect();
+    assert_eq!(player1, [1, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(player2, [0, 1, 0, 0, 0, 0, 0, 1]);
+}
+
+#[test]
+fn oam_dma_takes_an_extra_cycle_when_started_on_odd_cycle() {
+    let mut cpu = CPU::init();
+    //LDA #$02, STA $4014 from ram
+    for (i, byte) in [0xA9, 0x02, 0x8D, 0x14, 0x40].into_iter().enumerate() {
+        cpu.bus.write(i as u16, byte);
+    }
+    cpu.bus.write(0x0200, 0xAB);
+
+    cpu.pc = 0;
+    cpu.cpu_cycle = 0;
+    cpu.execute_instruction();
+    //the write finishes on cycle 6
+    cpu.execute_instruction();
+    assert_eq!(cpu.bus.extra_cycles, 513);
+    assert_eq!(cpu.bus.ppu.oam_ram[0], 0xAB);
+
+    cpu.pc = 0;
+    cpu.cpu_cycle = 1;
+    cpu.bus.extra_cycles = 0;
+    cpu.execute_instruction();
+    cpu.execute_instruction();
+    assert_eq!(cpu.bus.extra_cycles, 514);
+}
+
+#[test]
+fn trace_skips_oam_dma_cycles() {
+    //LDA #$02, STA $4014, NOP
+    let mut cpu = cpu_with_program(&[0xA9, 0x02, 0x8D, 0x14, 0x40, 0xEA]);
+    cpu.trace = Some(TraceBuffer::new(1000));
+    while cpu.pc != 0x0206 {
+        cpu.execute_instruction();
+    }
+
+    let trace = cpu.dump_trace();
+    assert_eq!(trace.iter().map(|entry| entry.pc).collect::<Vec<_>>(), [0x0200, 0x0202, 0x0205]);
+}
+
+//starts a one byte DMC sample at the fastest rate, the fetch happens on the next apu tick
+fn start_dmc_sample(cpu: &mut CPU) {
+    cpu.bus.write(0x4010, 0x0F);
+    cpu.bus.write(0x4012, 0x00);
+    cpu.bus.write(0x4013, 0x00);
+    cpu.bus.write(0x4015, 0x10);
+}
+
+#[test]
+fn dmc_fetch_stalls_the_cpu_four_cycles_outside_oam_dma() {
+    let mut cpu = CPU::init();
+    start_dmc_sample(&mut cpu);
+    cpu.bus.tick_apu(1, 1);
+    assert_eq!(cpu.bus.extra_cycles, 4);
+}
+
+#[test]
+fn dmc_fetch_during_oam_dma_steals_fewer_cycles() {
+    let mut cpu = CPU::init();
+    cpu.bus.write_oam_dma(0x02, 0);
+    start_dmc_sample(&mut cpu);
+    cpu.bus.tick_apu(1, 1);
+    assert_eq!(cpu.bus.extra_cycles, 513 + 2);
+
+    let mut cpu = CPU::init();
+    cpu.bus.write_oam_dma(0x02, 0);
+    start_dmc_sample(&mut cpu);
+    cpu.bus.tick_apu(1, 2);
+    assert_eq!(cpu.bus.extra_cycles, 513 + 1);
+
+    //the parity comes from the cpu's count, an apu that was reset on its own doesn't change it
+    let mut cpu = CPU::init();
+    cpu.bus.write_oam_dma(0x02, 0);
+    cpu.bus.apu.tick();
+    start_dmc_sample(&mut cpu);
+    cpu.bus.tick_apu(1, 1);
+    assert_eq!(cpu.bus.extra_cycles, 513 + 2);
+
+    //once the stall runs out the next fetch is back to the full cost
+    while cpu.bus.extra_cycles > 0 {
+        cpu.execute_instruction();
+    }
+    assert!(!cpu.bus.dma_active);
+}
+
+#[test]
+fn nmi_during_irq_push_hijacks_the_vector() {
+    let boot = || {
+        //one 16KB bank with the NMI vector at $9000 and the IRQ vector at $A000
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0];
+        rom.resize(16, 0);
+        rom.resize(16 + 0x4000 + 0x2000, 0);
+        rom[16 + 0x3FFA..16 + 0x3FFC].copy_from_slice(&[0x00, 0x90]);
+        rom[16 + 0x3FFE..16 + 0x4000].copy_from_slice(&[0x00, 0xA0]);
+        let mut cpu = CPU::init();
+        cpu.bus.load_cartridge(Mapper::with_cart(Cartridge::from_bytes(rom)));
+        cpu.reset();
+        cpu.status &= !CPU::FLAG_I;
+        cpu.bus.irq = true;
+        cpu
+    };
+
+    let mut cpu = boot();
+    assert_eq!(cpu.execute_instruction(), 5);
+    assert_eq!(cpu.execute_instruction(), 2);
+    assert_eq!(cpu.pc, 0xA000);
+
+    let mut cpu = boot();
+    cpu.execute_instruction();
+    //the ppu raises NMI while the IRQ is still pushing
+    cpu.bus.nmi_request = true;
+    cpu.execute_instruction();
+    assert_eq!(cpu.pc, 0x9000);
+    assert!(!cpu.bus.nmi_request);
+}
+
+#[test]
+fn irq_during_brk_is_absorbed() {
+    let mut cpu = CPU::init();
+    //BRK at $0000
+    cpu.bus.write(0x0000, 0x00);
+    cpu.pc = 0;
+    cpu.status &= !CPU::FLAG_I;
+
+    assert_eq!(cpu.execute_instruction(), 5);
+    //an IRQ comes in while BRK is pushing
+    cpu.bus.irq = true;
+    assert_eq!(cpu.execute_instruction(), 2);
+    assert!(!cpu.bus.irq);
+    //status on the stack has B set
+    assert_ne!(cpu.bus.read(0x0100 + cpu.sp + 1) & CPU::FLAG_B, 0);
+}
+
+#[test]
+fn kil_opcode_halts_until_reset() {
+    let mut cpu = CPU::init();
+    cpu.bus.write(0x0000, 0x02);
+    cpu.pc = 0;
+    assert_eq!(cpu.execute_instruction(), 2);
+    assert!(cpu.halted);
+    //pc stays stuck after the opcode
+    cpu.execute_instruction();
+    assert_eq!(cpu.pc, 1);
+
+    cpu.reset();
+    assert!(!cpu.halted);
+}
+
+#[test]
+fn reset_keeps_ram_and_power_cycle_clears_it() {
+    let mut cpu = CPU::init();
+    cpu.bus.write(0x0300, 0x42);
+    cpu.a = 0x11;
+
+    cpu.reset();
+    assert_eq!(cpu.a, 0);
+    assert_eq!(cpu.bus.read(0x0300), 0x42);
+
+    cpu.bus.reset();
+    cpu.reset();
+    assert_eq!(cpu.bus.read(0x0300), 0);
+}
+
+fn cpu_with_program(program: &[u8]) -> CPU {
+    let mut cpu = CPU::init();
+    for (i, byte) in program.iter().enumerate() {
+        cpu.bus.write(0x0200 + i as u16, *byte);
+    }
+    cpu.pc = 0x0200;
+    cpu
+}
+
+#[test]
+fn unofficial_immediate_opcodes() {
+    //LDA #$C3, ANC #$81
+    let mut cpu = cpu_with_program(&[0xA9, 0xC3, 0x0B, 0x81]);
+    cpu.execute_instruction();
+    cpu.execute_instruction();
+    assert_eq!(cpu.a, 0x81);
+    assert!(cpu.get_flag(CPU::FLAG_C) && cpu.get_flag(CPU::FLAG_N));
+
+    //LDA #$FF, ALR #$03
+    let mut cpu = cpu_with_program(&[0xA9, 0xFF, 0x4B, 0x03]);
+    cpu.execute_instruction();
+    cpu.execute_instruction();
+    assert_eq!(cpu.a, 0x01);
+    assert!(cpu.get_flag(CPU::FLAG_C));
+
+    //SEC, LDA #$FF, ARR #$C0: (A & $C0) >> 1 | carry = $E0, bits 6 and 5 both set
+    let mut cpu = cpu_with_program(&[0x38, 0xA9, 0xFF, 0x6B, 0xC0]);
+    for _ in 0..3 {
+        cpu.execute_instruction();
+    }
+    assert_eq!(cpu.a, 0xE0);
+    assert!(cpu.get_flag(CPU::FLAG_C) && !cpu.get_flag(CPU::FLAG_V));
+
+    //LDA #$0F, LDX #$07, AXS #$02
+    let mut cpu = cpu_with_program(&[0xA9, 0x0F, 0xA2, 0x07, 0xCB, 0x02]);
+    for _ in 0..3 {
+        cpu.execute_instruction();
+    }
+    assert_eq!(cpu.x, 0x05);
+    assert!(cpu.get_flag(CPU::FLAG_C));
+}
+
+#[test]
+fn unofficial_stack_and_store_opcodes() {
+    //LDY #$01, LAS $0300,Y
+    let mut cpu = cpu_with_program(&[0xA0, 0x01, 0xBB, 0x00, 0x03]);
+    cpu.bus.write(0x0301, 0x3C);
+    cpu.execute_instruction();
+    assert_eq!(cpu.execute_instruction(), 4);
+    //sp starts at $FD after reset
+    assert_eq!((cpu.a, cpu.x, cpu.sp), (0x3C, 0x3C, 0x3C));
+
+    //LDX #$FF, LDY #$02, SHX $0400,Y stores X & ($04 + 1)
+    let mut cpu = cpu_with_program(&[0xA2, 0xFF, 0xA0, 0x02, 0x9E, 0x00, 0x04]);
+    for _ in 0..3 {
+        cpu.execute_instruction();
+    }
+    assert_eq!(cpu.bus.read(0x0402), 0x05);
+}
+
+#[test]
+fn step_describes_the_executed_instruction() {
+    //LDA $0300, NOP
+    let mut cpu = cpu_with_program(&[0xAD, 0x00, 0x03, 0xEA]);
+    cpu.bus.write(0x0300, 0x42);
+
+    let step = cpu.step();
+    assert_eq!((step.pc_before, step.opcode, step.addr, step.cycles), (0x0200, 0xAD, 0x0300, 4));
+    assert_eq!(step.disasm, "$0200  AD 00 03  LDA $0300");
+    assert_eq!(cpu.a, 0x42);
+
+    let step = cpu.step();
+    assert_eq!((step.pc_before, step.opcode, step.addr), (0x0203, 0xEA, 0));
+}
+
+#[test]
+fn watchpoint_catches_matching_accesses() {
+    //LDA #$5A, STA $0301, LDX $0301
+    let mut cpu = cpu_with_program(&[0xA9, 0x5A, 0x8D, 0x01, 0x03, 0xAE, 0x01, 0x03]);
+    let read_watch = Watchpoint {
+        addr_range: 0x0300..=0x03FF,
+        on_read: true,
+        on_write: false,
+    };
+    cpu.bus.watchpoints.push(read_watch.clone());
+
+    cpu.execute_instruction();
+    cpu.execute_instruction();
+    assert_eq!(*cpu.bus.watchpoint_hit.borrow(), None);
+
+    cpu.execute_instruction();
+    let hit = cpu.bus.watchpoint_hit.take().unwrap();
+    assert_eq!(hit, WatchpointHit { watchpoint: read_watch, addr: 0x0301, val: 0x5A });
+}
+
+#[test]
+fn breakpoint_halts_until_resumed() {
+    //LDA #$01, LDA #$02, LDA #$03
+    let mut cpu = cpu_with_program(&[0xA9, 0x01, 0xA9, 0x02, 0xA9, 0x03]);
+    cpu.add_breakpoint(0x0202);
+
+    cpu.execute_instruction();
+    assert_eq!(cpu.execute_instruction(), 0);
+    assert!(cpu.halted && cpu.at_breakpoint);
+    assert_eq!((cpu.pc, cpu.a), (0x0202, 0x01));
+
+    cpu.resume();
+    cpu.execute_instruction();
+    assert_eq!(cpu.a, 0x02);
+    assert!(!cpu.halted);
+
+    cpu.remove_breakpoint(0x0202);
+    assert!(cpu.breakpoints.is_empty());
+}
+
+#[test]
+fn breakpoint_on_brk_return_address_waits_for_rti() {
+    //BRK, padding byte, NOP
+    let mut cpu = cpu_with_program(&[0x00, 0x00, 0xEA]);
+    cpu.add_breakpoint(0x0202);
+    let handler = cpu.bus.read_word(0xFFFE);
+
+    let step = cpu.step();
+    assert_eq!((step.pc_before, step.opcode, step.cycles), (0x0200, 0x00, 7));
+    assert_eq!((cpu.pc, cpu.sp), (handler, 0xFA));
+    assert!(!cpu.halted);
+}
+
+#[test]
+fn memory_viewer_edits_bytes_two_digits_at_a_time() {
+    use crate::color::Color;
+    use crate::memory_viewer::{MEMORY_VIEW_WIDTH, MemoryViewer};
+
+    let cpu = CPU::init();
+    assert_eq!(cpu.bus.peek_readable(0x0002), Some(0));
+    //apu registers and open bus show as ??
+    assert_eq!(cpu.bus.peek_readable(0x4015), None);
+    assert_eq!(cpu.bus.peek_readable(0x5000), None);
+
+    let mut viewer = MemoryViewer::new();
+    viewer.scroll(-1);
+    assert_eq!(viewer.base_addr(), 0x0000);
+    viewer.scroll(0x0F);
+    assert_eq!(viewer.base_addr(), 0x00F0);
+    //first byte of the second line
+    assert_eq!(viewer.addr_at(42, 2 + 9 * 2), Some(0x0100));
+    assert_eq!(viewer.addr_at(42, 2), None);
+    //the base address is drawn in the top left with the 8x8 font, '0' starts 2 pixels into its cell
+    let pixels = viewer.render();
+    assert_ne!(pixels[2 * MEMORY_VIEW_WIDTH + 4], Color::BLACK);
+    assert_eq!(pixels[2 * MEMORY_VIEW_WIDTH + 2], Color::BLACK);
+
+    viewer.select(0x01EF);
+    assert_eq!(viewer.type_digit(0x0A), None);
+    assert_eq!(viewer.type_digit(0x05), Some((0x01EF, 0xA5)));
+    //moving past the last byte on screen scrolls a line
+    assert_eq!(viewer.base_addr(), 0x0100);
+    assert_eq!(viewer.type_digit(0x01), None);
+    viewer.cancel_edit();
+    assert_eq!(viewer.type_digit(0x01), None);
+    assert!(!viewer.is_editing());
+}
+
+#[test]
+fn debugger_reads_leave_ppu_registers_alone() {
+    let cpu = CPU::init();
+    {
+        let mut registers = cpu.bus.ppu.registers.borrow_mut();
+        registers.status = 0x80;
+        registers.ppu_addr = 0x2400;
+        registers.data_buffer = 0x5A;
+    }
+
+    assert_eq!(cpu.bus.read_no_side_effects(0x2002) & 0x80, 0x80);
+    assert_eq!(cpu.bus.read_no_side_effects(0x200A) & 0x80, 0x80);
+    assert_eq!(cpu.bus.read_no_side_effects(0x2007), 0x5A);
+    assert_eq!(cpu.bus.ppu.registers.borrow().ppu_addr, 0x2400);
+
+    //a real read does clear vblank
+    assert_eq!(cpu.bus.read(0x2002) & 0x80, 0x80);
+    assert_eq!(cpu.bus.read_no_side_effects(0x2002) & 0x80, 0);
+}
+
+#[test]
+fn status_register_silences_channels_and_acks_frame_irq() {
+    let mut cpu = CPU::init();
+    cpu.bus.write(0x4015, 0x0F);
+    //load every length counter
+    for reg in [0x4003, 0x4007, 0x400B, 0x400F] {
+        cpu.bus.write(reg, 0x08);
+    }
+    assert_eq!(cpu.bus.read(0x4015) & 0x0F, 0x0F);
+
+    //an init routine silencing the pulses leaves triangle and noise going
+    cpu.bus.write(0x4015, 0x0C);
+    assert_eq!(cpu.bus.read(0x4015) & 0x0F, 0x0C);
+
+    cpu.bus.tick_apu(29830, cpu.cpu_cycle);
+    assert_eq!(cpu.bus.read(0x4015) & 0x40, 0x40);
+    assert_eq!(cpu.bus.read(0x4015) & 0x40, 0);
+}
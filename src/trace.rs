@@ -0,0 +1,95 @@
+use std::collections::VecDeque;
+
+use crate::cpu::{format_operand, opcode_info, AddressMode};
+
+//last 1000 instructions
+pub const DEFAULT_TRACE_CAPACITY: usize = 1000;
+
+//cpu state right before an instruction ran
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u8,
+    pub operand: [u8; 2],
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+    pub sp: u8,
+    pub cycle: u64,
+    pub ppu_scanline: u32,
+    pub ppu_dot: u32,
+}
+
+impl TraceEntry {
+    /*
+        Same layout as nestest.log. Memory has moved on since the instruction ran,
+        so operands don't show the "= value" nestest adds after them.
+    */
+    pub fn nestest_line(&self) -> String {
+        let (mnemonic, mode) = opcode_info(self.opcode);
+        let len = 1 + mode.map_or(0, AddressMode::operand_len) as usize;
+        let bytes = [self.opcode, self.operand[0], self.operand[1]][..len]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let instruction = format!(
+            "{:04X}  {:<8} {:>4} {}",
+            self.pc,
+            bytes,
+            mnemonic,
+            format_operand(mode, self.pc, self.operand[0], self.operand[1])
+        );
+        format!(
+            "{:<48}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            instruction.trim_end(),
+            self.a,
+            self.x,
+            self.y,
+            self.status,
+            self.sp,
+            self.ppu_scanline,
+            self.ppu_dot,
+            self.cycle
+        )
+    }
+}
+
+/*
+    Ring buffer of the most recently executed instructions, kept around
+    so a crash can be looked at after the fact. Only the oldest entry is
+    dropped once it's full.
+*/
+pub struct TraceBuffer {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl TraceBuffer {
+    pub fn new(capacity: usize) -> Self {
+        TraceBuffer {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+    //oldest entry first
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        self.entries.iter().copied().collect()
+    }
+}
+
+impl Default for TraceBuffer {
+    fn default() -> Self {
+        TraceBuffer::new(DEFAULT_TRACE_CAPACITY)
+    }
+}
@@ -0,0 +1,33 @@
+use crossbeam_channel::Receiver;
+use sdl2::audio::AudioCallback;
+
+//how many samples the emulator may queue ahead of the audio device
+pub const AUDIO_QUEUE_CAPACITY: usize = 4096;
+
+pub struct AudioOutput {
+    samples: Receiver<f32>,
+    last_sample: f32,
+}
+
+impl AudioOutput {
+    pub fn new(samples: Receiver<f32>) -> Self {
+        AudioOutput {
+            samples,
+            last_sample: 0.0,
+        }
+    }
+}
+
+impl AudioCallback for AudioOutput {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            //on an underrun, hold the last sample rather than dropping to 0, which would click
+            if let Ok(next) = self.samples.try_recv() {
+                self.last_sample = next;
+            }
+            *sample = self.last_sample;
+        }
+    }
+}
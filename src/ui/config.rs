@@ -1,23 +1,57 @@
-use sdl2::rect::Rect;
+use sdl2::rect::FRect;
 
-use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use rnes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//how the NES screen is fitted to the window, S cycles through them in this order
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ScaleMode {
+    //the largest whole multiple that fits, so every NES pixel is the same size
+    #[default]
+    IntegerScale,
+    //one window pixel per NES pixel, centered
+    PixelPerfect,
+    //fills the window, aspect ratio and all
+    Stretch,
+}
+
+impl ScaleMode {
+    pub fn next(self) -> Self {
+        match self {
+            ScaleMode::IntegerScale => ScaleMode::PixelPerfect,
+            ScaleMode::PixelPerfect => ScaleMode::Stretch,
+            ScaleMode::Stretch => ScaleMode::IntegerScale,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            ScaleMode::IntegerScale => "Integer scale",
+            ScaleMode::PixelPerfect => "Pixel perfect",
+            ScaleMode::Stretch => "Stretch",
+        }
+    }
+}
 
 pub struct UiConfig {
-    pub(super) width: u32,
-    pub(super) height: u32,
-    pub(super) scale: u32,
-    pub(super) offset_x: u32,
-    pub(super) offset_y: u32,
-    pub(super) dst_rect: Option<Rect>,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) scale_mode: ScaleMode,
+    //only Stretch gives the axes different or fractional scales
+    pub(crate) scale_x: f32,
+    pub(crate) scale_y: f32,
+    pub(crate) offset_x: f32,
+    pub(crate) offset_y: f32,
+    pub(crate) dst_rect: Option<FRect>,
 }
 impl UiConfig {
-    pub fn new(width: u32, height: u32) -> Self {
+    pub fn new(width: u32, height: u32, scale_mode: ScaleMode) -> Self {
         let mut cfg = UiConfig {
             width,
             height,
-            scale: 0,
-            offset_x: 0,
-            offset_y: 0,
+            scale_mode,
+            scale_x: 0.0,
+            scale_y: 0.0,
+            offset_x: 0.0,
+            offset_y: 0.0,
             dst_rect: None,
         };
         cfg.calculate_scale_and_offsets();
@@ -28,19 +62,28 @@ impl UiConfig {
         let screen_w = SCREEN_WIDTH as u32;
         let screen_h = SCREEN_HEIGHT as u32;
 
-        self.scale = (w / screen_w).min(h / screen_h);
-        assert!(self.scale >= 1, "Window must be at least 256x240px");
-
-        self.offset_x = w - self.scale * screen_w;
-        self.offset_x >>= 1;
+        (self.scale_x, self.scale_y) = match self.scale_mode {
+            ScaleMode::IntegerScale => {
+                //windows smaller than the NES screen just get it cropped
+                let scale = (w / screen_w).min(h / screen_h).max(1) as f32;
+                (scale, scale)
+            }
+            ScaleMode::PixelPerfect => (1.0, 1.0),
+            ScaleMode::Stretch => (w as f32 / screen_w as f32, h as f32 / screen_h as f32),
+        };
+        let dst_w = self.scale_x * screen_w as f32;
+        let dst_h = self.scale_y * screen_h as f32;
 
-        self.offset_y = h - self.scale * screen_h;
-        self.offset_y >>= 1;
-        self.dst_rect = Some(Rect::new(
-            self.offset_x as i32,
-            self.offset_y as i32,
-            SCREEN_WIDTH as u32 * self.scale,
-            SCREEN_HEIGHT as u32 * self.scale,
-        ))
+        //whole pixels so the integer modes stay crisp
+        self.offset_x = ((w as f32 - dst_w).max(0.0) / 2.0).floor();
+        self.offset_y = ((h as f32 - dst_h).max(0.0) / 2.0).floor();
+        self.dst_rect = Some(FRect::new(self.offset_x, self.offset_y, dst_w, dst_h))
+    }
+    //NES pixel under a window position, None over the borders
+    pub fn screen_pixel_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let x = ((x as f32 - self.offset_x) / self.scale_x).floor();
+        let y = ((y as f32 - self.offset_y) / self.scale_y).floor();
+        let on_screen = (0.0..SCREEN_WIDTH as f32).contains(&x) && (0.0..SCREEN_HEIGHT as f32).contains(&y);
+        on_screen.then_some((x as usize, y as usize))
     }
 }
@@ -0,0 +1,44 @@
+use sdl2::{
+    pixels::Color,
+    rect::{FRect, Rect},
+    render::{BlendMode, Canvas, Texture},
+    video::Window,
+};
+
+use rnes::ppu::SCREEN_HEIGHT;
+
+//40% black over every other line
+const STRIPE_ALPHA: u8 = 102;
+
+pub struct CrtEffect {
+    stripe_color: Color,
+}
+
+impl CrtEffect {
+    pub fn new() -> Self {
+        CrtEffect {
+            stripe_color: Color::RGBA(0, 0, 0, STRIPE_ALPHA),
+        }
+    }
+    //draws the NES texture and darkens the odd lines on top of it
+    pub fn apply(&self, canvas: &mut Canvas<Window>, texture: &Texture, src_rect: Option<Rect>, dst_rect: FRect) {
+        canvas.copy_f(texture, src_rect, dst_rect).unwrap();
+        let (old_color, old_blend) = (canvas.draw_color(), canvas.blend_mode());
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(self.stripe_color);
+        canvas.fill_frects(&scanline_stripes(dst_rect)).unwrap();
+        canvas.set_draw_color(old_color);
+        canvas.set_blend_mode(old_blend);
+    }
+}
+
+//one stripe per pair of NES lines, as tall as a line is drawn
+pub fn scanline_stripes(dst_rect: FRect) -> Vec<FRect> {
+    let line_height = dst_rect.height() / SCREEN_HEIGHT as f32;
+    (0..SCREEN_HEIGHT / 2)
+        .map(|pair| {
+            let y = dst_rect.y() + (pair * 2 + 1) as f32 * line_height;
+            FRect::new(dst_rect.x(), y, dst_rect.width(), line_height)
+        })
+        .collect()
+}
@@ -1,7 +0,0 @@
-pub enum UiEvent {
-    Quit,
-    LoadCart(String),
-    ControllerInput(u8),
-}
-
-unsafe impl Send for UiEvent {}
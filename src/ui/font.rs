@@ -0,0 +1,41 @@
+use rnes::font;
+use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
+
+pub use rnes::font::CHAR_SIZE;
+
+//draws the core's 8x8 font onto the canvas
+pub struct Font;
+
+impl Font {
+    //width in pixels of text drawn at the given scale
+    pub fn string_width(text: &str, scale: u32) -> u32 {
+        font::string_width(text, scale)
+    }
+    //one square per set pixel, the text's top left corner at (x, y)
+    pub fn string_rects(text: &str, x: i32, y: i32, scale: u32) -> Vec<Rect> {
+        let mut rects = Vec::new();
+        for (idx, c) in text.chars().enumerate() {
+            let char_x = x + (idx as u32 * CHAR_SIZE * scale) as i32;
+            for (row, bits) in font::glyph(c).into_iter().enumerate() {
+                for col in 0..CHAR_SIZE {
+                    if bits & (0x80 >> col) != 0 {
+                        let px = char_x + (col * scale) as i32;
+                        let py = y + (row as u32 * scale) as i32;
+                        rects.push(Rect::new(px, py, scale, scale));
+                    }
+                }
+            }
+        }
+        rects
+    }
+    pub fn draw_string(canvas: &mut Canvas<Window>, text: &str, x: i32, y: i32, color: Color) {
+        Font::draw_string_scaled(canvas, text, x, y, color, 1);
+    }
+    //each font pixel becomes a scale x scale square
+    pub fn draw_string_scaled(canvas: &mut Canvas<Window>, text: &str, x: i32, y: i32, color: Color, scale: u32) {
+        let old_color = canvas.draw_color();
+        canvas.set_draw_color(color);
+        canvas.fill_rects(&Font::string_rects(text, x, y, scale)).unwrap();
+        canvas.set_draw_color(old_color);
+    }
+}
@@ -0,0 +1,153 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+
+use gif::{Encoder, EncodingError, Frame, Repeat};
+
+use super::screenshot::timestamp;
+use rnes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//frames come from the ui loop, which FRAME_DURATION in ui.rs holds to 60 a second,
+//so every 4th one makes 15fps and 10 seconds of that is as long as a recording gets
+const FRAME_STEP: u32 = 4;
+const MAX_FRAMES: usize = 150;
+//gif delays are in hundredths of a second, 1/15s rounds to 7
+const FRAME_DELAY: u16 = 7;
+const MAX_COLORS: usize = 256;
+
+pub struct GifRecorder {
+    frames: Vec<Frame<'static>>,
+    recording: bool,
+    //frames offered since the recording started, captured or not
+    frame_count: u32,
+}
+
+impl GifRecorder {
+    pub fn new() -> Self {
+        GifRecorder {
+            frames: Vec::new(),
+            recording: false,
+            frame_count: 0,
+        }
+    }
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+    pub fn start(&mut self) {
+        self.frames.clear();
+        self.recording = true;
+        self.frame_count = 0;
+    }
+    //takes an RGBA frame, only every FRAME_STEP-th one is kept
+    pub fn capture(&mut self, framebuffer: &[u8]) {
+        if !self.recording || self.is_full() {
+            return;
+        }
+        self.frame_count += 1;
+        if !(self.frame_count - 1).is_multiple_of(FRAME_STEP) {
+            return;
+        }
+        let (palette, indices) = quantize(framebuffer);
+        self.frames.push(Frame {
+            width: SCREEN_WIDTH as u16,
+            height: SCREEN_HEIGHT as u16,
+            delay: FRAME_DELAY,
+            palette: Some(palette),
+            buffer: Cow::Owned(indices),
+            ..Frame::default()
+        });
+    }
+    pub fn is_full(&self) -> bool {
+        self.frames.len() >= MAX_FRAMES
+    }
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    //stops recording and writes the frames to the current directory, returning the file name
+    pub fn finish(&mut self) -> Result<String, EncodingError> {
+        self.recording = false;
+        let file_name = format!("recording_{}.gif", timestamp());
+        //every frame carries its own palette, so there's no global one
+        let mut encoder = Encoder::new(File::create(&file_name)?, SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16, &[])?;
+        encoder.set_repeat(Repeat::Infinite)?;
+        for frame in self.frames.drain(..) {
+            encoder.write_frame(&frame)?;
+        }
+        Ok(file_name)
+    }
+}
+
+/*
+    Median cut down to 256 colors. The box with the widest channel is
+    split at its pixel-weighted median until every box holds a single
+    color or there are 256 boxes, and each box becomes its weighted
+    average color. A frame rarely uses more than the 64 NES colors, so
+    usually every color ends up in a box of its own and the palette is exact.
+*/
+pub fn quantize(framebuffer: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for pixel in framebuffer.chunks_exact(4) {
+        *counts.entry([pixel[0], pixel[1], pixel[2]]).or_default() += 1;
+    }
+    let mut boxes: Vec<Vec<([u8; 3], u32)>> = vec![counts.into_iter().collect()];
+    while boxes.len() < MAX_COLORS {
+        let Some((idx, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(idx, colors)| {
+                let (channel, range) = widest_channel(colors);
+                (idx, channel, range)
+            })
+            .max_by_key(|&(_, _, range)| range)
+            .map(|(idx, channel, _)| (idx, channel))
+        else {
+            break;
+        };
+        let mut colors = boxes.swap_remove(idx);
+        colors.sort_unstable_by_key(|(color, _)| color[channel]);
+        let half = colors.iter().map(|&(_, count)| count as u64).sum::<u64>() / 2;
+        let mut seen = 0;
+        let split = colors
+            .iter()
+            .position(|&(_, count)| {
+                seen += count as u64;
+                seen > half
+            })
+            .unwrap_or(0)
+            .clamp(1, colors.len() - 1);
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut palette = Vec::with_capacity(boxes.len() * 3);
+    let mut lookup = HashMap::new();
+    for (idx, colors) in boxes.iter().enumerate() {
+        let total = colors.iter().map(|&(_, count)| count as u64).sum::<u64>().max(1);
+        for channel in 0..3 {
+            let sum: u64 = colors.iter().map(|&(color, count)| color[channel] as u64 * count as u64).sum();
+            palette.push((sum / total) as u8);
+        }
+        for &(color, _) in colors {
+            lookup.insert(color, idx as u8);
+        }
+    }
+    let indices = framebuffer
+        .chunks_exact(4)
+        .map(|pixel| lookup[&[pixel[0], pixel[1], pixel[2]]])
+        .collect();
+    (palette, indices)
+}
+
+fn widest_channel(colors: &[([u8; 3], u32)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = colors.iter().fold((u8::MAX, u8::MIN), |(min, max), (color, _)| {
+                (min.min(color[channel]), max.max(color[channel]))
+            });
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap()
+}
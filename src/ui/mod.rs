@@ -1,6 +1,8 @@
-mod config;
-mod event;
+pub mod audio;
+pub mod config;
+pub mod crt;
+pub mod font;
+pub mod gif_recorder;
 pub mod ui;
-pub use event::*;
-pub use ui::RnesUI;
-pub mod frame_buffer;
+pub use ui::{RnesUI, WINDOW_TITLE};
+pub mod screenshot;
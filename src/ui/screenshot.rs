@@ -0,0 +1,44 @@
+use image::RgbaImage;
+
+use rnes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//always the native 256x240, the render scale only applies to the window
+pub fn framebuffer_to_image(framebuffer: &[u8]) -> RgbaImage {
+    RgbaImage::from_raw(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, framebuffer.to_vec())
+        .expect("framebuffer is the size of the screen")
+}
+
+//saves to the current directory and returns the file name
+pub fn save_screenshot(framebuffer: &[u8]) -> Result<String, image::ImageError> {
+    let file_name = format!("screenshot_{}.png", timestamp());
+    framebuffer_to_image(framebuffer).save(&file_name)?;
+    Ok(file_name)
+}
+
+//YYYYMMDD_HHMMSS in UTC, std has no time zone support
+pub fn timestamp() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day, hour, minute, second) = date_time(secs);
+    format!("{year:04}{month:02}{day:02}_{hour:02}{minute:02}{second:02}")
+}
+
+//seconds since the unix epoch to (year, month, day, hour, minute, second) in UTC
+pub fn date_time(secs: u64) -> (i64, i64, i64, u64, u64, u64) {
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    //days since the epoch to a civil date, from Howard Hinnant's date algorithms
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day, secs_of_day / 3600, (secs_of_day / 60) % 60, secs_of_day % 60)
+}
@@ -1,32 +1,106 @@
-use crossbeam_channel::Sender;
+use crossbeam_channel::{Receiver, Sender};
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use nfd::Response;
 use sdl2::{
     EventPump,
-    event::Event,
-    keyboard::Mod,
-    pixels::{Color, PixelFormatEnum},
+    controller::GameController,
+    haptic::Haptic,
+    event::{Event, WindowEvent},
+    keyboard::{Keycode, Mod},
+    mouse::MouseButton,
+    pixels::PixelFormatEnum,
+    rect::Rect,
     render::{Canvas, Texture, TextureCreator},
-    video::{Window, WindowContext},
+    video::{FullscreenType, Window, WindowContext},
 };
 
-use super::config::UiConfig;
-use super::event::UiEvent;
-use crate::{
-    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
-    ui::frame_buffer::DoubleBuffer,
+use super::config::{ScaleMode, UiConfig};
+use super::crt::CrtEffect;
+use super::gif_recorder::GifRecorder;
+use super::font::{CHAR_SIZE, Font};
+use super::screenshot;
+use rnes::event::UiEvent;
+use rnes::{
+    apu::AuChannel,
+    color::Color,
+    config::Config,
+    input::Zapper,
+    memory_viewer::{MEMORY_VIEW_HEIGHT, MEMORY_VIEW_WIDTH, MemoryViewer},
+    palette_viewer::{self, PALETTE_VIEW_HEIGHT, PALETTE_VIEW_WIDTH},
+    pattern_viewer::{PATTERN_VIEW_HEIGHT, PATTERN_VIEW_WIDTH, PatternTableViewer},
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH, SpriteInfo},
+    rom_db::RomDatabase,
+    sprite_viewer::{self, SPRITE_VIEW_HEIGHT, SPRITE_VIEW_WIDTH},
+    savestate::{self, SAVE_SLOTS, SaveState},
+    frame_buffer::SharedFramebuffer,
 };
 
 pub struct RnesUI<'a> {
     canvas: Canvas<Window>,
     cfg: UiConfig,
+    is_fullscreen: bool,
     event_pump: EventPump,
     event_send: Sender<UiEvent>,
-    nes_input_state: u8,
+    //events coming back from the emulator thread
+    emulator_events: Option<Receiver<UiEvent>>,
+    //controller state from the keyboard, per player
+    keyboard_state: [u8; 2],
+    key_map: HashMap<Keycode, (usize, u8)>,
+    //one entry per player, opened in player order
+    game_controllers: Vec<GameController>,
+    //the first of those that can rumble, if any
+    haptic: Option<Haptic>,
+    //only present when the config plugs a Zapper into port 2
+    zapper: Option<Zapper>,
+    //buttons and thumbstick are tracked separately so releasing one doesn't cancel the other
+    gamepad_button_state: [u8; 2],
+    gamepad_axis_state: [u8; 2],
+    //path of the last rom sent to the emulator, save states are stored next to it
+    rom_path: Option<String>,
+    rewind_held: bool,
+    recording: bool,
+    //RNES plus the loaded rom
+    window_title: String,
+    //Ctrl+G starts and stops it
+    gif_recorder: GifRecorder,
+    //scanlines drawn over the screen, toggled with Shift+F12
+    crt_effect: Option<CrtEffect>,
+    //frames this thread has drawn, shown with the game's title in the top right while F1 has it on
+    show_fps: bool,
+    fps_display: String,
+    frames_rendered: u32,
+    fps_timer: Instant,
+    //from the rom database, None for roms it doesn't know
+    game_title: Option<String>,
+    //follows the pause key and breakpoints, the emulator doesn't report it back
+    paused: bool,
+    //short notices like save states, with when they were first shown
+    osd_message: Option<(String, Instant)>,
+    //kept so volume changes can be written back
+    config: Config,
+    //toggled with 1-5, in AuChannel order
+    muted_channels: [bool; 5],
     texture_creator: &'a TextureCreator<WindowContext>,
     texture: Texture<'a>,
-    framebuffer: Arc<DoubleBuffer>,
+    framebuffer: Arc<SharedFramebuffer>,
+    //the pattern table overlay, toggled with F3
+    pattern_viewer: Option<PatternTableViewer>,
+    pattern_texture: Texture<'a>,
+    //the OAM sprite overlay, toggled with F6, keeps the last sprites so clicks can print them
+    sprite_viewer: Option<Vec<SpriteInfo>>,
+    sprite_texture: Texture<'a>,
+    //the palette overlay, toggled with F7, keeps the last palette ram entries for hovering
+    palette_viewer: Option<[u8; 32]>,
+    palette_texture: Texture<'a>,
+    //address and value of the swatch under the mouse, drawn above the overlay
+    palette_hover: Option<String>,
+    //the memory viewer overlay, toggled with F10
+    memory_viewer: Option<MemoryViewer>,
+    memory_texture: Texture<'a>,
 }
 
 impl<'a> RnesUI<'a> {
@@ -36,9 +110,10 @@ impl<'a> RnesUI<'a> {
         width: u32,
         height: u32,
         event_send: Sender<UiEvent>,
-        canvas: Canvas<Window>,
+        mut canvas: Canvas<Window>,
         texture_creator: &'a TextureCreator<WindowContext>,
-        framebuffer: Arc<DoubleBuffer>,
+        framebuffer: Arc<SharedFramebuffer>,
+        config: &Config,
     ) -> Self {
         let sdl_context = sdl2::init().unwrap();
         let video = sdl_context.video().unwrap();
@@ -56,8 +131,23 @@ impl<'a> RnesUI<'a> {
             height
         };
 
-        let cfg = UiConfig::new(width, height);
+        let cfg = UiConfig::new(width, height, ScaleMode::default());
         let event_pump = sdl_context.event_pump().unwrap();
+
+        let controller_subsystem = sdl_context.game_controller().unwrap();
+        let joystick_count = controller_subsystem.num_joysticks().unwrap_or(0);
+        let controllers: Vec<(u32, GameController)> = (0..joystick_count)
+            .filter(|&id| controller_subsystem.is_game_controller(id))
+            .filter_map(|id| controller_subsystem.open(id).ok().map(|controller| (id, controller)))
+            .take(2)
+            .collect();
+        //not every controller has a motor, and SDL may be built without haptics at all
+        let haptic = sdl_context.haptic().ok().and_then(|haptic_subsystem| {
+            controllers
+                .iter()
+                .find_map(|(id, _)| haptic_subsystem.open_from_joystick_id(*id).ok())
+        });
+        let game_controllers = controllers.into_iter().map(|(_, controller)| controller).collect();
         let texture = texture_creator
             .create_texture_streaming(
                 PixelFormatEnum::RGBA32,
@@ -65,20 +155,85 @@ impl<'a> RnesUI<'a> {
                 SCREEN_HEIGHT as u32,
             )
             .unwrap();
+        let pattern_texture = texture_creator
+            .create_texture_streaming(
+                PixelFormatEnum::RGBA32,
+                PATTERN_VIEW_WIDTH as u32,
+                PATTERN_VIEW_HEIGHT as u32,
+            )
+            .unwrap();
+        let sprite_texture = texture_creator
+            .create_texture_streaming(
+                PixelFormatEnum::RGBA32,
+                SPRITE_VIEW_WIDTH as u32,
+                SPRITE_VIEW_HEIGHT as u32,
+            )
+            .unwrap();
+        let palette_texture = texture_creator
+            .create_texture_streaming(
+                PixelFormatEnum::RGBA32,
+                PALETTE_VIEW_WIDTH as u32,
+                PALETTE_VIEW_HEIGHT as u32,
+            )
+            .unwrap();
+        let memory_texture = texture_creator
+            .create_texture_streaming(
+                PixelFormatEnum::RGBA32,
+                MEMORY_VIEW_WIDTH as u32,
+                MEMORY_VIEW_HEIGHT as u32,
+            )
+            .unwrap();
+        //nothing is loaded yet, so point at what Ctrl+1 would open
+        let window_title = match config.recent_files.get(0) {
+            Some(recent) => format!("{WINDOW_TITLE} - Ctrl+1: {recent}"),
+            None => WINDOW_TITLE.to_string(),
+        };
+        canvas.window_mut().set_title(&window_title).unwrap();
         RnesUI {
             canvas,
             cfg,
+            is_fullscreen: false,
             event_send,
             event_pump,
-            nes_input_state: 0,
+            keyboard_state: [0; 2],
+            key_map: config.key_bindings.key_map(),
+            game_controllers,
+            haptic,
+            zapper: config.zapper.then(Zapper::default),
+            gamepad_button_state: [0; 2],
+            gamepad_axis_state: [0; 2],
+            rom_path: None,
+            rewind_held: false,
+            recording: false,
+            window_title,
+            crt_effect: config.crt_effect.then(CrtEffect::new),
+            gif_recorder: GifRecorder::new(),
+            show_fps: false,
+            fps_display: String::new(),
+            frames_rendered: 0,
+            fps_timer: Instant::now(),
+            game_title: None,
+            paused: false,
+            osd_message: None,
+            config: config.clone(),
+            muted_channels: [false; 5],
             texture_creator,
             texture,
             framebuffer,
+            emulator_events: None,
+            pattern_viewer: None,
+            pattern_texture,
+            sprite_viewer: None,
+            sprite_texture,
+            palette_viewer: None,
+            palette_texture,
+            palette_hover: None,
+            memory_viewer: None,
+            memory_texture,
         }
     }
     fn handle_input(&mut self) -> bool {
         for event in self.event_pump.poll_iter() {
-            use sdl2::keyboard::Keycode;
             match event {
                 Event::Quit { .. } => {
                     self.event_send.send(UiEvent::Quit).unwrap();
@@ -87,31 +242,30 @@ impl<'a> RnesUI<'a> {
                 Event::KeyDown {
                     keycode: Some(keycode),
                     keymod,
+                    repeat,
                     ..
                 } => match keycode {
-                    Keycode::X => {
-                        self.nes_input_state |= 1;
-                    }
-                    Keycode::Z => {
-                        self.nes_input_state |= 1 << 1;
-                    }
-                    Keycode::LSHIFT | Keycode::RSHIFT => {
-                        self.nes_input_state |= 1 << 2;
-                    }
-                    Keycode::Return => {
-                        self.nes_input_state |= 1 << 3;
-                    }
-                    Keycode::Up => {
-                        self.nes_input_state |= 1 << 4;
-                    }
-                    Keycode::Down => {
-                        self.nes_input_state |= 1 << 5;
+                    //while a byte is selected in the memory viewer hex digits edit it rather than press buttons
+                    _ if self.memory_viewer.as_ref().is_some_and(MemoryViewer::is_editing)
+                        && hex_digit(keycode).is_some() =>
+                    {
+                        if let Some(viewer) = &mut self.memory_viewer
+                            && let Some((addr, val)) = viewer.type_digit(hex_digit(keycode).unwrap())
+                        {
+                            self.event_send.send(UiEvent::WriteMemory(addr, val)).unwrap();
+                            self.event_send.send(UiEvent::SetMemoryViewer(Some(viewer.base_addr()))).unwrap();
+                        }
                     }
-                    Keycode::Left => {
-                        self.nes_input_state |= 1 << 6;
+                    Keycode::Escape if self.memory_viewer.as_ref().is_some_and(MemoryViewer::is_editing) => {
+                        if let Some(viewer) = &mut self.memory_viewer {
+                            viewer.cancel_edit();
+                        }
                     }
-                    Keycode::Right => {
-                        self.nes_input_state |= 1 << 7;
+                    Keycode::PageUp | Keycode::PageDown if self.memory_viewer.is_some() => {
+                        if let Some(viewer) = &mut self.memory_viewer {
+                            viewer.scroll(if keycode == Keycode::PageUp { -16 } else { 16 });
+                            self.event_send.send(UiEvent::SetMemoryViewer(Some(viewer.base_addr()))).unwrap();
+                        }
                     }
                     Keycode::O if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
                         if let Ok(result) =
@@ -128,77 +282,633 @@ impl<'a> RnesUI<'a> {
                             }
                         }
                     }
-                    _ => {
-                        return true;
+                    Keycode::Backspace => {
+                        self.rewind_held = true;
+                    }
+                    Keycode::Tab => {
+                        self.event_send.send(UiEvent::SetSpeed(2.0)).unwrap();
+                    }
+                    //holding a toggle key shouldn't flip it back and forth
+                    Keycode::Escape if !repeat => {
+                        self.paused = !self.paused;
+                        self.event_send.send(UiEvent::TogglePause).unwrap();
+                    }
+                    Keycode::N => {
+                        self.event_send.send(UiEvent::FrameAdvance).unwrap();
+                    }
+                    Keycode::P if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            self.event_send.send(UiEvent::StopPlayback).unwrap();
+                        } else if let Ok(Response::Okay(file_path)) =
+                            nfd::open_dialog(Some("fm2"), None, nfd::DialogType::SingleFile)
+                        {
+                            self.event_send
+                                .send(UiEvent::StartPlayback(file_path))
+                                .unwrap();
+                        }
+                    }
+                    //recording is only updated once the emulator says it started or stopped
+                    Keycode::R if !repeat && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                        if self.recording {
+                            self.event_send.send(UiEvent::StopRecording).unwrap();
+                        } else if let Ok(Response::Okay(file_path)) =
+                            nfd::open_save_dialog(Some("fm2"), None)
+                        {
+                            self.event_send
+                                .send(UiEvent::ToggleRecording(file_path))
+                                .unwrap();
+                        }
+                    }
+                    Keycode::R if !repeat && keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                        self.event_send.send(UiEvent::PowerCycle).unwrap();
+                    }
+                    Keycode::R if !repeat => {
+                        self.event_send.send(UiEvent::Reset).unwrap();
+                    }
+                    //Ctrl+1 to Ctrl+9 open the recent files newest first, Ctrl+0 the tenth
+                    _ if !repeat && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) && recent_file_slot(keycode).is_some() => {
+                        let idx = recent_file_slot(keycode).unwrap();
+                        self.event_send.send(UiEvent::LoadRecentCart(idx)).unwrap();
+                    }
+                    Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5 if !repeat => {
+                        let idx = keycode.into_i32() as usize - Keycode::Num1.into_i32() as usize;
+                        self.muted_channels[idx] = !self.muted_channels[idx];
+                        self.event_send
+                            .send(UiEvent::MuteChannel(AuChannel::ALL[idx], self.muted_channels[idx]))
+                            .unwrap();
                     }
+                    Keycode::Minus | Keycode::Equals => {
+                        let step = if keycode == Keycode::Minus { -0.1 } else { 0.1 };
+                        let vol = self.config.volume.master + step;
+                        Self::set_master_volume(&mut self.config, &self.event_send, vol);
+                    }
+                    Keycode::F11 if !repeat => {
+                        let mode = if self.is_fullscreen {
+                            FullscreenType::Off
+                        } else {
+                            FullscreenType::Desktop
+                        };
+                        match self.canvas.window_mut().set_fullscreen(mode) {
+                            Ok(()) => {
+                                self.is_fullscreen = !self.is_fullscreen;
+                                let (width, height) = self.canvas.output_size().unwrap();
+                                self.cfg = UiConfig::new(width, height, self.cfg.scale_mode);
+                            }
+                            Err(err) => eprintln!("Failed to toggle fullscreen: {err}"),
+                        }
+                    }
+                    Keycode::F2 if !repeat => match screenshot::save_screenshot(self.framebuffer.read_front_buffer()) {
+                        Ok(file_name) => println!("Saved screenshot to {file_name}"),
+                        Err(err) => eprintln!("Failed to save screenshot: {err}"),
+                    },
+                    Keycode::F8 if !repeat => {
+                        if let Ok(Response::Okay(file_path)) = nfd::open_save_dialog(Some("log"), None) {
+                            self.event_send.send(UiEvent::DumpTrace(file_path)).unwrap();
+                        }
+                    }
+                    //plain S is a player 2 button in the default bindings
+                    Keycode::S if !repeat && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                        let (width, height) = self.canvas.output_size().unwrap();
+                        self.cfg = UiConfig::new(width, height, self.cfg.scale_mode.next());
+                        self.osd_message = Some((self.cfg.scale_mode.name().to_string(), Instant::now()));
+                    }
+                    //plain G is a player 2 button in the default bindings
+                    Keycode::G if !repeat && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                        if self.gif_recorder.is_recording() {
+                            Self::save_gif(&mut self.gif_recorder);
+                        } else {
+                            self.gif_recorder.start();
+                            println!("Recording gif, Ctrl+G again to stop");
+                        }
+                    }
+                    //Shift+F1-F8 save to a slot and Ctrl+F1-F8 load it, plain F keys have their own jobs
+                    _ if !repeat && keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) && save_slot(keycode).is_some() => {
+                        self.event_send.send(UiEvent::SaveSlot(save_slot(keycode).unwrap())).unwrap();
+                    }
+                    _ if !repeat && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) && save_slot(keycode).is_some() => {
+                        self.event_send.send(UiEvent::LoadSlot(save_slot(keycode).unwrap())).unwrap();
+                    }
+                    Keycode::F1 if !repeat => self.show_fps = !self.show_fps,
+                    Keycode::F12 if !repeat && keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                        self.crt_effect = match self.crt_effect {
+                            Some(_) => None,
+                            None => Some(CrtEffect::new()),
+                        };
+                    }
+                    Keycode::F12 if !repeat => {
+                        self.event_send.send(UiEvent::ToggleNtscFilter).unwrap();
+                    }
+                    Keycode::F3 if !repeat => {
+                        self.pattern_viewer = match self.pattern_viewer {
+                            Some(_) => None,
+                            None => Some(PatternTableViewer::new()),
+                        };
+                        self.event_send.send(UiEvent::SetPatternViewer(self.pattern_viewer)).unwrap();
+                    }
+                    //cycles through the background palettes the tiles are drawn with
+                    Keycode::F4 if !repeat && self.pattern_viewer.is_some() => {
+                        if let Some(viewer) = &mut self.pattern_viewer {
+                            viewer.palette = (viewer.palette + 1) % 4;
+                        }
+                        self.event_send.send(UiEvent::SetPatternViewer(self.pattern_viewer)).unwrap();
+                    }
+                    Keycode::F6 if !repeat => {
+                        self.sprite_viewer = match self.sprite_viewer {
+                            Some(_) => None,
+                            None => Some(Vec::new()),
+                        };
+                        self.event_send.send(UiEvent::ShowSpriteViewer(self.sprite_viewer.is_some())).unwrap();
+                    }
+                    Keycode::F7 if !repeat => {
+                        self.palette_viewer = match self.palette_viewer {
+                            Some(_) => None,
+                            None => Some([0; 32]),
+                        };
+                        self.palette_hover = None;
+                        self.event_send.send(UiEvent::ShowPaletteViewer(self.palette_viewer.is_some())).unwrap();
+                    }
+                    Keycode::F10 if !repeat => {
+                        self.memory_viewer = match self.memory_viewer {
+                            Some(_) => None,
+                            None => Some(MemoryViewer::new()),
+                        };
+                        let base_addr = self.memory_viewer.as_ref().map(MemoryViewer::base_addr);
+                        self.event_send.send(UiEvent::SetMemoryViewer(base_addr)).unwrap();
+                    }
+                    Keycode::F5 => {
+                        self.event_send.send(UiEvent::SaveState).unwrap();
+                        self.osd_message = Some(("State saved".to_string(), Instant::now()));
+                    }
+                    Keycode::F9 => {
+                        if let Some(rom_path) = &self.rom_path {
+                            match SaveState::read_from_file(savestate::state_path(rom_path)) {
+                                Ok(state) => {
+                                    self.event_send.send(UiEvent::LoadState(Box::new(state))).unwrap();
+                                    self.osd_message = Some(("State loaded".to_string(), Instant::now()));
+                                }
+                                Err(err) => eprintln!("Failed to read save state: {err}"),
+                            }
+                        }
+                    }
+                    _ => match self.key_map.get(&keycode) {
+                        Some(&(player, bit)) => self.keyboard_state[player] |= bit,
+                        None => return true,
+                    },
                 },
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
                 } => match keycode {
-                    Keycode::X => {
-                        self.nes_input_state &= !1;
+                    Keycode::Backspace => {
+                        self.rewind_held = false;
+                    }
+                    Keycode::Tab => {
+                        self.event_send.send(UiEvent::SetSpeed(1.0)).unwrap();
                     }
-                    Keycode::Z => {
-                        self.nes_input_state &= !(1 << 1);
+                    _ => {
+                        if let Some(&(player, bit)) = self.key_map.get(&keycode) {
+                            self.keyboard_state[player] &= !bit;
+                        }
                     }
-                    Keycode::LShift | Keycode::RShift => {
-                        self.nes_input_state &= !(1 << 2);
+                },
+                //file managers can drop a rom on the window instead of going through Ctrl+O
+                Event::DropFile { filename, .. } => {
+                    if is_loadable_rom(&filename) {
+                        self.event_send.send(UiEvent::LoadCart(filename)).unwrap();
+                    } else {
+                        eprintln!("Ignoring dropped file {filename}, only .nes and .nsf files can be loaded");
+                    }
+                }
+                //the texture is always 256x240, so only the destination rect needs updating.
+                //SizeChanged also covers fullscreen switches that finish after set_fullscreen returns
+                Event::Window {
+                    win_event: WindowEvent::Resized(..) | WindowEvent::SizeChanged(..),
+                    ..
+                } => {
+                    let (width, height) = self.canvas.output_size().unwrap();
+                    self.cfg = UiConfig::new(width, height, self.cfg.scale_mode);
+                }
+                //clicking a tile in the pattern table overlay highlights it
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    if let Some(zapper) = &mut self.zapper {
+                        zapper.aim = self.cfg.screen_pixel_at(x, y);
+                        zapper.trigger = true;
                     }
-                    Keycode::Return => {
-                        self.nes_input_state &= !(1 << 3);
+                    let overlay = pattern_overlay_rect();
+                    if let Some(viewer) = &mut self.pattern_viewer
+                        && overlay.contains_point((x, y))
+                    {
+                        let view_x = (x - overlay.x()) as usize / OVERLAY_SCALE;
+                        let view_y = (y - overlay.y()) as usize / OVERLAY_SCALE;
+                        viewer.selected_tile = Some(PatternTableViewer::tile_at(view_x, view_y));
+                        self.event_send.send(UiEvent::SetPatternViewer(self.pattern_viewer)).unwrap();
                     }
-                    Keycode::Up => {
-                        self.nes_input_state &= !(1 << 4);
+                    //clicking a byte in the memory viewer selects it for editing
+                    let overlay = memory_overlay_rect(self.canvas.output_size().unwrap());
+                    if let Some(viewer) = &mut self.memory_viewer
+                        && overlay.contains_point((x, y))
+                    {
+                        let view_x = (x - overlay.x()) as usize / OVERLAY_SCALE;
+                        let view_y = (y - overlay.y()) as usize / OVERLAY_SCALE;
+                        match viewer.addr_at(view_x, view_y) {
+                            Some(addr) => viewer.select(addr),
+                            None => viewer.cancel_edit(),
+                        }
                     }
-                    Keycode::Down => {
-                        self.nes_input_state &= !(1 << 5);
+                    //clicking a sprite in the sprite overlay prints its OAM entry
+                    let overlay = sprite_overlay_rect(self.canvas.output_size().unwrap().0);
+                    if let Some(sprites) = &self.sprite_viewer
+                        && overlay.contains_point((x, y))
+                    {
+                        let view_x = (x - overlay.x()) as usize / OVERLAY_SCALE;
+                        let view_y = (y - overlay.y()) as usize / OVERLAY_SCALE;
+                        if let Some(sprite) = sprite_viewer::sprite_at(view_x, view_y).and_then(|i| sprites.get(i)) {
+                            let [y, tile, attribute, x] = sprite.oam;
+                            println!("Sprite {:02}: {:02X} {:02X} {:02X} {:02X}", sprite.index, y, tile, attribute, x);
+                        }
                     }
-                    Keycode::Left => {
-                        self.nes_input_state &= !(1 << 6);
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    if let Some(zapper) = &mut self.zapper {
+                        zapper.trigger = false;
                     }
-                    Keycode::Right => {
-                        self.nes_input_state &= !(1 << 7);
+                }
+                Event::MouseMotion { x, y, .. } => {
+                    if let Some(zapper) = &mut self.zapper {
+                        zapper.aim = self.cfg.screen_pixel_at(x, y);
                     }
-                    _ => {}
-                },
+                    let Some(entries) = &self.palette_viewer else {
+                        continue;
+                    };
+                    let overlay = palette_overlay_rect(self.canvas.output_size().unwrap().1);
+                    self.palette_hover = Some(overlay)
+                        .filter(|overlay| overlay.contains_point((x, y)))
+                        .and_then(|overlay| {
+                            palette_viewer::palette_addr_at(
+                                (x - overlay.x()) as usize / OVERLAY_SCALE,
+                                (y - overlay.y()) as usize / OVERLAY_SCALE,
+                            )
+                        })
+                        .map(|addr| {
+                            let entry = entries[(addr - 0x3F00) as usize];
+                            format!("${addr:04X}: ${entry:02X}")
+                        });
+                }
+                Event::MouseWheel { y, .. } => {
+                    if let Some(viewer) = &mut self.memory_viewer {
+                        viewer.scroll(-y);
+                        self.event_send.send(UiEvent::SetMemoryViewer(Some(viewer.base_addr()))).unwrap();
+                    }
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if let Some(player) = Self::controller_player(&self.game_controllers, which) {
+                        self.gamepad_button_state[player] |= self.config.controller.button_bit(button);
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    if let Some(player) = Self::controller_player(&self.game_controllers, which) {
+                        self.gamepad_button_state[player] &= !self.config.controller.button_bit(button);
+                    }
+                }
+                Event::ControllerAxisMotion { which, axis, value, .. } => {
+                    if let Some(player) = Self::controller_player(&self.game_controllers, which) {
+                        let state = &mut self.gamepad_axis_state[player];
+                        *state &= !self.config.controller.axis_mask(axis);
+                        *state |= self.config.controller.axis_bits(axis, value);
+                    }
+                }
                 _ => {}
             }
         }
+        let player1 = self.keyboard_state[0] | self.gamepad_button_state[0] | self.gamepad_axis_state[0];
+        let player2 = self.keyboard_state[1] | self.gamepad_button_state[1] | self.gamepad_axis_state[1];
+        let _ = self.event_send
+            .send(UiEvent::ControllerInput(player1));
         let _ = self.event_send
-            .send(UiEvent::ControllerInput(self.nes_input_state));
+            .send(UiEvent::ControllerInput2(player2));
+        if let Some(zapper) = self.zapper {
+            let _ = self.event_send.send(UiEvent::ZapperInput(zapper));
+        }
+        if self.rewind_held {
+            let _ = self.event_send.send(UiEvent::RewindFrame);
+        }
         true
     }
-    fn render_nes_framebuffer(&mut self, framebuffer: &[Color]) {
+    //takes the recorder directly for the same reason as controller_player
+    fn save_gif(gif_recorder: &mut GifRecorder) {
+        let frames = gif_recorder.frame_count();
+        match gif_recorder.finish() {
+            Ok(file_name) => println!("Saved {frames} frames to {file_name}"),
+            Err(err) => eprintln!("Failed to save gif: {err}"),
+        }
+    }
+    //takes the controller list directly since the event pump holds a borrow of self
+    fn controller_player(game_controllers: &[GameController], instance_id: u32) -> Option<usize> {
+        game_controllers
+            .iter()
+            .position(|controller| controller.instance_id() == instance_id)
+    }
+    //the frame is already RGBA bytes, so whole rows are copied at once
+    //takes the fields directly for the same reason as controller_player
+    fn set_master_volume(config: &mut Config, event_send: &Sender<UiEvent>, vol: f32) {
+        //rounded so repeated steps don't drift away from tenths
+        config.volume.master = (vol.clamp(0.0, 1.0) * 10.0).round() / 10.0;
+        event_send.send(UiEvent::SetVolume(config.volume.master)).unwrap();
+        if let Err(err) = config.write_to_file(Config::default_path()) {
+            eprintln!("Failed to save volume: {err}");
+        }
+    }
+    fn render_nes_framebuffer(&mut self, framebuffer: &[u8]) {
+        let row_len = SCREEN_WIDTH * 4;
         self.texture
             .with_lock(None, |buffer, pitch| {
-                for y in 0..SCREEN_HEIGHT {
-                    let offset_tex = y * pitch;
-                    let offset_src = y * SCREEN_WIDTH;
-                    for x in 0..SCREEN_WIDTH {
-                        let color = framebuffer[offset_src + x];
-                        let pixel_offset = offset_tex + x * 4;
-
-                        buffer[pixel_offset..pixel_offset + 4]
-                            .copy_from_slice(&[color.r, color.g, color.b, color.a]);
-                    }
+                for (y, row) in framebuffer.chunks_exact(row_len).enumerate() {
+                    buffer[y * pitch..y * pitch + row_len].copy_from_slice(row);
                 }
             })
             .unwrap();
     }
+    pub fn set_emulator_events(&mut self, emulator_events: Receiver<UiEvent>) {
+        self.emulator_events = Some(emulator_events);
+    }
+    fn handle_emulator_events(&mut self) {
+        let Some(emulator_events) = &self.emulator_events else {
+            return;
+        };
+        for event in emulator_events.try_iter() {
+            match event {
+                UiEvent::WatchpointHit(addr, val) => println!("Watchpoint hit at ${addr:04X} = ${val:02X}"),
+                UiEvent::SlotUsed { slot, saved, saved_at } => {
+                    let (year, month, day, hour, minute, _) = screenshot::date_time(saved_at);
+                    let message = if saved {
+                        format!("Saved slot {slot}")
+                    } else {
+                        format!("Loaded slot {slot} from {year:04}-{month:02}-{day:02} {hour:02}:{minute:02}")
+                    };
+                    self.osd_message = Some((message, Instant::now()));
+                }
+                UiEvent::RecordingChanged(recording) => self.recording = recording,
+                UiEvent::BreakpointHit(state) => {
+                    println!("Breakpoint hit: {state}");
+                    self.paused = true;
+                }
+                UiEvent::CartridgeLoaded(file_path, sha1) => {
+                    let file_name = Path::new(&file_path).file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    let info = RomDatabase::lookup(&sha1);
+                    self.game_title = info.as_ref().map(|info| info.title.clone());
+                    self.window_title = match info {
+                        Some(info) => format!("{WINDOW_TITLE} - {}", info.title),
+                        None => {
+                            //the last 4 bytes are enough to tell dumps apart at a glance
+                            let short_hash: String = sha1[16..].iter().map(|byte| format!("{byte:02x}")).collect();
+                            format!("{WINDOW_TITLE} - {file_name} [{short_hash}]")
+                        }
+                    };
+                    self.canvas.window_mut().set_title(&self.window_title).unwrap();
+                    self.config.recent_files.push(&file_path);
+                    if let Err(err) = self.config.write_to_file(Config::default_path()) {
+                        eprintln!("Failed to save recent files: {err}");
+                    }
+                    self.rom_path = Some(file_path);
+                }
+                UiEvent::PatternTables(pixels) => {
+                    copy_to_texture(&mut self.pattern_texture, &pixels[..], PATTERN_VIEW_WIDTH, PATTERN_VIEW_HEIGHT)
+                }
+                //sprites arriving after the viewer was closed are dropped
+                UiEvent::OamSprites(sprites) if self.sprite_viewer.is_some() => {
+                    let pixels = sprite_viewer::sprite_grid(&sprites);
+                    copy_to_texture(&mut self.sprite_texture, &pixels[..], SPRITE_VIEW_WIDTH, SPRITE_VIEW_HEIGHT);
+                    self.sprite_viewer = Some(sprites);
+                }
+                UiEvent::Palettes(colors, entries) if self.palette_viewer.is_some() => {
+                    let pixels = palette_viewer::palette_view(&colors);
+                    copy_to_texture(&mut self.palette_texture, &pixels[..], PALETTE_VIEW_WIDTH, PALETTE_VIEW_HEIGHT);
+                    self.palette_viewer = Some(entries);
+                }
+                UiEvent::MemoryPage(base_addr, bytes) => {
+                    if let Some(viewer) = &mut self.memory_viewer {
+                        viewer.set_bytes(base_addr, bytes);
+                    }
+                }
+                UiEvent::ControllerRumble { duration_ms, strength } => {
+                    if let Some(haptic) = &mut self.haptic {
+                        haptic.rumble_play(strength.clamp(0.0, 1.0), duration_ms);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
     pub fn run(&mut self) {
         'running: loop {
+            let frame_start = Instant::now();
             //A quit event returns false and sends a quit signal to the emulator thread.
             if !self.handle_input() {
                 break 'running;
             }
+            self.handle_emulator_events();
             let framebuffer = self.framebuffer.clone();
             self.render_nes_framebuffer(framebuffer.read_front_buffer());
+            self.gif_recorder.capture(framebuffer.read_front_buffer());
+            if self.gif_recorder.is_recording() && self.gif_recorder.is_full() {
+                Self::save_gif(&mut self.gif_recorder);
+            }
 
-            self.canvas
-                .copy(&self.texture, None, self.cfg.dst_rect)
-                .unwrap();
+            //the letterbox area isn't drawn over, so clear it in case the window was resized
+            self.canvas.clear();
+            match (&self.crt_effect, self.cfg.dst_rect) {
+                (Some(crt), Some(dst_rect)) => crt.apply(&mut self.canvas, &self.texture, None, dst_rect),
+                _ => self.canvas.copy_f(&self.texture, None, self.cfg.dst_rect).unwrap(),
+            }
+            if self.pattern_viewer.is_some() {
+                self.canvas
+                    .copy(&self.pattern_texture, None, pattern_overlay_rect())
+                    .unwrap();
+            }
+            if self.sprite_viewer.is_some() {
+                let overlay = sprite_overlay_rect(self.canvas.output_size().unwrap().0);
+                self.canvas.copy(&self.sprite_texture, None, overlay).unwrap();
+            }
+            if self.palette_viewer.is_some() {
+                let overlay = palette_overlay_rect(self.canvas.output_size().unwrap().1);
+                self.canvas.copy(&self.palette_texture, None, overlay).unwrap();
+                if let Some(text) = &self.palette_hover {
+                    let y = overlay.y() - (CHAR_SIZE * TEXT_SCALE + TEXT_MARGIN) as i32;
+                    Font::draw_string_scaled(&mut self.canvas, text, TEXT_MARGIN as i32, y, Color::WHITE.into(), TEXT_SCALE);
+                }
+            }
+            //redrawn every frame since selecting and typing change it without the emulator
+            if let Some(viewer) = &self.memory_viewer {
+                let pixels = viewer.render();
+                copy_to_texture(&mut self.memory_texture, &pixels[..], MEMORY_VIEW_WIDTH, MEMORY_VIEW_HEIGHT);
+                let overlay = memory_overlay_rect(self.canvas.output_size().unwrap());
+                self.canvas.copy(&self.memory_texture, None, overlay).unwrap();
+            }
+            self.count_frame();
+            if self.show_fps {
+                self.draw_fps_overlay();
+            }
+            if self.paused {
+                self.draw_pause_indicator();
+            }
+            if self.osd_message.as_ref().is_some_and(|(_, shown)| shown.elapsed() >= OSD_MESSAGE_DURATION) {
+                self.osd_message = None;
+            }
+            self.draw_osd_message();
             self.canvas.present();
+            //nothing new comes from the emulator any faster than this
+            if let Some(left) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+                std::thread::sleep(left);
+            }
+        }
+    }
+    //fps_display is refreshed once a second from the frames drawn since the last refresh
+    fn count_frame(&mut self) {
+        self.frames_rendered += 1;
+        let elapsed = self.fps_timer.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let fps = self.frames_rendered as f64 / elapsed.as_secs_f64();
+            self.fps_display = format!("{fps:.0} FPS");
+            self.frames_rendered = 0;
+            self.fps_timer = Instant::now();
+        }
+    }
+    //right aligned in the top right corner, the title goes under the fps
+    fn draw_fps_overlay(&mut self) {
+        let (window_width, _) = self.canvas.output_size().unwrap();
+        let lines = [Some(self.fps_display.as_str()), self.game_title.as_deref()];
+        for (line, text) in lines.into_iter().flatten().enumerate() {
+            let x = window_width.saturating_sub(Font::string_width(text, TEXT_SCALE) + TEXT_MARGIN) as i32;
+            let y = (TEXT_MARGIN + line as u32 * CHAR_SIZE * TEXT_SCALE) as i32;
+            Font::draw_string_scaled(&mut self.canvas, text, x, y, Color::WHITE.into(), TEXT_SCALE);
+        }
+    }
+    //centered near the top of the NES screen
+    fn draw_pause_indicator(&mut self) {
+        let (window_width, _) = self.canvas.output_size().unwrap();
+        let text = "PAUSED";
+        let x = (window_width.saturating_sub(Font::string_width(text, TEXT_SCALE)) / 2) as i32;
+        let y = self.cfg.offset_y as i32 + TEXT_MARGIN as i32;
+        Font::draw_string_scaled(&mut self.canvas, text, x, y, Color::WHITE.into(), TEXT_SCALE);
+    }
+    //bottom left, for OSD_MESSAGE_DURATION after it was set
+    fn draw_osd_message(&mut self) {
+        let Some((text, _)) = &self.osd_message else {
+            return;
+        };
+        let (_, window_height) = self.canvas.output_size().unwrap();
+        let y = window_height.saturating_sub(CHAR_SIZE * TEXT_SCALE + TEXT_MARGIN) as i32;
+        Font::draw_string_scaled(&mut self.canvas, text, TEXT_MARGIN as i32, y, Color::WHITE.into(), TEXT_SCALE);
+    }
+}
+
+pub const WINDOW_TITLE: &str = "RNES";
+const OSD_MESSAGE_DURATION: Duration = Duration::from_secs(2);
+//the ui redraws at most 60 times a second
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+//overlay text is drawn at 2x, away from the window edge
+const TEXT_SCALE: u32 = 2;
+const TEXT_MARGIN: u32 = 8;
+
+//debug overlays are drawn at twice their size, pattern tables in the top left corner,
+//sprites in the top right, palettes in the bottom left and memory in the bottom right
+const OVERLAY_SCALE: usize = 2;
+
+fn pattern_overlay_rect() -> Rect {
+    Rect::new(
+        0,
+        0,
+        (PATTERN_VIEW_WIDTH * OVERLAY_SCALE) as u32,
+        (PATTERN_VIEW_HEIGHT * OVERLAY_SCALE) as u32,
+    )
+}
+
+fn sprite_overlay_rect(window_width: u32) -> Rect {
+    let width = (SPRITE_VIEW_WIDTH * OVERLAY_SCALE) as u32;
+    Rect::new(
+        window_width.saturating_sub(width) as i32,
+        0,
+        width,
+        (SPRITE_VIEW_HEIGHT * OVERLAY_SCALE) as u32,
+    )
+}
+
+fn palette_overlay_rect(window_height: u32) -> Rect {
+    let height = (PALETTE_VIEW_HEIGHT * OVERLAY_SCALE) as u32;
+    Rect::new(
+        0,
+        window_height.saturating_sub(height) as i32,
+        (PALETTE_VIEW_WIDTH * OVERLAY_SCALE) as u32,
+        height,
+    )
+}
+
+fn memory_overlay_rect((window_width, window_height): (u32, u32)) -> Rect {
+    let width = (MEMORY_VIEW_WIDTH * OVERLAY_SCALE) as u32;
+    let height = (MEMORY_VIEW_HEIGHT * OVERLAY_SCALE) as u32;
+    Rect::new(
+        window_width.saturating_sub(width) as i32,
+        window_height.saturating_sub(height) as i32,
+        width,
+        height,
+    )
+}
+
+//what load_cartridge knows how to open, judged by the extension since the file isn't read yet
+pub fn is_loadable_rom(file_path: &str) -> bool {
+    Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("nes") || ext.eq_ignore_ascii_case("nsf"))
+}
+
+fn save_slot(keycode: Keycode) -> Option<u8> {
+    let slot = keycode.into_i32() - Keycode::F1.into_i32() + 1;
+    //F1-F12 have consecutive keycodes
+    (1..=SAVE_SLOTS as i32).contains(&slot).then_some(slot as u8)
+}
+
+fn recent_file_slot(keycode: Keycode) -> Option<usize> {
+    match keycode {
+        Keycode::Num0 => Some(9),
+        Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5 | Keycode::Num6
+        | Keycode::Num7 | Keycode::Num8 | Keycode::Num9 => {
+            Some((keycode.into_i32() - Keycode::Num1.into_i32()) as usize)
         }
+        _ => None,
+    }
+}
+
+fn hex_digit(keycode: Keycode) -> Option<u8> {
+    let name = keycode.name();
+    if name.len() != 1 {
+        return None;
     }
+    u8::from_str_radix(&name, 16).ok()
+}
+
+fn copy_to_texture(texture: &mut Texture, pixels: &[Color], width: usize, height: usize) {
+    texture
+        .with_lock(None, |buffer, pitch| {
+            for y in 0..height {
+                let offset_tex = y * pitch;
+                let offset_src = y * width;
+                for x in 0..width {
+                    let color = pixels[offset_src + x];
+                    let pixel_offset = offset_tex + x * 4;
+
+                    buffer[pixel_offset..pixel_offset + 4]
+                        .copy_from_slice(&[color.r, color.g, color.b, color.a]);
+                }
+            }
+        })
+        .unwrap();
 }
@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+"""Builds the rom database RNES reads titles from, out of a NesCartDB XML export.
+
+    tools/build_rom_db.py nescartdb.xml [~/.config/rnes/roms.json.gz]
+
+NesCartDB's cartridge sha1 covers PRG+CHR without the iNES header, which
+is what Cartridge::prg_chr_sha1 hashes. Entries already in the output file
+are kept unless the export has the same sha1.
+
+NesCartDB's data isn't redistributed with RNES, run this against your own
+export. The output goes next to config.toml by default, where RNES looks for it.
+test_roms/roms.json.gz is a fixture for the tests with only the test roms.
+"""
+import gzip
+import json
+import os
+import sys
+import xml.etree.ElementTree as ET
+
+PAL_SYSTEMS = ("NES-PAL", "NES-PAL-A", "NES-PAL-B")
+
+
+def load(path):
+    if not os.path.exists(path):
+        return {}
+    with gzip.open(path, "rt", encoding="utf-8") as f:
+        return json.load(f)
+
+
+def entries(xml_path):
+    for game in ET.parse(xml_path).getroot().iter("game"):
+        title = game.get("name")
+        for cart in game.iter("cartridge"):
+            board = cart.find("board")
+            sha1 = cart.get("sha1")
+            if board is None or not sha1 or board.get("mapper") is None:
+                continue
+            yield sha1.lower(), {
+                "title": title,
+                "region": "Pal" if cart.get("system") in PAL_SYSTEMS else "Ntsc",
+                "mapper": int(board.get("mapper")),
+                "has_battery": any(ram.get("battery") == "1" for ram in board.iter("wram")),
+            }
+
+
+#same directory as Config::default_path
+def default_path():
+    config_dir = os.environ.get("XDG_CONFIG_HOME") or os.path.join(os.path.expanduser("~"), ".config")
+    return os.path.join(config_dir, "rnes", "roms.json.gz")
+
+
+def main():
+    if len(sys.argv) not in (2, 3):
+        sys.exit(__doc__)
+    out = sys.argv[2] if len(sys.argv) == 3 else default_path()
+    os.makedirs(os.path.dirname(os.path.abspath(out)), exist_ok=True)
+    db = load(out)
+    db.update(entries(sys.argv[1]))
+    #sorted and with a fixed mtime so rebuilding the same data gives the same file
+    data = json.dumps(db, sort_keys=True, indent=1).encode()
+    with open(out, "wb") as f:
+        f.write(gzip.compress(data, mtime=0))
+    print(f"{len(db)} roms in {out}")
+
+
+if __name__ == "__main__":
+    main()